@@ -0,0 +1,57 @@
+//! run with `cargo bench`.
+//! the workloads are the programs in `examples.ap`, sized so one run takes a few milliseconds
+
+use aplang_lib::interpreter::{Engine, Settings};
+use aplang_lib::ApLang;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+// mostly procedure calls, so this measures entering and leaving scopes
+fn fib_source() -> String {
+    include_str!("../examples.ap/fib.ap").replace("DISPLAY(fib(30))", "fib(20)")
+}
+
+// mostly variable reads and writes inside loops
+fn bubblesort_source() -> String {
+    let numbers = (1..=150)
+        .rev()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    include_str!("../examples.ap/bubblesort.ap")
+        .replace("[5, 2, 9, 1, 5, 6]", &format!("[{numbers}]"))
+        .replace("DISPLAY(numbers)", "")
+}
+
+fn run(source: &str, engine: Engine) {
+    ApLang::new_from_stdin(source)
+        .with_settings(Settings {
+            engine,
+            ..Settings::default()
+        })
+        .lex()
+        .unwrap()
+        .parse()
+        .unwrap()
+        .execute()
+        .unwrap();
+}
+
+fn interpreter(c: &mut Criterion) {
+    let fib = fib_source();
+    let bubblesort = bubblesort_source();
+
+    for (name, engine) in [("tree", Engine::Tree), ("bytecode", Engine::Bytecode)] {
+        c.bench_function(&format!("fib/{name}"), |b| {
+            b.iter(|| run(black_box(&fib), engine))
+        });
+
+        c.bench_function(&format!("bubblesort/{name}"), |b| {
+            b.iter(|| run(black_box(&bubblesort), engine))
+        });
+    }
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
@@ -1,12 +1,13 @@
 use crate::interpreter::errors::RuntimeError;
 use crate::interpreter::FunctionMap;
 use crate::interpreter::Interpreter;
+use crate::interpreter::Settings;
 use crate::interpreter::Value;
 use crate::lexer::token::Token;
 use crate::lexer::Lexer;
 use crate::parser::ast::pretty::TreePrinter;
-use crate::parser::ast::Ast;
-use crate::parser::Parser;
+use crate::parser::ast::{Ast, ProcDeclaration, Stmt};
+use crate::parser::{Linter, Lints, Parser, Resolver};
 use miette::Report;
 use std::fmt::Write;
 use std::marker::PhantomData;
@@ -28,6 +29,8 @@ pub struct ApLang<State = Initialized> {
     ast: Option<Ast>,           // generated with the parser
     values: Option<Vec<Value>>,
 
+    settings: Settings,
+
     _state: PhantomData<State>,
 }
 
@@ -43,6 +46,7 @@ impl ApLang {
             tokens: None,
             ast: None,
             values: None,
+            settings: Settings::default(),
 
             _state: PhantomData,
         })
@@ -55,6 +59,7 @@ impl ApLang {
             tokens: None,
             ast: None,
             values: None,
+            settings: Settings::default(),
 
             _state: PhantomData,
         }
@@ -69,6 +74,7 @@ impl ApLang {
             tokens: None,
             ast: None,
             values: None,
+            settings: Settings::default(),
 
             _state: PhantomData,
         }
@@ -79,6 +85,14 @@ impl ApLang {
     }
 }
 
+impl<State> ApLang<State> {
+    /// change how the program will run once it is executed
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
 impl ApLang<Initialized> {
     /// executes the lexer to convert source code into tokens
     pub fn lex(self) -> Result<ApLang<Lexed>, Vec<Report>> {
@@ -99,6 +113,7 @@ impl ApLang<Initialized> {
             tokens: Some(tokens), // tokens now exist
             ast: None,
             values: None,
+            settings: self.settings,
 
             _state: PhantomData,
         })
@@ -122,12 +137,21 @@ impl ApLang<Lexed> {
 
         let ast = parser.parse()?;
 
+        // find mistakes before anything runs
+        let resolver = Resolver::new(
+            Arc::clone(&self.source_code),
+            &file_name,
+            self.file_path.clone(),
+        );
+        resolver.resolve(&ast)?;
+
         Ok(ApLang {
             source_code: self.source_code,
             file_path: self.file_path,
             tokens: None,
             ast: Some(ast),
             values: None,
+            settings: self.settings,
 
             _state: PhantomData,
         })
@@ -147,20 +171,26 @@ impl ApLang<Lexed> {
 }
 
 impl ApLang<Parsed> {
+    /// creates an interpreter for the program with these settings
+    pub fn interpreter(self) -> Interpreter {
+        Interpreter::new(unsafe { self.ast.unwrap_unchecked() }, self.file_path)
+            .with_settings(self.settings)
+    }
+
     pub fn execute_as_module(self) -> Result<FunctionMap, RuntimeError> {
-        Interpreter::new(unsafe { self.ast.unwrap_unchecked() }, self.file_path).interpret_module()
+        self.interpreter().interpret_module()
     }
 
     pub fn execute(self) -> Result<ApLang<Executed>, Report> {
-        Interpreter::new(
+        let mut interpreter = Interpreter::new(
             unsafe { self.ast.unwrap_unchecked() },
             self.file_path.clone(),
         )
-        .interpret()
-        .map_err(|err| {
-            let named_source = err.named_source.clone();
-            Report::from(err).with_source_code(named_source)
-        })?;
+        .with_settings(self.settings.clone());
+
+        interpreter
+            .interpret()
+            .map_err(|err| interpreter.report(err))?;
 
         Ok(ApLang {
             source_code: self.source_code,
@@ -168,6 +198,7 @@ impl ApLang<Parsed> {
             tokens: None,
             ast: None,
             values: None,
+            settings: self.settings,
 
             _state: PhantomData,
         })
@@ -175,11 +206,11 @@ impl ApLang<Parsed> {
 
     pub fn execute_with_debug(self) -> Result<ApLang<ExecutedWithDebug>, Report> {
         let ast = unsafe { self.ast.unwrap_unchecked() };
-        let mut interpreter = Interpreter::new(ast, self.file_path.clone());
-        let values = interpreter.interpret_debug().map_err(|err| {
-            let named_source = err.named_source.clone();
-            Report::from(err).with_source_code(named_source)
-        })?;
+        let mut interpreter =
+            Interpreter::new(ast, self.file_path.clone()).with_settings(self.settings.clone());
+        let values = interpreter
+            .interpret_debug()
+            .map_err(|err| interpreter.report(err))?;
 
         Ok(ApLang {
             source_code: self.source_code,
@@ -187,11 +218,41 @@ impl ApLang<Parsed> {
             tokens: None,
             ast: None,
             values: Some(values),
+            settings: self.settings,
 
             _state: PhantomData,
         })
     }
 
+    /// the PROCEDUREs at the top level whose names start with `TEST_`, for `aplang test`
+    pub fn tests(&self) -> Vec<Arc<ProcDeclaration>> {
+        let ast = unsafe { self.ast.as_ref().unwrap_unchecked() };
+        ast.program
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::ProcDeclaration(procedure) if procedure.name.starts_with("TEST_") => {
+                    Some(procedure.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// finds code that is probably a mistake, without running anything
+    pub fn lint(&self, lints: Lints) -> Vec<Report> {
+        let ast = unsafe { self.ast.as_ref().unwrap_unchecked() };
+
+        let file_name = self
+            .file_path
+            .clone()
+            .unwrap()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Linter::new(Arc::clone(&self.source_code), &file_name, lints).lint(ast)
+    }
+
     pub fn debug_output<Writer: Write>(&self, buf: &mut Writer) -> fmt::Result {
         let ast = unsafe { self.ast.as_ref().unwrap_unchecked() };
 
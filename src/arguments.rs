@@ -1,6 +1,10 @@
-use clap::{Parser, ValueEnum};
+use crate::interpreter::DEFAULT_MAX_CALL_DEPTH;
+use crate::parser::{KeywordCase, Level, Lints, Rule};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(ValueEnum, Debug, Clone, Copy)]
 pub enum DebugMode {
@@ -21,13 +25,28 @@ pub enum DebugMode {
     Interpreter,
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum EngineMode {
+    /// Walk the syntax tree directly
+    Tree,
+
+    /// Compile to bytecode and run it on a virtual machine.
+    /// Much faster for recursive code
+    Bytecode,
+}
+
 #[derive(Parser, Debug)]
 #[clap(
     author,
     version,
-    about = "A language designed for AP Computer Science Principals students"
+    about = "A language designed for AP Computer Science Principals students",
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
 )]
 pub struct CommandLine {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// The source file that will be run, pass in a file path
     #[arg(value_name="FILE_PATH", required_unless_present_any=&["eval", "eval_stdin", "info"], conflicts_with_all=&["eval", "eval_stdin"])]
     pub file: Option<PathBuf>,
@@ -36,7 +55,7 @@ pub struct CommandLine {
     #[arg(short='e', long, value_name="CODE", conflicts_with_all=&["file", "eval_stdin"])]
     pub eval: Option<Arc<str>>,
 
-    /// Execute code from standard input (not a repl)
+    /// Execute code from standard input (not a repl, see `aplang repl`)
     #[arg(long, conflicts_with_all=&["file", "eval"])]
     pub eval_stdin: bool,
 
@@ -50,7 +69,23 @@ pub struct CommandLine {
             "check",
             "debug",
             "enforce_file_extension",
-            "stack_size"
+            "stack_size",
+            "engine",
+            "max_call_depth",
+            "max_steps",
+            "time_limit",
+            "max_list_len",
+            "seed",
+            "virtual_clock",
+            "trace",
+            "trace_file",
+            "trace_json",
+            "trace_html",
+            "profile",
+            "profile_folded",
+            "coverage",
+            "allow",
+            "deny"
         ]
     )]
     pub info: bool,
@@ -70,10 +105,105 @@ pub struct CommandLine {
     )]
     pub debug: DebugMode,
 
+    /// Choose how the code is executed
+    #[arg(
+        long,
+        value_name = "ENGINE",
+        value_enum,
+        default_value = "tree",
+        help_heading = "Advanced Options"
+    )]
+    pub engine: EngineMode,
+
+    /// How many PROCEDURE calls can be nested before the program is stopped.
+    /// Set to 0 for no limit
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        default_value_t = DEFAULT_MAX_CALL_DEPTH,
+        help_heading = "Advanced Options"
+    )]
+    pub max_call_depth: usize,
+
+    /// Stop the program after it runs this many statements
+    #[arg(long, value_name = "STEPS", help_heading = "Limits")]
+    pub max_steps: Option<u64>,
+
+    /// Stop the program after it runs for this many seconds
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, help_heading = "Limits")]
+    pub time_limit: Option<Duration>,
+
+    /// Stop the program if a LIST gets longer than this
+    #[arg(long, value_name = "LENGTH", help_heading = "Limits")]
+    pub max_list_len: Option<usize>,
+
+    /// Make RANDOM give the same numbers every run
+    #[arg(long, value_name = "SEED", help_heading = "Reproducibility")]
+    pub seed: Option<u64>,
+
+    /// Start TIME at 0 and have SLEEP move it forward without waiting
+    #[arg(long, help_heading = "Reproducibility")]
+    pub virtual_clock: bool,
+
+    /// Show each statement as it runs, with every assignment, call and loop,
+    /// like tracing the code by hand. The trace goes to stderr
+    #[arg(long, conflicts_with_all = &["engine", "check", "debug"], help_heading = "Tracing")]
+    pub trace: bool,
+
+    /// Write the trace to this file instead of stderr
+    #[arg(long, value_name = "FILE", requires = "trace", help_heading = "Tracing")]
+    pub trace_file: Option<PathBuf>,
+
+    /// Record every variable and list before each statement and write it as JSON,
+    /// for tools that draw memory step by step
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &["engine", "check", "debug", "trace"],
+        help_heading = "Tracing"
+    )]
+    pub trace_json: Option<PathBuf>,
+
+    /// Write a web page that steps through the program and draws its variables and lists.
+    /// It works offline, so it can be shared as one file
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &["engine", "check", "debug", "trace"],
+        help_heading = "Tracing"
+    )]
+    pub trace_html: Option<PathBuf>,
+
+    /// Time every PROCEDURE and standard library call, then show a table of the calls
+    /// and where the time went on stderr
+    #[arg(
+        long,
+        conflicts_with_all = &["engine", "check", "debug", "trace", "trace_json", "trace_html"],
+        help_heading = "Profiling"
+    )]
+    pub profile: bool,
+
+    /// Also write the time of each chain of calls as folded stacks, for flamegraph tools
+    #[arg(long, value_name = "FILE", requires = "profile", help_heading = "Profiling")]
+    pub profile_folded: Option<PathBuf>,
+
+    /// Write which lines ran and which way each IF went to this file, in the lcov format
+    /// editors can show. Imported modules are included under their own path
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &["engine", "check", "debug", "trace", "trace_json", "trace_html", "profile"],
+        help_heading = "Coverage"
+    )]
+    pub coverage: Option<PathBuf>,
+
     /// Run the checker without executing the code
     #[arg(short = 'c', long, conflicts_with = "debug")]
     pub check: bool,
 
+    #[command(flatten)]
+    pub lints: LintOptions,
+
     // windows stack size is 1MB which is way too small for some nested recursive code
     // on linux you should be okay most of the time without `stacker`
     // but on windows you 100% do
@@ -91,3 +221,174 @@ pub struct CommandLine {
     )]
     pub stack_size: usize,
 }
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Report code that works but is probably a mistake, without running it
+    Lint(LintCommand),
+
+    /// Rewrite files in the standard style
+    Fmt(FmtCommand),
+
+    /// Start a language server that editors talk to over stdin and stdout
+    #[cfg(feature = "lsp")]
+    Lsp,
+
+    /// Start a debug adapter that editors talk to over stdin and stdout
+    #[cfg(feature = "dap")]
+    Dap,
+
+    /// Run code as it is typed, keeping variables and PROCEDUREs between inputs
+    #[cfg(feature = "repl")]
+    Repl,
+
+    /// Run every PROCEDURE whose name starts with TEST_, and report which ones failed
+    #[cfg(feature = "tester")]
+    Test(TestCommand),
+
+    /// Run a program once for each case in a case file, and check its output
+    #[cfg(feature = "grader")]
+    Grade(GradeCommand),
+
+    /// Grade every program in a folder against the same case file, several at a time
+    #[cfg(feature = "grader")]
+    GradeDir(GradeDirCommand),
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum CaseMode {
+    /// Write keywords like `IF` and `PROCEDURE` in uppercase
+    Upper,
+    /// Write keywords like `if` and `procedure` in lowercase
+    Lower,
+}
+
+impl From<CaseMode> for KeywordCase {
+    fn from(case: CaseMode) -> Self {
+        match case {
+            CaseMode::Upper => KeywordCase::Upper,
+            CaseMode::Lower => KeywordCase::Lower,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct FmtCommand {
+    /// The files to format. Directories are searched for .ap files
+    #[arg(value_name = "PATH", required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Do not change any files, fail if any of them are not formatted
+    #[arg(long)]
+    pub check: bool,
+
+    /// How keywords are written
+    #[arg(long, value_name = "CASE", value_enum, default_value = "upper")]
+    pub keyword_case: CaseMode,
+}
+
+#[derive(Args, Debug)]
+pub struct TestCommand {
+    /// The files to test. Directories are searched for .ap files
+    #[arg(value_name = "PATH", default_value = ".")]
+    pub paths: Vec<PathBuf>,
+
+    /// How the results are shown
+    #[arg(long, value_name = "FORMAT", value_enum, default_value = "human")]
+    pub format: ReportFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct GradeCommand {
+    /// The program being graded
+    #[arg(value_name = "SUBMISSION")]
+    pub submission: PathBuf,
+
+    /// A .json or .toml file listing the input and expected output of each case
+    #[arg(value_name = "CASES")]
+    pub cases: PathBuf,
+
+    /// How the results are shown
+    #[arg(long, value_name = "FORMAT", value_enum, default_value = "human")]
+    pub format: ReportFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct GradeDirCommand {
+    /// The folder of programs being graded. Every .ap file in it is one submission
+    #[arg(value_name = "DIR")]
+    pub dir: PathBuf,
+
+    /// A .json or .toml file listing the input and expected output of each case
+    #[arg(value_name = "CASES")]
+    pub cases: PathBuf,
+
+    /// How many programs are graded at the same time. Defaults to the number of cores
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// How the results are shown
+    #[arg(long, value_name = "FORMAT", value_enum, default_value = "human")]
+    pub format: ReportFormat,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// A line for each result, then the details of the ones that failed
+    Human,
+    /// One JSON object with every result, for graders and other tools
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct LintCommand {
+    /// The source file that will be linted, pass in a file path
+    #[arg(value_name = "FILE_PATH", required_unless_present = "eval", conflicts_with = "eval")]
+    pub file: Option<PathBuf>,
+
+    /// Lint code from a string
+    #[arg(short = 'e', long, value_name = "CODE")]
+    pub eval: Option<Arc<str>>,
+
+    #[command(flatten)]
+    pub lints: LintOptions,
+}
+
+/// choose how each lint rule is reported by `lint` and `--check`
+#[derive(Args, Debug)]
+pub struct LintOptions {
+    /// Turn off a lint rule. Can be used more than once
+    #[arg(long, value_name = "RULE", value_parser = rule_parser(), help_heading = "Lints")]
+    pub allow: Vec<Rule>,
+
+    /// Report a lint rule as an error. Can be used more than once
+    #[arg(long, value_name = "RULE", value_parser = rule_parser(), help_heading = "Lints")]
+    pub deny: Vec<Rule>,
+}
+
+impl LintOptions {
+    pub fn lints(&self) -> Lints {
+        let mut lints = Lints::default();
+        for &rule in &self.allow {
+            lints.set(rule, Level::Allow);
+        }
+        for &rule in &self.deny {
+            lints.set(rule, Level::Deny);
+        }
+        lints
+    }
+}
+
+fn rule_parser() -> impl TypedValueParser<Value = Rule> {
+    PossibleValuesParser::new(Rule::ALL.map(Rule::name))
+        .map(|name| name.parse::<Rule>().expect("only rule names are possible"))
+}
+
+/// a number of seconds that is more than 0
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    let seconds: f64 = seconds.parse().map_err(|err| format!("{err}"))?;
+    Some(seconds)
+        .filter(|seconds| *seconds > 0.0)
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| "it has to be a number of seconds more than 0".to_string())
+}
@@ -0,0 +1,216 @@
+//! `aplang dap`, a debug adapter that editors talk to over stdin and stdout
+
+use crate::aplang::{ApLang, Parsed};
+use crate::input::Lines;
+use crate::interpreter::debug::Debugger;
+use crate::interpreter::Interpreter;
+use crate::output::Output;
+use crate::parser::ast::Stmt;
+use miette::{miette, GraphicalReportHandler, GraphicalTheme, Report, Result};
+use serde_json::{json, Value as Json};
+use session::Session;
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+mod session;
+
+/// aplang runs one program on one thread, so there is only ever this one
+const THREAD: i64 = 1;
+
+/// runs the debug adapter until the editor disconnects
+pub fn serve() -> Result<()> {
+    let client = Client::default();
+    let session = Rc::new(RefCell::new(Session::new(client.clone(), listen())));
+
+    // the editor sends the program and its breakpoints before anything runs
+    let Some(launch) = session.borrow_mut().configure() else {
+        return Ok(());
+    };
+
+    let exit_code = match load(&launch) {
+        Ok(parsed) => {
+            let mut interpreter = parsed
+                .interpreter()
+                .with_output(Console(client.clone()))
+                .with_input(Lines::new(launch.input))
+                .with_debugger(Hook(session.clone()));
+
+            match interpreter.interpret() {
+                Ok(()) => 0,
+                // the editor asked to stop, that is not an error in the program
+                Err(_) if session.borrow().stopped() => 0,
+                Err(err) => {
+                    client.report(interpreter.report(err));
+                    1
+                }
+            }
+        }
+        Err(reports) => {
+            reports.into_iter().for_each(|report| client.report(report));
+            1
+        }
+    };
+
+    client.event("exited", json!({ "exitCode": exit_code }));
+    client.event("terminated", json!({}));
+
+    session.borrow_mut().finish();
+    Ok(())
+}
+
+/// the settings the editor launched the program with
+#[derive(Debug, Default)]
+pub struct Launch {
+    pub program: String,
+    /// stop before the first statement instead of at the first breakpoint
+    pub stop_on_entry: bool,
+    /// the lines INPUT reads, since stdin belongs to the editor
+    pub input: Vec<String>,
+}
+
+fn load(launch: &Launch) -> Result<ApLang<Parsed>, Vec<Report>> {
+    ApLang::new_from_file(launch.program.clone().into())
+        .map_err(|err| vec![miette!("could not read {}\n{}", launch.program, err)])?
+        .lex()?
+        .parse()
+}
+
+/// a request from the editor
+#[derive(Debug)]
+pub struct Request {
+    seq: i64,
+    command: String,
+    arguments: Json,
+}
+
+/// reads requests from stdin on another thread, so they can be
+/// checked for between statements while the program runs
+fn listen() -> Receiver<Request> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        while let Some(message) = read_message(&mut stdin) {
+            let request = Request {
+                seq: message["seq"].as_i64().unwrap_or_default(),
+                command: message["command"].as_str().unwrap_or_default().to_string(),
+                arguments: message["arguments"].clone(),
+            };
+            if sender.send(request).is_err() {
+                break;
+            }
+        }
+        // dropping the sender tells the session the editor is gone
+    });
+
+    receiver
+}
+
+/// reads one message with a `Content-Length` header. None once stdin is closed
+fn read_message(reader: &mut impl BufRead) -> Option<Json> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length?];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+/// sends responses and events to the editor over stdout.
+/// clones share the same sequence numbers
+#[derive(Clone, Default)]
+pub struct Client {
+    seq: Rc<Cell<i64>>,
+}
+
+impl Client {
+    fn send(&self, mut message: Json) {
+        self.seq.set(self.seq.get() + 1);
+        message["seq"] = json!(self.seq.get());
+
+        let body = message.to_string();
+        let mut stdout = io::stdout().lock();
+        // if the editor is gone there is nobody to tell
+        let _ = write!(stdout, "Content-Length: {}\r\n\r\n{body}", body.len());
+        let _ = stdout.flush();
+    }
+
+    pub fn respond(&self, request: &Request, body: Json) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": true,
+            "command": request.command,
+            "body": body,
+        }));
+    }
+
+    pub fn fail(&self, request: &Request, message: String) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": false,
+            "command": request.command,
+            "message": message,
+        }));
+    }
+
+    pub fn event(&self, event: &str, body: Json) {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    /// shows text in the debug console
+    fn output(&self, category: &str, text: &str) {
+        self.event("output", json!({ "category": category, "output": text }));
+    }
+
+    /// shows an error in the debug console, without colors since it is not a terminal
+    fn report(&self, report: Report) {
+        let mut text = String::new();
+        let handler = GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor());
+        if handler.render_report(&mut text, report.as_ref()).is_err() {
+            text = format!("{report}\n");
+        }
+        self.output("stderr", &text);
+    }
+}
+
+/// sends everything the program displays to the debug console
+struct Console(Client);
+
+impl Output for Console {
+    fn write(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.0.output("stdout", text);
+        }
+    }
+}
+
+/// lets the session suspend the interpreter between statements
+struct Hook(Rc<RefCell<Session>>);
+
+impl Debugger for Hook {
+    fn reached(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> bool {
+        self.0.borrow_mut().reached(stmt, interpreter)
+    }
+}
@@ -0,0 +1,451 @@
+use super::{Client, Launch, Request, THREAD};
+use crate::interpreter::{Interpreter, Value};
+use crate::lexer::token::Token;
+use crate::parser::ast::Stmt;
+use crate::parser::walk::first_token;
+use serde_json::{json, Value as Json};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+/// when the program should be suspended next
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Step {
+    /// only at a breakpoint
+    Continue,
+    /// at the next statement, because the editor asked
+    Pause,
+    /// at the first statement, because the launch asked
+    Entry,
+    /// at the next line that is not inside of a call made from this depth
+    Over(usize),
+    /// at the next line, even inside of a call
+    In,
+    /// once the call at this depth has returned
+    Out(usize),
+}
+
+/// something the variables view can open
+enum Reference {
+    /// the variables made inside of a frame
+    Locals(usize),
+    /// the variables a frame can see from where its PROCEDURE was written
+    Enclosing(usize),
+    /// the items of a list
+    List(Rc<RefCell<Vec<Value>>>),
+}
+
+/// what to do after a request is answered
+enum Flow {
+    /// keep reading requests
+    Wait,
+    /// the editor is done setting up, run the program
+    Start,
+    /// let the program run until the next stop
+    Resume(Step),
+    /// stop the program for good
+    Stop,
+}
+
+/// the state of one debugging session, from `initialize` to `disconnect`
+pub struct Session {
+    client: Client,
+    requests: Receiver<Request>,
+    /// requests that only make sense once the program is suspended,
+    /// sent before it got there
+    pending: VecDeque<Request>,
+
+    launch: Launch,
+    /// the lines with a breakpoint in each file
+    breakpoints: HashMap<PathBuf, HashSet<usize>>,
+    /// the files the interpreter names, as the paths the editor uses
+    paths: HashMap<String, PathBuf>,
+
+    step: Step,
+    /// the file, line and depth of the statement before this one.
+    /// a line can have many statements, but it should only be stopped at once
+    last: Option<(String, usize, usize)>,
+    /// what the variables view can open while the program is suspended.
+    /// each `variablesReference` is an index into this plus one
+    references: Vec<Reference>,
+
+    /// the editor asked to stop the program, or went away
+    stopped: bool,
+}
+
+impl Session {
+    pub fn new(client: Client, requests: Receiver<Request>) -> Self {
+        Self {
+            client,
+            requests,
+            pending: VecDeque::new(),
+            launch: Launch::default(),
+            breakpoints: HashMap::new(),
+            paths: HashMap::new(),
+            step: Step::Continue,
+            last: None,
+            references: vec![],
+            stopped: false,
+        }
+    }
+
+    /// answers requests until the editor says the program can start.
+    /// None if it disconnected first
+    pub fn configure(&mut self) -> Option<Launch> {
+        while let Some(request) = self.next() {
+            match self.handle(request, None) {
+                Flow::Start => {
+                    if self.launch.stop_on_entry {
+                        self.step = Step::Entry;
+                    }
+                    return Some(std::mem::take(&mut self.launch));
+                }
+                Flow::Stop => return None,
+                Flow::Wait | Flow::Resume(_) => {}
+            }
+        }
+        None
+    }
+
+    /// answers requests after the program finished, until the editor disconnects
+    pub fn finish(&mut self) {
+        if self.stopped {
+            return;
+        }
+        while let Some(request) = self.next() {
+            if let Flow::Stop = self.handle(request, None) {
+                return;
+            }
+        }
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// decides if the program stops before `stmt`.
+    /// returns false if the program should not continue at all
+    pub fn reached(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> bool {
+        // the editor can pause or disconnect while the program runs
+        loop {
+            match self.requests.try_recv() {
+                // keep them in order behind anything that is already waiting
+                Ok(request) if !self.pending.is_empty() || needs_suspended(&request) => {
+                    self.pending.push_back(request)
+                }
+                Ok(request) => match self.handle(request, None) {
+                    Flow::Stop => return false,
+                    Flow::Wait | Flow::Start | Flow::Resume(_) => {}
+                },
+                Err(TryRecvError::Empty) => break,
+                // what it sent before it left still gets answered
+                Err(TryRecvError::Disconnected) if !self.pending.is_empty() => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.stopped = true;
+                    return false;
+                }
+            }
+        }
+
+        let token = first_token(stmt);
+        let file = interpreter.get_file_path();
+        let line = token.line_number;
+        let depth = interpreter.depth();
+
+        let breakpoint = self.has_breakpoint(&file, line);
+
+        let here = Some((file, line, depth));
+        let moved = self.last != here;
+        self.last = here;
+
+        let reason = match self.step {
+            Step::Entry => Some("entry"),
+            Step::Pause => Some("pause"),
+            Step::In if moved => Some("step"),
+            Step::Over(from) if moved && depth <= from => Some("step"),
+            Step::Out(from) if depth < from => Some("step"),
+            _ => None,
+        };
+        let reason = reason.or((moved && breakpoint).then_some("breakpoint"));
+
+        match reason {
+            Some(reason) => self.suspend(reason, interpreter, token),
+            None => true,
+        }
+    }
+
+    /// waits for the editor to resume the program, answering what it asks in the meantime
+    fn suspend(&mut self, reason: &str, interpreter: &Interpreter, token: &Token) -> bool {
+        self.references.clear();
+        self.client.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true }),
+        );
+
+        while let Some(request) = self.next() {
+            match self.handle(request, Some((interpreter, token))) {
+                Flow::Resume(step) => {
+                    self.step = step;
+                    return true;
+                }
+                Flow::Stop => return false,
+                Flow::Wait | Flow::Start => {}
+            }
+        }
+
+        self.stopped = true;
+        false
+    }
+
+    /// the next request to answer, waiting for one if needed. None once the editor is gone
+    fn next(&mut self) -> Option<Request> {
+        self.pending
+            .pop_front()
+            .or_else(|| self.requests.recv().ok())
+    }
+
+    fn has_breakpoint(&mut self, file: &str, line: usize) -> bool {
+        if self.breakpoints.is_empty() {
+            return false;
+        }
+        let path = self
+            .paths
+            .entry(file.to_string())
+            .or_insert_with(|| canonical(Path::new(file)));
+        self.breakpoints
+            .get(path)
+            .is_some_and(|lines| lines.contains(&line))
+    }
+
+    /// answers one request. `suspended` is the program and where it is, if it is suspended
+    fn handle(&mut self, request: Request, suspended: Option<(&Interpreter, &Token)>) -> Flow {
+        let arguments = &request.arguments;
+
+        match request.command.as_str() {
+            "initialize" => {
+                self.client.respond(
+                    &request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsTerminateRequest": true,
+                    }),
+                );
+                self.client.event("initialized", json!({}));
+            }
+            "launch" => {
+                let Some(program) = arguments["program"].as_str() else {
+                    self.client
+                        .fail(&request, "launch needs the `program` to debug".to_string());
+                    return Flow::Wait;
+                };
+                self.launch = Launch {
+                    program: program.to_string(),
+                    stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+                    input: arguments["input"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|line| line.as_str().map(String::from))
+                        .collect(),
+                };
+                self.client.respond(&request, json!({}));
+            }
+            "setBreakpoints" => {
+                let path = canonical(Path::new(
+                    arguments["source"]["path"].as_str().unwrap_or_default(),
+                ));
+                let lines: Vec<usize> = arguments["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                    .map(|line| line as usize)
+                    .collect();
+
+                let breakpoints: Vec<Json> = lines
+                    .iter()
+                    .map(|line| json!({ "verified": true, "line": line }))
+                    .collect();
+                self.breakpoints.insert(path, lines.into_iter().collect());
+                self.client
+                    .respond(&request, json!({ "breakpoints": breakpoints }));
+            }
+            "setExceptionBreakpoints" => self.client.respond(&request, json!({})),
+            "configurationDone" => {
+                self.client.respond(&request, json!({}));
+                return Flow::Start;
+            }
+            "threads" => self.client.respond(
+                &request,
+                json!({ "threads": [{ "id": THREAD, "name": "main" }] }),
+            ),
+            "stackTrace" => {
+                let frames: Vec<Json> = suspended
+                    .map(|(interpreter, token)| interpreter.frames(token))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, frame)| {
+                        let name = frame.procedure.unwrap_or_else(|| "top level".to_string());
+                        let path = canonical(Path::new(&frame.file));
+                        json!({
+                            "id": id,
+                            "name": name,
+                            "source": {
+                                "name": path.file_name().map(|name| name.to_string_lossy()),
+                                "path": path,
+                            },
+                            "line": frame.token.line_number,
+                            "column": column(&frame.token),
+                        })
+                    })
+                    .collect();
+                let total = frames.len();
+                self.client.respond(
+                    &request,
+                    json!({ "stackFrames": frames, "totalFrames": total }),
+                );
+            }
+            "scopes" => {
+                let frame = arguments["frameId"].as_u64().unwrap_or_default() as usize;
+                let locals = self.reference(Reference::Locals(frame));
+                let enclosing = self.reference(Reference::Enclosing(frame));
+                self.client.respond(
+                    &request,
+                    json!({ "scopes": [
+                        { "name": "Locals", "variablesReference": locals, "expensive": false },
+                        { "name": "Enclosing", "variablesReference": enclosing, "expensive": false },
+                    ]}),
+                );
+            }
+            "variables" => {
+                let variables = match suspended {
+                    Some((interpreter, _)) => self.variables(
+                        interpreter,
+                        arguments["variablesReference"].as_u64().unwrap_or_default() as usize,
+                    ),
+                    None => vec![],
+                };
+                let variables: Vec<Json> = variables
+                    .into_iter()
+                    .map(|(name, value)| self.variable(name, value))
+                    .collect();
+                self.client
+                    .respond(&request, json!({ "variables": variables }));
+            }
+            "continue" => {
+                self.client
+                    .respond(&request, json!({ "allThreadsContinued": true }));
+                return Flow::Resume(Step::Continue);
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.client.respond(&request, json!({}));
+                let Some((interpreter, _)) = suspended else {
+                    return Flow::Wait;
+                };
+                let depth = interpreter.depth();
+                return Flow::Resume(match request.command.as_str() {
+                    "next" => Step::Over(depth),
+                    "stepIn" => Step::In,
+                    _ => Step::Out(depth),
+                });
+            }
+            "pause" => {
+                self.client.respond(&request, json!({}));
+                if suspended.is_none() {
+                    self.step = Step::Pause;
+                }
+            }
+            "disconnect" | "terminate" => {
+                self.client.respond(&request, json!({}));
+                self.stopped = true;
+                return Flow::Stop;
+            }
+            command => self.client.fail(
+                &request,
+                format!("aplang does not know how to answer {command}"),
+            ),
+        }
+
+        Flow::Wait
+    }
+
+    /// lets the variables view open something. returns its `variablesReference`
+    fn reference(&mut self, reference: Reference) -> usize {
+        self.references.push(reference);
+        self.references.len()
+    }
+
+    /// the children of a `variablesReference`
+    fn variables(&self, interpreter: &Interpreter, reference: usize) -> Vec<(String, Value)> {
+        match reference
+            .checked_sub(1)
+            .and_then(|index| self.references.get(index))
+        {
+            Some(Reference::Locals(frame)) => interpreter.locals(*frame),
+            Some(Reference::Enclosing(frame)) => interpreter.enclosing(*frame),
+            // lists start at 1
+            Some(Reference::List(list)) => list
+                .borrow()
+                .iter()
+                .enumerate()
+                .map(|(index, item)| ((index + 1).to_string(), item.clone()))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    fn variable(&mut self, name: String, value: Value) -> Json {
+        let (shown, kind) = match &value {
+            Value::Null => ("NULL".to_string(), "null"),
+            Value::Number(_) => (value.to_string(), "number"),
+            Value::Bool(_) => (value.to_string(), "boolean"),
+            Value::String(string) => (format!("{string:?}"), "string"),
+            Value::List(_) => (value.to_string(), "list"),
+            Value::NativeFunction(_) | Value::Function(_) => (value.to_string(), "procedure"),
+            Value::NativeObject(_) => (value.to_string(), "native"),
+        };
+
+        // lists can be opened to see each item
+        let (reference, items) = match value {
+            Value::List(list) => {
+                let items = list.borrow().len();
+                (self.reference(Reference::List(list)), items)
+            }
+            _ => (0, 0),
+        };
+
+        json!({
+            "name": name,
+            "value": shown,
+            "type": kind,
+            "variablesReference": reference,
+            "indexedVariables": items,
+        })
+    }
+}
+
+/// if the request is about a suspended program, like looking at its variables or stepping
+fn needs_suspended(request: &Request) -> bool {
+    matches!(
+        request.command.as_str(),
+        "stackTrace" | "scopes" | "variables" | "continue" | "next" | "stepIn" | "stepOut"
+    )
+}
+
+/// the path the editor would use for a file, so breakpoints match however the file was named
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// the column of the token, starting at 1 like lines
+fn column(token: &Token) -> usize {
+    let offset = token.span.offset();
+    let line_start = token.source[..offset]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+    token.source[line_start..offset].chars().count() + 1
+}
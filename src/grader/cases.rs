@@ -0,0 +1,300 @@
+//! the case file `aplang grade` checks a program against.
+//!
+//! a case file is TOML or JSON, and has a `cases` array with a table for each case.
+//! in TOML that is a `[[cases]]` table for each case:
+//!
+//! ```toml
+//! # these are used by every case that does not set its own
+//! match = "trimmed"
+//! max_steps = 100000
+//!
+//! [[cases]]
+//! name = "adds two numbers"   # optional, "case 1", "case 2" and so on otherwise
+//! input = ["2", "3"]          # the lines INPUT gets, or one string with a line each
+//! expected = """
+//! 5
+//! """
+//! ```
+//!
+//! every case has to have `expected`. `name` and `input` only belong to a case.
+//! `match` (`exact`, `trimmed` or `regex`), `max_steps`, `time_limit` (in seconds)
+//! and `seed` can be set for a single case or at the top for every case.
+//! any other key is a mistake, and the file is not used
+
+use crate::interpreter::Budget;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// how long a case can run when the case file does not say, so a loop that never
+/// ends cannot stop the grading
+pub const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(10);
+
+/// the seed RANDOM gets when the case file does not say, so a program that uses
+/// RANDOM gets the same grade every time it is graded
+pub const DEFAULT_SEED: u64 = 0;
+
+/// one run of the program
+#[derive(Debug)]
+pub struct Case {
+    pub name: String,
+    /// the lines INPUT gets, in order
+    pub input: Vec<String>,
+    /// the output, or a regex for it
+    pub expected: String,
+    pub matching: Matching,
+    pub budget: Budget,
+    pub seed: u64,
+}
+
+/// how the output is compared to what was expected
+#[derive(Debug)]
+pub enum Matching {
+    /// exactly the same
+    Exact,
+    /// the same, without the spaces at the ends of lines and blank lines at the start and end
+    Trimmed,
+    /// the regex matches somewhere in the output. use `^` and `$` to match all of it
+    Regex(Regex),
+}
+
+impl Matching {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Matching::Exact => "exact",
+            Matching::Trimmed => "trimmed",
+            Matching::Regex(_) => "regex",
+        }
+    }
+
+    pub fn matches(&self, output: &str, expected: &str) -> bool {
+        match self {
+            Matching::Exact => output == expected,
+            Matching::Trimmed => trim(output) == trim(expected),
+            Matching::Regex(regex) => regex.is_match(output),
+        }
+    }
+}
+
+/// what `Trimmed` compares
+pub fn trim(text: &str) -> String {
+    text.lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_string()
+}
+
+/// the case file as it is written
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    cases: Vec<Written>,
+    #[serde(rename = "match")]
+    matching: Option<Match>,
+    max_steps: Option<u64>,
+    time_limit: Option<f64>,
+    seed: Option<u64>,
+}
+
+/// a case as it is written. the settings it leaves out come from the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Written {
+    name: Option<String>,
+    #[serde(default)]
+    input: Input,
+    expected: String,
+    #[serde(rename = "match")]
+    matching: Option<Match>,
+    max_steps: Option<u64>,
+    time_limit: Option<f64>,
+    seed: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Match {
+    Exact,
+    Trimmed,
+    Regex,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a string or a list of lines")]
+enum Input {
+    Text(String),
+    Lines(Vec<Line>),
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input::Lines(vec![])
+    }
+}
+
+/// a number can be written without quotes
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a string or a number")]
+enum Line {
+    Text(String),
+    Number(serde_json::Number),
+}
+
+/// reads a `.json` or `.toml` case file
+pub fn load(path: &Path) -> Result<Vec<Case>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("could not read it\n{err}"))?;
+
+    let file: File = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str(&text).map_err(|err| err.to_string())?,
+        Some("toml") => toml::from_str(&text).map_err(|err| err.to_string())?,
+        _ => return Err("case files end in .json or .toml".to_string()),
+    };
+
+    file.cases
+        .iter()
+        .enumerate()
+        .map(|(index, case)| {
+            read_case(index, case, &file).map_err(|err| format!("case {}: {err}", index + 1))
+        })
+        .collect()
+}
+
+fn read_case(index: usize, case: &Written, file: &File) -> Result<Case, String> {
+    let name = case
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("case {}", index + 1));
+
+    let input = match &case.input {
+        Input::Text(input) => input.lines().map(str::to_string).collect(),
+        Input::Lines(lines) => lines
+            .iter()
+            .map(|line| match line {
+                Line::Text(line) => line.clone(),
+                Line::Number(number) => number.to_string(),
+            })
+            .collect(),
+    };
+
+    // the case's own setting, or the one for every case
+    let matching = match case.matching.or(file.matching) {
+        None | Some(Match::Exact) => Matching::Exact,
+        Some(Match::Trimmed) => Matching::Trimmed,
+        Some(Match::Regex) => Matching::Regex(
+            Regex::new(&case.expected)
+                .map_err(|err| format!("`expected` is not a regex\n{err}"))?,
+        ),
+    };
+
+    let time_limit = match case.time_limit.or(file.time_limit) {
+        None => DEFAULT_TIME_LIMIT,
+        Some(seconds) => Some(seconds)
+            .filter(|seconds| *seconds > 0.0)
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or("`time_limit` has to be a number of seconds")?,
+    };
+
+    Ok(Case {
+        name,
+        input,
+        expected: case.expected.clone(),
+        matching,
+        budget: Budget {
+            max_steps: case.max_steps.or(file.max_steps),
+            time_limit: Some(time_limit),
+            max_list_len: None,
+        },
+        seed: case.seed.or(file.seed).unwrap_or(DEFAULT_SEED),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<File, String> {
+        toml::from_str(text).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn shared_settings_fill_in_cases() {
+        let file = parse(
+            r#"
+            match = "trimmed"
+            seed = 7
+
+            [[cases]]
+            expected = "a"
+
+            [[cases]]
+            name = "own"
+            expected = "b"
+            match = "exact"
+            "#,
+        )
+        .unwrap();
+        let first = read_case(0, &file.cases[0], &file).unwrap();
+        let second = read_case(1, &file.cases[1], &file).unwrap();
+
+        assert_eq!(first.name, "case 1");
+        assert_eq!(first.matching.name(), "trimmed");
+        assert_eq!(first.seed, 7);
+        assert_eq!(second.name, "own");
+        assert_eq!(second.matching.name(), "exact");
+        assert_eq!(second.budget.time_limit, Some(DEFAULT_TIME_LIMIT));
+    }
+
+    #[test]
+    fn input_is_lines() {
+        let file = parse(
+            r#"
+[[cases]]
+input = ["a b", 3]
+expected = """
+3
+"""
+
+[[cases]]
+input = "first\nsecond"
+expected = '^\d+$'
+"#,
+        )
+        .unwrap();
+        let first = read_case(0, &file.cases[0], &file).unwrap();
+        let second = read_case(1, &file.cases[1], &file).unwrap();
+
+        assert_eq!(first.input, ["a b", "3"]);
+        assert_eq!(first.expected, "3\n");
+        assert_eq!(second.input, ["first", "second"]);
+        assert_eq!(second.expected, r"^\d+$");
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        let error = parse("[[cases]]\nexpect = \"\"\n").err().unwrap();
+        assert!(error.contains("unknown field `expect`"), "{error}");
+
+        let error = parse("[[cases]]\nname = \"x\"\n").err().unwrap();
+        assert!(error.contains("missing field `expected`"), "{error}");
+
+        let error = parse("match = \"close\"\ncases = []\n").err().unwrap();
+        assert!(error.contains("unknown variant `close`"), "{error}");
+
+        let error = parse("[[cases]]\nexpected = \"\"\ninput = true\n")
+            .err()
+            .unwrap();
+        assert!(error.contains("a string or a list of lines"), "{error}");
+
+        let file = parse("[[cases]]\nexpected = \"(\"\nmatch = \"regex\"\n").unwrap();
+        let error = read_case(0, &file.cases[0], &file).err().unwrap();
+        assert!(error.starts_with("`expected` is not a regex"), "{error}");
+
+        let file = parse("time_limit = 0\n[[cases]]\nexpected = \"\"\n").unwrap();
+        let error = read_case(0, &file.cases[0], &file).err().unwrap();
+        assert_eq!(error, "`time_limit` has to be a number of seconds");
+    }
+}
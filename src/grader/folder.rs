@@ -0,0 +1,145 @@
+//! `aplang grade-dir`, grades a folder of programs on several threads.
+//! an `Interpreter` keeps its values in `Rc`s, so it can never move to another thread.
+//! instead each thread reads, parses and runs its programs from the start with its own
+//! input and output, and only the paths going in and the results coming out cross threads
+//!
+//! the results do not depend on which thread runs what. the one exception is `time_limit`,
+//! which is measured on the clock, so a busy computer can push a slow program over it.
+//! `max_steps` stops a program at the same place every time
+
+use super::cases::Case;
+use super::{grade, load_cases, to_json, Outcome};
+use crate::arguments::{GradeDirCommand, ReportFormat};
+use crate::find_sources;
+use miette::{miette, Result};
+use serde_json::json;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// grades every program in the folder and shows the results
+pub fn run_dir(command: GradeDirCommand, stack_size: usize) -> Result<()> {
+    let cases = load_cases(&command.cases)?;
+    let mut submissions = vec![];
+    find_sources(&command.dir, &mut submissions)?;
+
+    let jobs = command
+        .jobs
+        .map(usize::from)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+        .min(submissions.len())
+        .max(1);
+    let graded = grade_all(&submissions, &cases, jobs, stack_size)?;
+
+    let names: Vec<String> = submissions
+        .iter()
+        .map(|submission| submission.display().to_string())
+        .collect();
+    match command.format {
+        ReportFormat::Human => show(&names, &graded, cases.len()),
+        ReportFormat::Json => {
+            let passed = graded.iter().filter(|results| passed(results)).count();
+            let submissions: Vec<_> = names
+                .iter()
+                .zip(&graded)
+                .map(|(name, results)| to_json(name, results))
+                .collect();
+            let json = json!({
+                "cases": cases.len(),
+                "passed": passed,
+                "failed": graded.len() - passed,
+                "submissions": submissions,
+            });
+            println!("{json:#}");
+        }
+    }
+
+    let failed = graded.iter().filter(|results| !passed(results)).count();
+    if failed > 0 {
+        return Err(miette!(
+            "{failed} of {} submission(s) did not pass every case",
+            graded.len()
+        ));
+    }
+    Ok(())
+}
+
+/// grades each submission on one of `jobs` threads.
+/// the results are in the same order as the submissions, whichever thread finishes first
+fn grade_all<'a>(
+    submissions: &[PathBuf],
+    cases: &'a [Case],
+    jobs: usize,
+    stack_size: usize,
+) -> Result<Vec<Vec<Outcome<'a>>>> {
+    let next = AtomicUsize::new(0);
+    let mut graded: Vec<Option<Vec<Outcome>>> = submissions.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let next = &next;
+            thread::Builder::new()
+                .name("grader".to_string())
+                .stack_size(stack_size)
+                .spawn_scoped(scope, move || loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(submission) = submissions.get(index) else {
+                        break;
+                    };
+                    // only fails once nothing is listening for results anymore
+                    let _ = sender.send((index, grade(submission, cases)));
+                })
+                .map_err(|err| miette!("could not start a thread to grade on\n{err}"))?;
+        }
+        drop(sender);
+
+        for (index, results) in receiver {
+            graded[index] = Some(results);
+        }
+        Ok::<_, miette::Report>(())
+    })?;
+
+    Ok(graded
+        .into_iter()
+        .map(|results| results.expect("every submission was graded"))
+        .collect())
+}
+
+fn passed(results: &[Outcome]) -> bool {
+    results.iter().all(Outcome::passed)
+}
+
+/// a line for each submission with the cases it did not pass.
+/// `aplang grade` shows why a single submission failed
+fn show(names: &[String], graded: &[Vec<Outcome>], cases: usize) {
+    println!(
+        "grading {} submission(s) with {cases} case(s)",
+        graded.len()
+    );
+    for (name, results) in names.iter().zip(graded) {
+        let passed_cases = results.iter().filter(|result| result.passed()).count();
+        if passed(results) {
+            println!("submission {name} ... ok ({passed_cases}/{cases})");
+        } else {
+            let failed: Vec<&str> = results
+                .iter()
+                .filter(|result| !result.passed())
+                .map(|result| result.case.name.as_str())
+                .collect();
+            println!(
+                "submission {name} ... FAILED ({passed_cases}/{cases}): {}",
+                failed.join(", ")
+            );
+        }
+    }
+
+    let passed = graded.iter().filter(|results| passed(results)).count();
+    println!(
+        "\ngrade: {passed}/{} submission(s) passed every case",
+        graded.len()
+    );
+}
@@ -0,0 +1,344 @@
+//! `aplang grade`, runs a program once for each case in a case file and checks what it displayed.
+//! every case runs in this process with its own interpreter, input and limits
+
+use crate::arguments::{GradeCommand, ReportFormat};
+use crate::input::Lines;
+use crate::interpreter::{Clock, Settings};
+use crate::output::Buffer;
+use crate::tester::{load, Failure};
+use cases::{Case, Matching};
+use miette::{miette, Result};
+use serde_json::{json, Value as Json};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub use folder::run_dir;
+
+mod cases;
+mod folder;
+
+/// how many unchanged lines are shown around each difference
+const CONTEXT: usize = 2;
+
+/// diffs bigger than this many lines times lines only compare the lines in order
+const MAX_DIFF: usize = 1_000_000;
+
+/// grades the program and shows the results
+pub fn run(command: GradeCommand) -> Result<()> {
+    let cases = load_cases(&command.cases)?;
+    let results = grade(&command.submission, &cases);
+
+    let submission = command.submission.display().to_string();
+    match command.format {
+        ReportFormat::Human => show(&submission, &results),
+        ReportFormat::Json => println!("{:#}", to_json(&submission, &results)),
+    }
+
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    if failed > 0 {
+        return Err(miette!(
+            "{failed} of {} case(s) did not pass",
+            results.len()
+        ));
+    }
+    Ok(())
+}
+
+fn load_cases(path: &Path) -> Result<Vec<Case>> {
+    cases::load(path)
+        .map_err(|err| miette!("could not load the cases in {}\n{err}", path.display()))
+}
+
+/// runs every case, in order
+fn grade<'a>(submission: &Path, cases: &'a [Case]) -> Vec<Outcome<'a>> {
+    cases
+        .iter()
+        .map(|case| {
+            // a program that makes aplang panic fails the case instead of stopping the grading
+            let start = Instant::now();
+            panic::catch_unwind(AssertUnwindSafe(|| run_case(submission, case))).unwrap_or_else(
+                |panic| {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    Outcome {
+                        case,
+                        status: Status::Error(Failure::from_reports(vec![miette!(
+                            "aplang crashed while running this program\n{message}"
+                        )])),
+                        output: String::new(),
+                        time: start.elapsed(),
+                    }
+                },
+            )
+        })
+        .collect()
+}
+
+/// how one case went
+struct Outcome<'a> {
+    case: &'a Case,
+    status: Status,
+    /// everything the program displayed, even when it stopped with an error
+    output: String,
+    time: Duration,
+}
+
+enum Status {
+    Passed,
+    /// the program finished, but displayed something else
+    Failed {
+        diff: Option<String>,
+    },
+    /// the program could not run, or stopped with an error
+    Error(Failure),
+}
+
+fn run_case<'a>(submission: &Path, case: &'a Case) -> Outcome<'a> {
+    let output = Buffer::new();
+    let settings = Settings {
+        budget: case.budget.clone(),
+        seed: Some(case.seed),
+        // SLEEP does not slow the grading down, and TIME is the same every run
+        clock: Clock::Virtual,
+        ..Settings::default()
+    };
+
+    let start = Instant::now();
+    let error = load(submission)
+        .map_err(Failure::from_reports)
+        .and_then(|parsed| {
+            let mut interpreter = parsed
+                .with_settings(settings)
+                .interpreter()
+                .with_output(output.clone())
+                .with_input(Lines::new(case.input.clone()));
+
+            interpreter
+                .interpret()
+                .map_err(|err| Failure::from_runtime(err, |err| interpreter.report(err)))
+        })
+        .err();
+    let time = start.elapsed();
+
+    let output = output.contents();
+    let status = match error {
+        Some(failure) => Status::Error(failure),
+        None if case.matching.matches(&output, &case.expected) => Status::Passed,
+        None => Status::Failed {
+            diff: match case.matching {
+                Matching::Exact => Some(diff(&case.expected, &output)),
+                Matching::Trimmed => {
+                    Some(diff(&cases::trim(&case.expected), &cases::trim(&output)))
+                }
+                Matching::Regex(_) => None,
+            },
+        },
+    };
+
+    Outcome {
+        case,
+        status,
+        output,
+        time,
+    }
+}
+
+/// the lines that are different, with `-` for expected lines that are missing
+/// and `+` for lines that were displayed instead
+fn diff(expected: &str, actual: &str) -> String {
+    if expected.trim_end() == actual.trim_end() {
+        return "only the spaces or blank lines at the end are different\n".to_string();
+    }
+
+    let expected: Vec<&str> = expected.split_terminator('\n').collect();
+    let actual: Vec<&str> = actual.split_terminator('\n').collect();
+
+    // the same lines at the start and end are not part of the difference
+    let start = expected
+        .iter()
+        .zip(&actual)
+        .take_while(|(expected, actual)| expected == actual)
+        .count();
+    let end = expected[start..]
+        .iter()
+        .rev()
+        .zip(actual[start..].iter().rev())
+        .take_while(|(expected, actual)| expected == actual)
+        .count();
+    let (old, new) = (
+        &expected[start..expected.len() - end],
+        &actual[start..actual.len() - end],
+    );
+
+    let mut lines: Vec<(char, &str)> = expected[..start].iter().map(|line| (' ', *line)).collect();
+    lines.extend(changes(old, new));
+    lines.extend(
+        expected[expected.len() - end..]
+            .iter()
+            .map(|line| (' ', *line)),
+    );
+
+    // only the lines near a change are shown
+    let near = |index: usize| {
+        lines[index.saturating_sub(CONTEXT)..(index + CONTEXT + 1).min(lines.len())]
+            .iter()
+            .any(|(kind, _)| *kind != ' ')
+    };
+    let mut text = String::new();
+    let mut skipped = false;
+    for (index, (kind, line)) in lines.iter().enumerate() {
+        if near(index) {
+            text.push_str(&format!("{kind} {line}\n"));
+            skipped = false;
+        } else if !skipped {
+            text.push_str("  ...\n");
+            skipped = true;
+        }
+    }
+    text
+}
+
+/// the fewest removed and added lines that turn `old` into `new`
+fn changes<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)> {
+    if old.len() * new.len() > MAX_DIFF {
+        return old
+            .iter()
+            .map(|line| ('-', *line))
+            .chain(new.iter().map(|line| ('+', *line)))
+            .collect();
+    }
+
+    // longest[i][j] is how many lines old[i..] and new[j..] have in common
+    let mut longest = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            longest[i][j] = if old[i] == new[j] {
+                longest[i + 1][j + 1] + 1
+            } else {
+                longest[i + 1][j].max(longest[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut changes = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            changes.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && longest[i + 1][j] >= longest[i][j + 1]) {
+            changes.push(('-', old[i]));
+            i += 1;
+        } else {
+            changes.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+impl Outcome<'_> {
+    fn passed(&self) -> bool {
+        matches!(self.status, Status::Passed)
+    }
+}
+
+impl Status {
+    fn label(&self) -> &'static str {
+        match self {
+            Status::Passed => "passed",
+            Status::Failed { .. } => "failed",
+            Status::Error(_) => "error",
+        }
+    }
+}
+
+/// a line for each case, then what went wrong in the ones that did not pass
+fn show(submission: &str, results: &[Outcome]) {
+    println!("grading {submission} with {} case(s)", results.len());
+    for result in results {
+        let status = match result.status {
+            Status::Passed => "ok",
+            Status::Failed { .. } => "FAILED",
+            Status::Error(_) => "ERROR",
+        };
+        println!("case {} ... {status}", result.case.name);
+    }
+
+    let failures: Vec<&Outcome> = results.iter().filter(|result| !result.passed()).collect();
+    if !failures.is_empty() {
+        println!("\nfailures:");
+    }
+    for result in &failures {
+        let case = result.case;
+        println!("\n---- {} ----", case.name);
+        if !case.input.is_empty() {
+            println!("input:");
+            case.input.iter().for_each(|line| println!("  {line}"));
+        }
+        match &result.status {
+            Status::Passed => {}
+            Status::Failed { diff: Some(diff) } => {
+                println!(
+                    "the output is different ({}, - expected, + got):",
+                    case.matching.name()
+                );
+                print!("{diff}");
+            }
+            Status::Failed { diff: None } => {
+                println!("the output does not match the regex {}", case.expected);
+                println!("output:\n{}", result.output.trim_end());
+            }
+            Status::Error(failure) => {
+                failure
+                    .reports
+                    .iter()
+                    .for_each(|report| println!("{report:?}"));
+                if !result.output.is_empty() {
+                    println!("output:\n{}", result.output.trim_end());
+                }
+            }
+        }
+    }
+
+    let passed = results.len() - failures.len();
+    println!("\ngrade: {passed}/{} case(s) passed", results.len());
+}
+
+fn to_json(submission: &str, results: &[Outcome]) -> Json {
+    let cases: Vec<Json> = results
+        .iter()
+        .map(|result| {
+            let case = result.case;
+            let mut json = json!({
+                "name": case.name,
+                "status": result.status.label(),
+                "passed": result.passed(),
+                "match": case.matching.name(),
+                "input": case.input,
+                "expected": case.expected,
+                "output": result.output,
+                "seconds": result.time.as_secs_f64(),
+            });
+            match &result.status {
+                Status::Failed { diff: Some(diff) } => json["diff"] = json!(diff),
+                Status::Error(failure) => json["error"] = failure.to_json(),
+                _ => {}
+            }
+            json
+        })
+        .collect();
+
+    let passed = results.iter().filter(|result| result.passed()).count();
+    json!({
+        "submission": submission,
+        "passed": passed,
+        "failed": results.len() - passed,
+        "cases": cases,
+    })
+}
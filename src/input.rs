@@ -0,0 +1,100 @@
+//! where INPUT gets its lines from
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::path::Path;
+
+/// somewhere a program reads its input from.
+/// INPUT and INPUT_PROMPT read one line from it each time they are called
+pub trait Input {
+    /// the next line, without trailing whitespace. None once there is nothing left to read
+    fn read_line(&mut self, prompt: &str) -> Option<String>;
+
+    /// if `read_line` shows the prompt itself.
+    /// if not, the prompt is written to the output of the interpreter first
+    fn shows_prompt(&self) -> bool {
+        false
+    }
+}
+
+/// the terminal, or the javascript callback on wasm. this is the default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stdin;
+
+#[cfg(not(feature = "wasm"))]
+impl Input for Stdin {
+    fn read_line(&mut self, _prompt: &str) -> Option<String> {
+        read_line(&mut io::stdin().lock())
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl Input for Stdin {
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        use crate::wasm::IN;
+        use wasm_bindgen::JsValue;
+
+        IN.with(|input| {
+            let callback = input.borrow();
+            let callback = callback.as_ref()?;
+
+            callback
+                .call1(&JsValue::NULL, &JsValue::from_str(prompt))
+                .ok()?
+                .as_string()
+        })
+    }
+
+    fn shows_prompt(&self) -> bool {
+        true
+    }
+}
+
+/// lines that were prepared ahead of time, like a transcript for a grader
+#[derive(Debug, Default, Clone)]
+pub struct Lines {
+    lines: VecDeque<String>,
+}
+
+impl Lines {
+    pub fn new<L: Into<String>>(lines: impl IntoIterator<Item = L>) -> Self {
+        Self {
+            lines: lines.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Input for Lines {
+    fn read_line(&mut self, _prompt: &str) -> Option<String> {
+        self.lines.pop_front()
+    }
+}
+
+/// reads lines from anything that implements `BufRead`, like a file
+#[derive(Debug)]
+pub struct Reader<R: BufRead>(pub R);
+
+impl Reader<io::BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self(io::BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> Input for Reader<R> {
+    fn read_line(&mut self, _prompt: &str) -> Option<String> {
+        read_line(&mut self.0)
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> Option<String> {
+    let mut buf = String::new();
+
+    // nothing was read, so the input is over
+    if reader.read_line(&mut buf).ok()? == 0 {
+        return None;
+    }
+
+    Some(buf.trim_end().to_string())
+}
@@ -0,0 +1,150 @@
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::settings::Budget;
+use crate::interpreter::{Interpreter, Value};
+use crate::lexer::token::{Token, TokenType};
+use crate::parser::ast::ProcCall;
+use miette::NamedSource;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// how much of its budget a program has used.
+/// imported modules share the meter of the file that imported them
+#[derive(Clone, Debug, Default)]
+pub struct Meter {
+    /// the statements that ran so far
+    steps: Rc<Cell<u64>>,
+    /// when the program has to be done by
+    deadline: Option<Instant>,
+}
+
+impl Meter {
+    pub fn start(budget: &Budget) -> Self {
+        Self {
+            steps: Rc::default(),
+            // a limit too far away for the clock to count is no limit
+            deadline: budget
+                .time_limit
+                .and_then(|limit| Instant::now().checked_add(limit)),
+        }
+    }
+}
+
+/// what was running when the budget was checked.
+/// a program can only run forever in a loop or through calls, so those are the only checks
+pub enum Running<'a> {
+    /// a REPEAT or FOR EACH loop, by its first token
+    Loop(&'a Token),
+    /// a call to a PROCEDURE
+    Call(&'a ProcCall, &'a str),
+}
+
+impl Interpreter {
+    /// counts one statement towards `max_steps`
+    pub(super) fn step(&self) {
+        self.meter.steps.set(self.meter.steps.get() + 1);
+    }
+
+    /// how long until the time limit is reached. None if there is no time limit
+    pub(super) fn time_left(&self) -> Option<Duration> {
+        self.meter
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// stops the program if it ran out of steps or time, or was cancelled
+    pub(super) fn check_budget(&self, running: Running) -> Result<(), RuntimeError> {
+        if let Some(cancel) = &self.get_settings().cancel {
+            if cancel.is_cancelled() {
+                return Err(self.out_of_budget(
+                    running,
+                    "Execution Cancelled",
+                    "The program was stopped before it finished".to_string(),
+                ));
+            }
+        }
+
+        if let Some(max_steps) = self.get_settings().budget.max_steps {
+            if self.meter.steps.get() > max_steps {
+                return Err(self.out_of_budget(
+                    running,
+                    "Step Limit Reached",
+                    format!("The program ran more than {max_steps} statements. Make sure every loop can end"),
+                ));
+            }
+        }
+
+        if let Some(deadline) = self.meter.deadline {
+            if Instant::now() >= deadline {
+                return Err(self.out_of_budget(
+                    running,
+                    "Time Limit Reached",
+                    "The program ran for too long. Make sure every loop can end".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn out_of_budget(&self, running: Running, message: &str, help: String) -> RuntimeError {
+        let (token, label) = match running {
+            Running::Loop(token) => {
+                let kind = if token.token_type == TokenType::For {
+                    "FOR EACH"
+                } else {
+                    "REPEAT"
+                };
+                (token, format!("This {kind} loop was running"))
+            }
+            Running::Call(call, name) => (&call.token, format!("This call to `{name}` was running")),
+        };
+
+        RuntimeError {
+            named_source: NamedSource::new(self.get_file_path(), token.source.clone()),
+            span: token.span,
+            message: message.to_string(),
+            help,
+            label,
+        }
+    }
+
+    /// stops the program if `value` is a LIST longer than `max_list_len`
+    pub(super) fn check_list_len(&self, value: &Value, token: &Token) -> Result<(), RuntimeError> {
+        let (Some(max_list_len), Value::List(list)) = (self.get_settings().budget.max_list_len, value)
+        else {
+            return Ok(());
+        };
+
+        let len = list.borrow().len();
+        if len <= max_list_len {
+            return Ok(());
+        }
+
+        Err(RuntimeError {
+            named_source: NamedSource::new(self.get_file_path(), token.source.clone()),
+            span: token.span,
+            message: "List Too Long".to_string(),
+            help: format!("A LIST can have at most {max_list_len} items"),
+            label: format!("This made a LIST with {len} items"),
+        })
+    }
+
+    /// a call to the standard library can grow the LISTs it is given or return a long one
+    pub(super) fn check_native_lists(
+        &self,
+        call: &ProcCall,
+        args: &[Value],
+        value: &Value,
+    ) -> Result<(), RuntimeError> {
+        if self.get_settings().budget.max_list_len.is_none() {
+            return Ok(());
+        }
+
+        for value in args.iter().chain([value]) {
+            self.check_list_len(value, &call.token)?;
+        }
+
+        Ok(())
+    }
+}
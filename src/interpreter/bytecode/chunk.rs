@@ -0,0 +1,112 @@
+use crate::interpreter::Value;
+use crate::lexer::token::Token;
+use crate::parser::ast::{
+    Access, Assignment, Binary, ForEach, Import, ProcCall, ProcDeclaration, RepeatTimes, Set,
+    Unary, Variable,
+};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// a single instruction.
+///
+/// instructions that can fail point into a table of the chunk they belong to.
+/// the table keeps the syntax tree node so errors have the exact same spans
+/// as they do when walking the tree
+#[derive(Copy, Clone, Debug)]
+pub enum Op {
+    /// push `constants[i]`
+    Constant(u32),
+    Null,
+    True,
+    False,
+
+    /// throw away the top of the stack
+    Pop,
+    /// move the top of the stack into the debug output
+    Collect,
+
+    /// build a LIST out of the top n values
+    List(u32),
+    /// create an anonymous PROCEDURE from `functions[i]`
+    Lambda(u32),
+
+    /// push the value of `variables[i]`
+    Get(u32),
+    /// assign the top of the stack with `assignments[i]`. leaves the value on the stack
+    Assign(u32),
+
+    /// `binaries[i]` on the top two values
+    Binary(u32),
+    /// `unaries[i]` on the top value
+    Unary(u32),
+    /// index a LIST or STRING with `accesses[i]`
+    Access(u32),
+    /// set an item in a LIST with `sets[i]`
+    Set(u32),
+
+    /// jump to an instruction
+    Jump(u32),
+    /// pop the top value and jump if it is not truthy
+    JumpIfFalse(u32),
+    /// pop the top value and jump if it is truthy
+    JumpIfTrue(u32),
+    /// jump if the top value is truthy, otherwise pop it. used for OR
+    JumpIfTrueOrPop(u32),
+    /// jump if the top value is not truthy, otherwise pop it. used for AND
+    JumpIfFalseOrPop(u32),
+
+    /// start a REPEAT n TIMES loop with the count on the top of the stack
+    Repeat(u32),
+    /// start a FOR EACH loop with the LIST on the top of the stack
+    Iterate(u32),
+    /// go to the next round of the loop, or jump to the end if it is done
+    Next(u32),
+    /// write the FOR EACH item back into the LIST
+    Store,
+    /// leave the innermost loop
+    EndLoop,
+
+    /// call `calls[i]` with the arguments on the top of the stack
+    Call(u32),
+    /// return the top of the stack from the current PROCEDURE
+    Return,
+    /// declare `declarations[i]`
+    Declare(u32),
+    /// run `imports[i]`
+    Import(u32),
+
+    /// count a statement towards the step limit. only there if there is one
+    Step,
+    /// check the budget and the cancel token at the start of a round of the loop
+    /// at `checkpoints[i]`. only there if the program is metered
+    Check(u32),
+}
+
+/// compiled code along with the tables its instructions point into
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+
+    pub constants: Vec<Value>,
+    pub variables: Vec<Arc<Variable>>,
+    pub assignments: Vec<Arc<Assignment>>,
+    pub binaries: Vec<Arc<Binary>>,
+    pub unaries: Vec<Arc<Unary>>,
+    pub accesses: Vec<Arc<Access>>,
+    pub sets: Vec<Arc<Set>>,
+    pub calls: Vec<Arc<ProcCall>>,
+    pub repeats: Vec<Arc<RepeatTimes>>,
+    pub loops: Vec<(Arc<ForEach>, Arc<Variable>)>,
+    pub functions: Vec<Rc<Function>>,
+    pub declarations: Vec<(Arc<ProcDeclaration>, Rc<Function>)>,
+    pub imports: Vec<Arc<Import>>,
+    /// the first token of each loop that checks the budget
+    pub checkpoints: Vec<Token>,
+}
+
+/// a compiled PROCEDURE, or the top level of a file
+pub struct Function {
+    pub name: Rc<str>,
+    pub params: Vec<Arc<Variable>>,
+    pub chunk: Chunk,
+}
@@ -0,0 +1,337 @@
+use crate::interpreter::bytecode::chunk::{Chunk, Function, Op};
+use crate::interpreter::{Interpreter, Settings};
+use crate::lexer::token::Token;
+use crate::parser::ast::{Ast, Expr, Literal, LogicalOp, ProcDeclaration, Stmt, Variable};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// turns a syntax tree into bytecode.
+/// the parser and the resolver already checked the program, so compiling cannot fail
+#[derive(Default)]
+pub struct Compiler {
+    chunk: Chunk,
+
+    /// the loops we are inside of, innermost last
+    loops: Vec<Loop>,
+
+    /// if statements have to be counted for `max_steps`
+    count_steps: bool,
+    /// if each round of a loop checks the budget and the cancel token
+    check_loops: bool,
+}
+
+struct Loop {
+    /// where a CONTINUE jumps to
+    start: u32,
+    /// BREAK jumps that go to the end of the loop, patched once the end is known
+    breaks: Vec<usize>,
+}
+
+impl Compiler {
+    /// compiles the top level of a program.
+    /// if `collect` is set the values of top level expressions are kept for debug output
+    pub fn compile(ast: &Ast, collect: bool, settings: &Settings) -> Function {
+        let mut compiler = Compiler {
+            count_steps: settings.budget.max_steps.is_some(),
+            check_loops: settings.is_metered(),
+            ..Compiler::default()
+        };
+
+        // PROCEDUREs at the top level can be called before their declaration
+        for stmt in &ast.program {
+            if let Stmt::ProcDeclaration(proc_dec) = stmt {
+                compiler.declaration(proc_dec);
+            }
+        }
+
+        for stmt in &ast.program {
+            match stmt {
+                Stmt::Expr(expr) if collect => {
+                    compiler.expr(expr);
+                    compiler.emit(Op::Collect);
+                }
+                // already declared
+                Stmt::ProcDeclaration(_) => {}
+                stmt => compiler.stmt(stmt),
+            }
+        }
+
+        // the top level does not have a name
+        compiler.finish("".into(), vec![])
+    }
+
+    fn procedure(&self, name: &str, params: &[Variable], body: &Stmt) -> Function {
+        let mut compiler = Compiler {
+            count_steps: self.count_steps,
+            check_loops: self.check_loops,
+            ..Compiler::default()
+        };
+        compiler.stmt(body);
+
+        let params = params.iter().cloned().map(Arc::new).collect();
+        compiler.finish(name.into(), params)
+    }
+
+    fn declaration(&mut self, proc_dec: &Arc<ProcDeclaration>) {
+        let function = self.procedure(&proc_dec.name, &proc_dec.params, &proc_dec.body);
+        let declaration = add(
+            &mut self.chunk.declarations,
+            (proc_dec.clone(), Rc::new(function)),
+        );
+        self.emit(Op::Declare(declaration));
+    }
+
+    fn finish(mut self, name: Rc<str>, params: Vec<Arc<Variable>>) -> Function {
+        // falling off the end returns NULL
+        self.emit(Op::Null);
+        self.emit(Op::Return);
+
+        Function {
+            name,
+            params,
+            chunk: self.chunk,
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        if self.count_steps {
+            self.emit(Op::Step);
+        }
+
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.expr(expr);
+                self.emit(Op::Pop);
+            }
+            Stmt::If(if_stmt) => {
+                self.expr(&if_stmt.condition);
+                let to_else = self.emit(Op::JumpIfFalse(0));
+
+                self.stmt(&if_stmt.then_branch);
+
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    let to_end = self.emit(Op::Jump(0));
+                    self.patch(to_else);
+                    self.stmt(else_branch);
+                    self.patch(to_end);
+                } else {
+                    self.patch(to_else);
+                }
+            }
+            Stmt::RepeatTimes(repeat_times) => {
+                self.expr(&repeat_times.count);
+                let repeat = add(&mut self.chunk.repeats, repeat_times.clone());
+                self.emit(Op::Repeat(repeat));
+
+                self.counted_loop(&repeat_times.repeat_token, &repeat_times.body, false);
+            }
+            Stmt::RepeatUntil(repeat_until) => {
+                let start = self.here();
+                self.expr(&repeat_until.condition);
+                let exit = self.emit(Op::JumpIfTrue(0));
+                self.check(&repeat_until.repeat_token);
+
+                self.loops.push(Loop {
+                    start,
+                    breaks: vec![],
+                });
+                self.stmt(&repeat_until.body);
+                self.emit(Op::Jump(start));
+
+                self.patch(exit);
+                self.patch_breaks();
+            }
+            Stmt::ForEach(for_each) => {
+                self.expr(&for_each.list);
+                let item = Arc::new(for_each.item.clone());
+                let each = add(&mut self.chunk.loops, (for_each.clone(), item));
+                self.emit(Op::Iterate(each));
+
+                self.counted_loop(&for_each.for_token, &for_each.body, true);
+            }
+            Stmt::ProcDeclaration(proc_dec) => self.declaration(proc_dec),
+            Stmt::Block(block) => {
+                for stmt in &block.statements {
+                    self.stmt(stmt)
+                }
+            }
+            Stmt::Return(ret_val) => {
+                if let Some(expr) = &ret_val.data {
+                    self.expr(expr);
+                } else {
+                    self.emit(Op::Null);
+                }
+                self.emit(Op::Return);
+            }
+            Stmt::Continue(_) => {
+                let start = self
+                    .loops
+                    .last()
+                    .expect("the resolver only allows CONTINUE inside of a loop")
+                    .start;
+                self.emit(Op::Jump(start));
+            }
+            Stmt::Break(_) => {
+                let jump = self.emit(Op::Jump(0));
+                self.loops
+                    .last_mut()
+                    .expect("the resolver only allows BREAK inside of a loop")
+                    .breaks
+                    .push(jump);
+            }
+            Stmt::Import(import) => {
+                let import = add(&mut self.chunk.imports, import.clone());
+                self.emit(Op::Import(import));
+            }
+        }
+    }
+
+    // REPEAT n TIMES and FOR EACH keep their state in the vm and
+    // use `Next` to move to the next round
+    fn counted_loop(&mut self, token: &Token, body: &Stmt, store: bool) {
+        let start = self.here();
+        let exit = self.emit(Op::Next(0));
+        self.check(token);
+
+        self.loops.push(Loop {
+            start,
+            breaks: vec![],
+        });
+        self.stmt(body);
+
+        // CONTINUE and BREAK skip writing the item back, the same as the tree walker
+        if store {
+            self.emit(Op::Store);
+        }
+        self.emit(Op::Jump(start));
+
+        self.patch(exit);
+        self.patch_breaks();
+        self.emit(Op::EndLoop);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(lit) => {
+                match &lit.value {
+                    Literal::True => self.emit(Op::True),
+                    Literal::False => self.emit(Op::False),
+                    Literal::Null => self.emit(Op::Null),
+                    value => {
+                        let constant = add(&mut self.chunk.constants, Interpreter::literal(value));
+                        self.emit(Op::Constant(constant))
+                    }
+                };
+            }
+            Expr::Binary(binary) => {
+                self.expr(&binary.left);
+                self.expr(&binary.right);
+                let binary = add(&mut self.chunk.binaries, binary.clone());
+                self.emit(Op::Binary(binary));
+            }
+            Expr::Logical(logical) => {
+                self.expr(&logical.left);
+
+                // the left side is the result if it decides the answer
+                let short_circuit = match logical.operator {
+                    LogicalOp::Or => self.emit(Op::JumpIfTrueOrPop(0)),
+                    LogicalOp::And => self.emit(Op::JumpIfFalseOrPop(0)),
+                };
+
+                self.expr(&logical.right);
+                self.patch(short_circuit);
+            }
+            Expr::Unary(unary) => {
+                self.expr(&unary.right);
+                let unary = add(&mut self.chunk.unaries, unary.clone());
+                self.emit(Op::Unary(unary));
+            }
+            Expr::Grouping(grouping) => self.expr(&grouping.expr),
+            Expr::ProcCall(proc) => {
+                for arg in &proc.arguments {
+                    self.expr(arg);
+                }
+                let call = add(&mut self.chunk.calls, proc.clone());
+                self.emit(Op::Call(call));
+            }
+            Expr::Access(access) => {
+                self.expr(&access.list);
+                self.expr(&access.key);
+                let access = add(&mut self.chunk.accesses, access.clone());
+                self.emit(Op::Access(access));
+            }
+            Expr::List(list) => {
+                for item in &list.items {
+                    self.expr(item);
+                }
+                self.emit(Op::List(list.items.len() as u32));
+            }
+            Expr::Variable(variable) => {
+                let variable = add(&mut self.chunk.variables, variable.clone());
+                self.emit(Op::Get(variable));
+            }
+            Expr::Assign(assignment) => {
+                self.expr(&assignment.value);
+                let assignment = add(&mut self.chunk.assignments, assignment.clone());
+                self.emit(Op::Assign(assignment));
+            }
+            Expr::Set(set) => {
+                self.expr(&set.list);
+                self.expr(&set.idx);
+                self.expr(&set.value);
+                let set = add(&mut self.chunk.sets, set.clone());
+                self.emit(Op::Set(set));
+            }
+            Expr::Lambda(lambda) => {
+                let function = self.procedure("anonymous", &lambda.params, &lambda.body);
+                let function = add(&mut self.chunk.functions, Rc::new(function));
+                self.emit(Op::Lambda(function));
+            }
+        }
+    }
+
+    /// checks the budget at the start of each round of a loop
+    fn check(&mut self, token: &Token) {
+        if self.check_loops {
+            let checkpoint = add(&mut self.chunk.checkpoints, token.clone());
+            self.emit(Op::Check(checkpoint));
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.chunk.code.push(op);
+        self.chunk.code.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    /// points the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let here = self.here();
+
+        match &mut self.chunk.code[at] {
+            Op::Jump(target)
+            | Op::JumpIfFalse(target)
+            | Op::JumpIfTrue(target)
+            | Op::JumpIfTrueOrPop(target)
+            | Op::JumpIfFalseOrPop(target)
+            | Op::Next(target) => *target = here,
+            op => unreachable!("cannot patch {op:?} because it does not jump"),
+        }
+    }
+
+    fn patch_breaks(&mut self) {
+        let lp = self.loops.pop().expect("not inside of a loop");
+
+        for jump in lp.breaks {
+            self.patch(jump)
+        }
+    }
+}
+
+fn add<T>(table: &mut Vec<T>, item: T) -> u32 {
+    table.push(item);
+    (table.len() - 1) as u32
+}
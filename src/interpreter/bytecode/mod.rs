@@ -0,0 +1,13 @@
+//! the bytecode engine.
+//! the compiler flattens the syntax tree into instructions and
+//! the vm runs them with its own stack, so recursive PROCEDUREs
+//! do not recurse in rust.
+//!
+//! the vm is just more methods on `Interpreter`.
+//! it shares the env, the standard library, and the errors with the tree walker
+
+mod chunk;
+mod compiler;
+mod vm;
+
+pub use vm::Closure;
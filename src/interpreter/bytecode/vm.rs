@@ -0,0 +1,414 @@
+use crate::interpreter::bytecode::chunk::{Function, Op};
+use crate::interpreter::budget::Running;
+use crate::interpreter::bytecode::compiler::Compiler;
+use crate::interpreter::env::Scope;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::procedure::Callable;
+use crate::interpreter::{Interpreter, Value};
+use crate::parser::ast::Variable;
+use miette::SourceSpan;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// a compiled PROCEDURE and the scope it was created in
+pub struct Closure {
+    pub function: Rc<Function>,
+    /// storing the closure in this scope makes a loop that `cycles` breaks
+    pub closure: Scope,
+    /// the file the PROCEDURE was written in
+    pub file: Arc<str>,
+}
+
+impl Callable for Closure {
+    // only used when something outside of the vm calls the PROCEDURE.
+    // the vm itself pushes a new frame instead
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        args: &[Value],
+        _args_tokens: &[SourceSpan],
+        _source: Arc<str>,
+    ) -> Result<Value, RuntimeError> {
+        let mut machine = Machine::default();
+
+        interpreter.enter(self, args.iter().cloned());
+        machine
+            .frames
+            .push(Frame::procedure(self.function.clone(), 0, 0));
+
+        interpreter.run(&mut machine)
+    }
+
+    fn arity(&self) -> u8 {
+        self.function.params.len().try_into().unwrap()
+    }
+
+    fn name(&self) -> &str {
+        &self.function.name
+    }
+
+    fn compiled(&self) -> Option<&Closure> {
+        Some(self)
+    }
+
+    fn file(&self) -> Option<&Arc<str>> {
+        Some(&self.file)
+    }
+
+    fn closure(&self) -> Option<&Scope> {
+        Some(&self.closure)
+    }
+}
+
+struct Frame {
+    function: Rc<Function>,
+    /// the next instruction. only kept up to date while another frame runs
+    ip: usize,
+
+    /// where the values of this call start on the stack
+    stack: usize,
+    /// where the loops of this call start
+    loops: usize,
+
+    /// if the call entered a procedure scope that it has to leave
+    procedure: bool,
+    /// if the call is on the call stack of the interpreter.
+    /// calls from outside of the vm are put there by the caller
+    traced: bool,
+}
+
+impl Frame {
+    fn procedure(function: Rc<Function>, stack: usize, loops: usize) -> Self {
+        Self {
+            function,
+            ip: 0,
+            stack,
+            loops,
+            procedure: true,
+            traced: false,
+        }
+    }
+}
+
+enum LoopState {
+    Repeat {
+        remaining: usize,
+    },
+    Each {
+        list: Rc<RefCell<Vec<Value>>>,
+        item: Arc<Variable>,
+        next: usize,
+        len: usize,
+        /// a variable with the same name as the item, put back once the loop is done
+        cached: Option<(Value, Arc<Variable>)>,
+    },
+}
+
+#[derive(Default)]
+struct Machine {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    loops: Vec<LoopState>,
+
+    /// the values of top level expressions, for debug output
+    collected: Vec<Value>,
+}
+
+impl Interpreter {
+    /// compiles the program and runs it.
+    /// the values of top level expressions are returned if `collect` is set
+    pub(in crate::interpreter) fn run_program(
+        &mut self,
+        collect: bool,
+    ) -> Result<Vec<Value>, RuntimeError> {
+        let main = Compiler::compile(&self.ast, collect, self.get_settings());
+
+        let mut machine = Machine::default();
+        machine.frames.push(Frame {
+            procedure: false,
+            ..Frame::procedure(Rc::new(main), 0, 0)
+        });
+
+        self.run(&mut machine)?;
+        Ok(machine.collected)
+    }
+
+    // the procedure gets a fresh scope that can see every variable from where it was created
+    fn enter(&mut self, closure: &Closure, args: impl IntoIterator<Item = Value>) {
+        self.venv
+            .enter_procedure(closure.function.name.clone(), closure.closure.clone());
+
+        for (param, arg) in closure.function.params.iter().zip(args) {
+            self.venv.define(param.clone(), arg);
+        }
+    }
+
+    fn run(&mut self, machine: &mut Machine) -> Result<Value, RuntimeError> {
+        let result = self.execute(machine);
+
+        // leave every procedure that was still running so the env is back where it started.
+        // the call stack is left alone, it is the trace of the error
+        if result.is_err() {
+            for frame in machine.frames.drain(..).rev() {
+                if frame.procedure {
+                    self.venv.exit_procedure();
+                }
+            }
+        }
+
+        result
+    }
+
+    fn execute(&mut self, machine: &mut Machine) -> Result<Value, RuntimeError> {
+        let frame = machine.frames.last().expect("there is no frame to run");
+        let mut function = frame.function.clone();
+        let mut ip = frame.ip;
+
+        loop {
+            let op = function.chunk.code[ip];
+            let chunk = &function.chunk;
+            ip += 1;
+
+            match op {
+                Op::Constant(constant) => machine
+                    .stack
+                    .push(chunk.constants[constant as usize].clone()),
+                Op::Null => machine.stack.push(Value::Null),
+                Op::True => machine.stack.push(Value::Bool(true)),
+                Op::False => machine.stack.push(Value::Bool(false)),
+                Op::Pop => {
+                    pop(&mut machine.stack);
+                }
+                Op::Collect => {
+                    let value = pop(&mut machine.stack);
+                    machine.collected.push(value);
+                }
+                Op::List(len) => {
+                    let start = machine.stack.len() - len as usize;
+                    let items = machine.stack.drain(start..).collect();
+                    machine
+                        .stack
+                        .push(Value::List(Rc::new(RefCell::new(items))));
+                }
+                Op::Lambda(lambda) => {
+                    let closure = Closure {
+                        function: chunk.functions[lambda as usize].clone(),
+                        closure: self.venv.capture(),
+                        file: self.get_file_path().into(),
+                    };
+                    machine.stack.push(Value::Function(Rc::new(closure)));
+                }
+                Op::Get(variable) => {
+                    let value = self.variable(&chunk.variables[variable as usize])?;
+                    machine.stack.push(value);
+                }
+                Op::Assign(assignment) => {
+                    let value = machine.stack.last().expect("nothing to assign");
+                    self.assign(&chunk.assignments[assignment as usize], value);
+                }
+                Op::Binary(binary) => {
+                    let rhs = pop(&mut machine.stack);
+                    let lhs = pop(&mut machine.stack);
+                    let value = self.binary_value(&chunk.binaries[binary as usize], lhs, rhs)?;
+                    machine.stack.push(value);
+                }
+                Op::Unary(unary) => {
+                    let value = pop(&mut machine.stack);
+                    let value = self.unary_value(&chunk.unaries[unary as usize], value)?;
+                    machine.stack.push(value);
+                }
+                Op::Access(access) => {
+                    let idx = pop(&mut machine.stack);
+                    let list = pop(&mut machine.stack);
+                    let value = self.access_value(&chunk.accesses[access as usize], list, idx)?;
+                    machine.stack.push(value);
+                }
+                Op::Set(set) => {
+                    let value = pop(&mut machine.stack);
+                    let idx = pop(&mut machine.stack);
+                    let list = pop(&mut machine.stack);
+                    let value = self.set_value(&chunk.sets[set as usize], list, idx, value)?;
+                    machine.stack.push(value);
+                }
+                Op::Jump(target) => ip = target as usize,
+                Op::JumpIfFalse(target) => {
+                    if !Self::is_truthy(&pop(&mut machine.stack)) {
+                        ip = target as usize
+                    }
+                }
+                Op::JumpIfTrue(target) => {
+                    if Self::is_truthy(&pop(&mut machine.stack)) {
+                        ip = target as usize
+                    }
+                }
+                Op::JumpIfTrueOrPop(target) => {
+                    if Self::is_truthy(machine.stack.last().expect("nothing to check")) {
+                        ip = target as usize
+                    } else {
+                        pop(&mut machine.stack);
+                    }
+                }
+                Op::JumpIfFalseOrPop(target) => {
+                    if !Self::is_truthy(machine.stack.last().expect("nothing to check")) {
+                        ip = target as usize
+                    } else {
+                        pop(&mut machine.stack);
+                    }
+                }
+                Op::Repeat(repeat) => {
+                    let count = pop(&mut machine.stack);
+                    let remaining = self.repeat_count(&chunk.repeats[repeat as usize], count)?;
+                    machine.loops.push(LoopState::Repeat { remaining });
+                }
+                Op::Iterate(each) => {
+                    let (for_each, item) = &chunk.loops[each as usize];
+                    let values = pop(&mut machine.stack);
+                    let list = self.iterable(for_each, values)?;
+
+                    // if the variable already exists temporarily remove it so it doesn't get lost
+                    let cached = self.venv.remove(item.clone());
+                    let len = list.borrow().len();
+
+                    machine.loops.push(LoopState::Each {
+                        list,
+                        item: item.clone(),
+                        next: 0,
+                        len,
+                        cached,
+                    });
+                }
+                Op::Next(exit) => match machine.loops.last_mut().expect("not in a loop") {
+                    LoopState::Repeat { remaining } => {
+                        if *remaining == 0 {
+                            ip = exit as usize;
+                        } else {
+                            *remaining -= 1;
+                        }
+                    }
+                    LoopState::Each {
+                        list,
+                        item,
+                        next,
+                        len,
+                        ..
+                    } => {
+                        let value = list.borrow().get(*next).cloned();
+
+                        match value {
+                            Some(value) if *next < *len => {
+                                self.venv.define(item.clone(), value);
+                                *next += 1;
+                            }
+                            // the list might have gotten shorter while looping
+                            _ => ip = exit as usize,
+                        }
+                    }
+                },
+                Op::Store => {
+                    let Some(LoopState::Each {
+                        list, item, next, ..
+                    }) = machine.loops.last()
+                    else {
+                        unreachable!("only a FOR EACH loop stores its item")
+                    };
+
+                    // get the item out and change it in the list
+                    if let Some((value, _)) = self.venv.remove(item.clone()) {
+                        if let Some(slot) = list.borrow_mut().get_mut(next - 1) {
+                            *slot = value;
+                        }
+                    }
+                }
+                Op::EndLoop => {
+                    // put the variable back if it was originally defined
+                    if let Some(LoopState::Each {
+                        cached: Some((value, variable)),
+                        ..
+                    }) = machine.loops.pop()
+                    {
+                        self.venv.define(variable, value)
+                    }
+                }
+                Op::Call(call) => {
+                    let call = &chunk.calls[call as usize];
+                    let arguments = call.arguments.len();
+                    let callable = self.callee(call, arguments)?;
+                    let start = machine.stack.len() - arguments;
+
+                    if let Some(closure) = callable.compiled() {
+                        // run the procedure in a new frame instead of recursing
+                        machine.frames.last_mut().expect("no caller").ip = ip;
+
+                        let traced = self.enter_call(&callable, call)?;
+                        self.enter(closure, machine.stack.drain(start..));
+                        machine.frames.push(Frame {
+                            traced,
+                            ..Frame::procedure(closure.function.clone(), start, machine.loops.len())
+                        });
+
+                        function = closure.function.clone();
+                        ip = 0;
+                    } else {
+                        let args: Vec<Value> = machine.stack.drain(start..).collect();
+                        let traced = self.enter_call(&callable, call)?;
+                        let value = callable.call(
+                            self,
+                            &args,
+                            &call.arguments_spans,
+                            call.token.source.clone(),
+                        )?;
+
+                        if traced {
+                            self.call_stack.pop();
+                        } else {
+                            self.check_native_lists(call, &args, &value)?;
+                        }
+                        machine.stack.push(value);
+                    }
+                }
+                Op::Return => {
+                    let value = pop(&mut machine.stack);
+                    let frame = machine.frames.pop().expect("no frame to return from");
+
+                    machine.stack.truncate(frame.stack);
+                    machine.loops.truncate(frame.loops);
+
+                    if frame.procedure {
+                        self.venv.exit_procedure();
+                    }
+                    if frame.traced {
+                        self.call_stack.pop();
+                    }
+
+                    let Some(caller) = machine.frames.last() else {
+                        return Ok(value);
+                    };
+
+                    function = caller.function.clone();
+                    ip = caller.ip;
+                    machine.stack.push(value);
+                }
+                Op::Declare(declaration) => {
+                    let (proc_dec, function) = &chunk.declarations[declaration as usize];
+                    let closure = Rc::new(Closure {
+                        function: function.clone(),
+                        closure: self.venv.capture(),
+                        file: self.get_file_path().into(),
+                    });
+
+                    self.declare(proc_dec, closure);
+                }
+                Op::Import(import) => self.import(&chunk.imports[import as usize])?,
+                Op::Step => self.step(),
+                Op::Check(checkpoint) => {
+                    self.check_budget(Running::Loop(&chunk.checkpoints[checkpoint as usize]))?
+                }
+            }
+        }
+    }
+}
+
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().expect("the compiler keeps the stack balanced")
+}
@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// stops a running program from outside of it.
+///
+/// clones share the same flag, so one can be put in the `Settings` and another
+/// kept by whoever wants to stop the program, even from another thread.
+/// the interpreter checks it at the start of each round of a loop and before each call
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// asks the program to stop. it ends with an "Execution Cancelled" error
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// lets programs run again after one was cancelled, like the next input of the repl
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,142 @@
+use crate::interpreter::debug::Debugger;
+use crate::interpreter::Interpreter;
+use crate::parser::ast::{If, Stmt};
+use crate::parser::walk::{first_token, walk, Node};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// counts which lines ran and which way each IF went, for `--coverage`.
+/// every file is counted on its own, including imported modules.
+/// clones share the same counts
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    files: Rc<RefCell<BTreeMap<String, FileCoverage>>>,
+}
+
+#[derive(Debug, Default)]
+struct FileCoverage {
+    /// how many times the statements on each line ran.
+    /// lines with code that never ran are here as 0
+    lines: BTreeMap<usize, u64>,
+    /// each IF, by where it is in the file
+    branches: BTreeMap<usize, Branches>,
+}
+
+#[derive(Debug)]
+struct Branches {
+    line: usize,
+    /// how many times the condition was true
+    taken: u64,
+    /// how many times it was false, running the ELSE if there is one
+    not_taken: u64,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the counts in the lcov format that editors and `genhtml` read
+    pub fn lcov(&self) -> String {
+        let mut lcov = String::new();
+
+        for (file, coverage) in self.files.borrow().iter() {
+            let _ = writeln!(lcov, "TN:");
+            let _ = writeln!(lcov, "SF:{file}");
+
+            let mut hit = 0;
+            for (block, branches) in coverage.branches.values().enumerate() {
+                let ran = coverage
+                    .lines
+                    .get(&branches.line)
+                    .is_some_and(|count| *count > 0);
+                for (branch, count) in [branches.taken, branches.not_taken].into_iter().enumerate()
+                {
+                    // `-` is for a branch whose IF never ran
+                    let taken = if ran {
+                        count.to_string()
+                    } else {
+                        "-".to_string()
+                    };
+                    let _ = writeln!(lcov, "BRDA:{},{block},{branch},{taken}", branches.line);
+                    hit += usize::from(count > 0);
+                }
+            }
+            let _ = writeln!(lcov, "BRF:{}", coverage.branches.len() * 2);
+            let _ = writeln!(lcov, "BRH:{hit}");
+
+            for (line, count) in &coverage.lines {
+                let _ = writeln!(lcov, "DA:{line},{count}");
+            }
+            let ran = coverage.lines.values().filter(|count| **count > 0).count();
+            let _ = writeln!(lcov, "LF:{}", coverage.lines.len());
+            let _ = writeln!(lcov, "LH:{ran}");
+            let _ = writeln!(lcov, "end_of_record");
+        }
+
+        lcov
+    }
+}
+
+impl Debugger for Coverage {
+    fn reached(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> bool {
+        let mut files = self.files.borrow_mut();
+        let file = files.entry(interpreter.get_file_path()).or_default();
+        *file.lines.entry(first_token(stmt).line_number).or_default() += 1;
+        true
+    }
+
+    fn loaded(&mut self, file: &str, program: &[Stmt]) {
+        let mut files = self.files.borrow_mut();
+        let file = files.entry(file.to_string()).or_default();
+
+        // every line with code on it counts, even inside of PROCEDUREs that are never called
+        for stmt in program {
+            walk(Node::Stmt(stmt), true, &mut |node| {
+                let Node::Stmt(stmt) = node else {
+                    return;
+                };
+                match stmt {
+                    // only braces, or declared before anything runs
+                    Stmt::Block(_) | Stmt::ProcDeclaration(_) => {}
+                    Stmt::If(if_stmt) => {
+                        file.lines.entry(if_stmt.if_token.line_number).or_default();
+                        file.branches
+                            .entry(if_stmt.if_token.span.offset())
+                            .or_insert_with(|| Branches::new(if_stmt));
+                    }
+                    stmt => {
+                        file.lines.entry(first_token(stmt).line_number).or_default();
+                    }
+                }
+            });
+        }
+    }
+
+    fn branched(&mut self, if_stmt: &If, taken: bool, interpreter: &Interpreter) {
+        let mut files = self.files.borrow_mut();
+        let file = files.entry(interpreter.get_file_path()).or_default();
+        let branches = file
+            .branches
+            .entry(if_stmt.if_token.span.offset())
+            .or_insert_with(|| Branches::new(if_stmt));
+
+        if taken {
+            branches.taken += 1;
+        } else {
+            branches.not_taken += 1;
+        }
+    }
+}
+
+impl Branches {
+    fn new(if_stmt: &If) -> Self {
+        Self {
+            line: if_stmt.if_token.line_number,
+            taken: 0,
+            not_taken: 0,
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! a PROCEDURE holds on to the scope it was created in. storing it in that scope, or in a
+//! list in that scope, makes a loop of `Rc`s that never drops on its own, so the scope and
+//! everything in it would stay until aplang exits.
+//!
+//! `collect` finds those loops. it counts how many of the handles to each scope, list and
+//! PROCEDURE come from what it can reach from the scopes it is given. anything with more
+//! handles than that is used from outside, and so is everything it reaches.
+//! the scopes and lists that are left only keep each other alive, so they are emptied.
+//!
+//! holding the scope weakly from a PROCEDURE stored in it would not need this, but a LIST
+//! of PROCEDUREs can be returned from the call that made them, and then nothing else
+//! would keep their scope alive.
+//!
+//! it runs at two times, both in `Env`:
+//! - when a call ends while something still holds its scope, once there are `COLLECT_AFTER`
+//!   such scopes and twice as many as were left after the last collection.
+//!   everything it looks at is reachable from those scopes
+//! - when the `Env` is dropped, for every scope it still has. a clone of the `Env` shares
+//!   its scopes, but its handles count as being from outside, so nothing it uses is emptied
+
+use crate::interpreter::env::Scope;
+use crate::interpreter::procedure::Callable;
+use crate::interpreter::Value;
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::rc::Rc;
+
+/// something that holds handles to other things
+enum Node {
+    Scope(Scope),
+    List(Rc<RefCell<Vec<Value>>>),
+    Procedure(Rc<dyn Callable>),
+}
+
+struct Counted {
+    node: Node,
+    /// how many handles to it come from the things that were found
+    inside: usize,
+    /// None if it could not be looked into, because it is being changed
+    children: Option<Vec<usize>>,
+}
+
+impl Node {
+    fn of(value: &Value) -> Option<Node> {
+        match value {
+            Value::List(list) => Some(Node::List(list.clone())),
+            Value::Function(procedure) | Value::NativeFunction(procedure) => {
+                Some(Node::Procedure(procedure.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> usize {
+        match self {
+            Node::Scope(scope) => Rc::as_ptr(scope) as *const () as usize,
+            Node::List(list) => Rc::as_ptr(list) as *const () as usize,
+            Node::Procedure(procedure) => Rc::as_ptr(procedure) as *const () as usize,
+        }
+    }
+
+    fn handles(&self) -> usize {
+        match self {
+            Node::Scope(scope) => Rc::strong_count(scope),
+            Node::List(list) => Rc::strong_count(list),
+            Node::Procedure(procedure) => Rc::strong_count(procedure),
+        }
+    }
+
+    fn children(&self) -> Option<Vec<Node>> {
+        match self {
+            Node::Scope(scope) => {
+                let context = scope.try_borrow().ok()?;
+                let mut children: Vec<Node> = context.values().filter_map(Node::of).collect();
+                children.extend(context.enclosing().map(Node::Scope));
+                Some(children)
+            }
+            Node::List(list) => Some(
+                list.try_borrow()
+                    .ok()?
+                    .iter()
+                    .filter_map(Node::of)
+                    .collect(),
+            ),
+            Node::Procedure(procedure) => Some(
+                procedure
+                    .closure()
+                    .cloned()
+                    .map(Node::Scope)
+                    .into_iter()
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// empties the scopes, and the lists in them, that nothing but each other can reach
+pub fn collect(scopes: Vec<Scope>) {
+    // the graph holds one handle to everything in it, and `scopes` is where it starts
+    let mut graph: HashMap<usize, Counted> = HashMap::new();
+    let mut found: Vec<usize> = vec![];
+    for scope in scopes {
+        let node = Node::Scope(scope);
+        let id = node.id();
+        if let Entry::Vacant(entry) = graph.entry(id) {
+            entry.insert(Counted {
+                node,
+                inside: 0,
+                children: None,
+            });
+            found.push(id);
+        }
+    }
+
+    while let Some(id) = found.pop() {
+        let Some(children) = graph[&id].node.children() else {
+            continue;
+        };
+        let mut ids = Vec::with_capacity(children.len());
+        for child in children {
+            let child_id = child.id();
+            match graph.get_mut(&child_id) {
+                Some(counted) => counted.inside += 1,
+                None => {
+                    graph.insert(
+                        child_id,
+                        Counted {
+                            node: child,
+                            inside: 1,
+                            children: None,
+                        },
+                    );
+                    found.push(child_id);
+                }
+            }
+            ids.push(child_id);
+        }
+        graph.get_mut(&id).expect("it was found").children = Some(ids);
+    }
+
+    // everything used from outside stays, along with everything it reaches
+    let mut live: Vec<usize> = graph
+        .iter()
+        .filter(|(_, counted)| {
+            counted.children.is_none() || counted.node.handles() > counted.inside + 1
+        })
+        .map(|(id, _)| *id)
+        .collect();
+    let mut kept = HashSet::new();
+    while let Some(id) = live.pop() {
+        if kept.insert(id) {
+            live.extend(graph[&id].children.iter().flatten());
+        }
+    }
+
+    // emptying them drops the handles that kept the loops alive.
+    // the values are dropped at the end, after nothing is borrowed anymore
+    let (mut scopes, mut lists) = (vec![], vec![]);
+    for (id, counted) in &graph {
+        if kept.contains(id) {
+            continue;
+        }
+        match &counted.node {
+            Node::Scope(scope) => scopes.push(mem::take(&mut *scope.borrow_mut())),
+            Node::List(list) => lists.push(mem::take(&mut *list.borrow_mut())),
+            Node::Procedure(_) => {}
+        }
+    }
+    drop(graph);
+    drop((scopes, lists));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aplang::ApLang;
+    use crate::interpreter::env::COLLECT_AFTER;
+    use crate::interpreter::{Engine, Interpreter, Settings};
+    use crate::output::Buffer;
+    use std::rc::Rc;
+
+    fn run(source: &str, engine: Engine) -> (Interpreter, String) {
+        let output = Buffer::new();
+        let mut interpreter = ApLang::new_from_stdin(source)
+            .lex()
+            .unwrap()
+            .parse()
+            .unwrap()
+            .with_settings(Settings {
+                engine,
+                ..Settings::default()
+            })
+            .interpreter()
+            .with_output(output.clone());
+        interpreter.interpret().unwrap();
+        (interpreter, output.contents())
+    }
+
+    /// how many ended calls are still held on to
+    fn held(interpreter: &Interpreter) -> usize {
+        interpreter
+            .venv
+            .captured
+            .iter()
+            .filter(|scope| scope.strong_count() > 0)
+            .count()
+    }
+
+    #[test]
+    fn calls_that_store_a_procedure_are_freed() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let (interpreter, _) = run(
+                "PROCEDURE f(n) {\n\
+                    g <- PROCEDURE () { RETURN n }\n\
+                    helpers <- [g]\n\
+                    RETURN g()\n\
+                }\n\
+                REPEAT 10000 TIMES {\n\
+                    f(1)\n\
+                }\n",
+                engine,
+            );
+
+            let held = held(&interpreter);
+            assert!(held < 2 * COLLECT_AFTER, "{held} calls are still held");
+        }
+    }
+
+    #[test]
+    fn lists_of_procedures_are_freed_unless_kept() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let (interpreter, output) = run(
+                "PROCEDURE steps(n) {\n\
+                    list <- []\n\
+                    REPEAT 3 TIMES {\n\
+                        APPEND(list, PROCEDURE (x) { RETURN x + n })\n\
+                    }\n\
+                    RETURN list\n\
+                }\n\
+                kept <- steps(10)\n\
+                REPEAT 5000 TIMES {\n\
+                    steps(1)\n\
+                }\n\
+                first <- kept[1]\n\
+                DISPLAY(first(1))\n",
+                engine,
+            );
+
+            assert_eq!(output, "11\n");
+            let held = held(&interpreter);
+            assert!(held < 2 * COLLECT_AFTER, "{held} calls are still held");
+        }
+    }
+
+    #[test]
+    fn mutually_recursive_procedures_are_freed_unless_kept() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let (interpreter, output) = run(
+                "PROCEDURE parity() {\n\
+                    isEven <- PROCEDURE (n) {\n\
+                        IF (n == 0) { RETURN TRUE }\n\
+                        RETURN isOdd(n - 1)\n\
+                    }\n\
+                    isOdd <- PROCEDURE (n) {\n\
+                        IF (n == 0) { RETURN FALSE }\n\
+                        RETURN isEven(n - 1)\n\
+                    }\n\
+                    RETURN isEven\n\
+                }\n\
+                even <- parity()\n\
+                REPEAT 5000 TIMES {\n\
+                    check <- parity()\n\
+                    check(3)\n\
+                }\n\
+                DISPLAY(even(10))\n",
+                engine,
+            );
+
+            assert_eq!(output, "TRUE\n");
+            let held = held(&interpreter);
+            assert!(held < 2 * COLLECT_AFTER, "{held} calls are still held");
+        }
+    }
+
+    #[test]
+    fn nested_captures_are_freed() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let (interpreter, output) = run(
+                "PROCEDURE outer(n) {\n\
+                    middle <- PROCEDURE () {\n\
+                        inner <- PROCEDURE () { RETURN n }\n\
+                        RETURN inner()\n\
+                    }\n\
+                    RETURN middle()\n\
+                }\n\
+                total <- 0\n\
+                REPEAT 5000 TIMES {\n\
+                    total <- total + outer(1)\n\
+                }\n\
+                DISPLAY(total)\n",
+                engine,
+            );
+
+            assert_eq!(output, "5000\n");
+            let held = held(&interpreter);
+            assert!(held < 2 * COLLECT_AFTER, "{held} calls are still held");
+        }
+    }
+
+    #[test]
+    fn procedures_still_in_use_keep_their_scope() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let (_, output) = run(
+                "PROCEDURE counter() {\n\
+                    c <- 0\n\
+                    next <- PROCEDURE () {\n\
+                        c <- c + 1\n\
+                        RETURN c\n\
+                    }\n\
+                    RETURN next\n\
+                }\n\
+                counters <- []\n\
+                REPEAT 1000 TIMES {\n\
+                    APPEND(counters, counter())\n\
+                }\n\
+                FOR EACH next IN counters {\n\
+                    next()\n\
+                }\n\
+                first <- counters[1]\n\
+                last <- counters[1000]\n\
+                DISPLAY(first() + last())\n",
+                engine,
+            );
+            assert_eq!(output, "4\n");
+        }
+    }
+
+    #[test]
+    fn the_top_level_is_freed_with_the_interpreter() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let (interpreter, _) = run("square <- PROCEDURE (n) { RETURN n * n }\n", engine);
+            let top_level = Rc::downgrade(&interpreter.venv.top_level());
+
+            drop(interpreter);
+            assert_eq!(top_level.strong_count(), 0);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::trace::CallFrame;
+use crate::interpreter::Interpreter;
+use crate::interpreter::Value;
+use crate::lexer::token::Token;
+use crate::parser::ast::{If, Set, Stmt, Variable};
+use crate::parser::walk::first_token;
+use miette::NamedSource;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// watches the program run one statement at a time,
+/// for `aplang dap`, `--trace`, `--trace-json` and `--coverage`.
+/// only the tree walking engine tells it anything
+pub trait Debugger {
+    /// called before each statement runs.
+    /// can wait as long as it wants, the program stays suspended until it returns.
+    /// returning false stops the program
+    fn reached(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> bool;
+
+    /// a variable was assigned. `old` is None if it was just created
+    fn assigned(
+        &mut self,
+        variable: &Variable,
+        old: Option<&Value>,
+        new: &Value,
+        interpreter: &Interpreter,
+    ) {
+    }
+
+    /// an item of a list was replaced, like `list[index] <- new`
+    fn set(
+        &mut self,
+        set: &Set,
+        index: &Value,
+        old: &Value,
+        new: &Value,
+        interpreter: &Interpreter,
+    ) {
+    }
+
+    /// a PROCEDURE written in aplang was called, and its arguments are in place
+    fn entered(
+        &mut self,
+        procedure: &str,
+        params: &[Variable],
+        arguments: &[Value],
+        interpreter: &Interpreter,
+    ) {
+    }
+
+    /// a PROCEDURE written in aplang finished
+    fn returned(&mut self, procedure: &str, value: &Value, interpreter: &Interpreter) {}
+
+    /// a loop is about to run its body. `count` is 1 the first time
+    fn iteration(&mut self, token: &Token, count: usize, interpreter: &Interpreter) {}
+
+    /// a file is about to run, the one that was started or a module it imported
+    fn loaded(&mut self, file: &str, program: &[Stmt]) {}
+
+    /// an IF checked its condition. `taken` is true if it runs the first branch, not the ELSE
+    fn branched(&mut self, if_stmt: &If, taken: bool, interpreter: &Interpreter) {}
+}
+
+/// one call that is running, as a debugger shows it
+pub struct Frame {
+    /// the PROCEDURE that is running, or None for the top level
+    pub procedure: Option<String>,
+    /// the file the code is in
+    pub file: String,
+    /// where it is in that file
+    pub token: Token,
+}
+
+impl Interpreter {
+    /// lets `debugger` suspend the program between statements
+    pub fn with_debugger(mut self, debugger: impl Debugger + 'static) -> Self {
+        self.debugger = Some(Rc::new(RefCell::new(debugger)));
+        self
+    }
+
+    /// if a debugger is watching. saves work that only it would need
+    pub(super) fn debugging(&self) -> bool {
+        self.debugger.is_some()
+    }
+
+    /// tells the debugger about something that happened, if there is one
+    pub(super) fn notify(&self, event: impl FnOnce(&mut dyn Debugger, &Interpreter)) {
+        if let Some(debugger) = &self.debugger {
+            event(&mut *debugger.borrow_mut(), self);
+        }
+    }
+
+    /// hands the statement to the debugger before it runs
+    pub(super) fn reach(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        let Some(debugger) = self.debugger.clone() else {
+            return Ok(());
+        };
+
+        if debugger.borrow_mut().reached(stmt, self) {
+            return Ok(());
+        }
+
+        let token = first_token(stmt);
+        Err(RuntimeError {
+            named_source: NamedSource::new(self.get_file_path(), token.source.clone()),
+            span: token.span,
+            message: "Execution Stopped".to_string(),
+            help: "The debugger stopped the program".to_string(),
+            label: "The program was stopped here".to_string(),
+        })
+    }
+
+    /// how many calls to PROCEDUREs written in aplang are running
+    pub fn depth(&self) -> usize {
+        self.call_stack
+            .iter()
+            .filter(|frame| matches!(frame, CallFrame::Call { .. }))
+            .count()
+    }
+
+    /// every call that is running, innermost first.
+    /// `at` is where the innermost one is right now
+    pub fn frames(&self, at: &Token) -> Vec<Frame> {
+        let mut file = self.get_root_file_path();
+        let mut procedure = None;
+
+        let mut frames = vec![];
+        for frame in &self.call_stack {
+            // imports run in their own interpreter, so they are never below a call here
+            let CallFrame::Call {
+                procedure: callee,
+                call,
+            } = frame
+            else {
+                continue;
+            };
+
+            // the caller is waiting where it made the call
+            frames.push(Frame {
+                procedure: procedure.replace(callee.name().to_string()),
+                file: file.clone(),
+                token: call.token.clone(),
+            });
+
+            if let Some(callee_file) = callee.file() {
+                file = callee_file.to_string();
+            }
+        }
+
+        frames.push(Frame {
+            procedure,
+            file,
+            token: at.clone(),
+        });
+
+        frames.reverse();
+        frames
+    }
+
+    /// the variables made inside of a frame from `frames`
+    pub fn locals(&self, frame: usize) -> Vec<(String, Value)> {
+        self.venv
+            .frame(frame)
+            .map(|scope| scope.borrow().variables())
+            .unwrap_or_default()
+    }
+
+    /// the variables a frame can see from where its PROCEDURE was written
+    pub fn enclosing(&self, frame: usize) -> Vec<(String, Value)> {
+        let mut variables: Vec<(String, Value)> = vec![];
+
+        let mut scope = self
+            .venv
+            .frame(frame)
+            .and_then(|scope| scope.borrow().enclosing());
+        while let Some(context) = scope {
+            for (name, value) in context.borrow().variables() {
+                // an inner variable hides an outer one with the same name
+                if !variables.iter().any(|(seen, _)| *seen == name) {
+                    variables.push((name, value));
+                }
+            }
+            scope = context.borrow().enclosing();
+        }
+
+        variables
+    }
+}
+
+/// a copy of a list that does not change when the list does,
+/// so a debugger can show what a variable was before it was assigned
+pub(super) fn snapshot(value: Value) -> Value {
+    match value {
+        Value::List(list) => Value::List(Rc::new(RefCell::new(list.borrow().clone()))),
+        value => value,
+    }
+}
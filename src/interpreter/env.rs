@@ -1,14 +1,26 @@
+use crate::interpreter::cycles;
 use crate::interpreter::errors::RuntimeError;
 use crate::interpreter::procedure::{Callable, FunctionMap};
 use crate::interpreter::Value;
 use crate::lexer::token::Token;
 use crate::parser::ast::Variable;
+use crate::parser::symbol::Symbol;
 use miette::NamedSource;
-use std::collections::HashMap;
-use std::rc::Rc;
+use std::cell::RefCell;
+use std::mem;
+use std::rc::{Rc, Weak};
 use std::sync::Arc;
 
-#[derive(Clone)]
+/// how many ended calls can still be held on to before looking for the ones
+/// that only keep each other alive. see `cycles` for when that happens
+pub(super) const COLLECT_AFTER: usize = 256;
+
+/// a shared handle to a scope.
+/// procedures hold on to the scope they were created in
+pub type Scope = Rc<RefCell<Context>>;
+
+// the default scope is the top level of the program
+#[derive(Clone, Default)]
 pub struct Env {
     /// private functions
     pub functions: FunctionMap,
@@ -16,75 +28,189 @@ pub struct Env {
     /// public functions
     pub exports: FunctionMap,
 
-    venv: Vec<Context>,
+    /// the scope that is currently running
+    scope: Scope,
+
+    /// the scopes of procedures that are waiting on a call to finish
+    callers: Vec<Scope>,
+
+    /// the scopes of calls that ended while something still held on to them,
+    /// usually a PROCEDURE made in the call
+    pub(super) captured: Vec<Weak<RefCell<Context>>>,
+
+    /// how many captured scopes there can be before the next collection
+    collect_at: usize,
 }
 
 impl Env {
-    /// used for creating function (or the base env)
-    pub fn initialize_empty_scope(&mut self) {
-        self.venv.push(Context::default())
+    /// enter a procedure call.
+    /// the new scope can see every variable from where the procedure was created
+    pub fn enter_procedure(&mut self, name: Rc<str>, closure: Scope) {
+        let scope = Rc::new(RefCell::new(Context {
+            variables: Vec::new(),
+            enclosing: Some(closure),
+            procedure: Some(name),
+        }));
+
+        let caller = std::mem::replace(&mut self.scope, scope);
+        self.callers.push(caller);
     }
 
-    /// creates a new block scope.
-    /// used for something like a For Loop, or an If Stmt
-    pub fn create_nested_layer(&mut self) {
-        let enclosing = self.activate().clone();
-        self.venv.push(enclosing)
+    /// leave the procedure call and go back to the scope of the caller
+    pub fn exit_procedure(&mut self) {
+        let caller = self
+            .callers
+            .pop()
+            .expect("attempted to exit a procedure but there was no caller");
+        let scope = mem::replace(&mut self.scope, caller);
+
+        if Rc::strong_count(&scope) > 1 {
+            self.captured.push(Rc::downgrade(&scope));
+            if self.captured.len() >= self.collect_at.max(COLLECT_AFTER) {
+                self.collect();
+            }
+        }
     }
 
-    /// replace the previous layer with the current layer.
-    pub fn flatten_nested_layer(&mut self) {
-        let context = self.scrape();
+    /// frees the captured scopes that are only kept alive by a PROCEDURE stored in them
+    fn collect(&mut self) {
+        cycles::collect(self.captured.iter().filter_map(Weak::upgrade).collect());
+        self.captured.retain(|scope| scope.strong_count() > 0);
+        self.collect_at = self.captured.len() * 2;
+    }
 
-        *self.activate() = context;
+    /// the scope of a call that is running.
+    /// 0 is the current scope, 1 is the caller waiting on it and so on
+    pub fn frame(&self, depth: usize) -> Option<Scope> {
+        match depth {
+            0 => Some(self.scope.clone()),
+            depth => self.callers.iter().rev().nth(depth - 1).cloned(),
+        }
     }
 
-    /// pops off the current layer of the venv
-    pub fn scrape(&mut self) -> Context {
-        self.venv
-            .pop()
-            .expect("attempted to remove context but failed")
+    /// the scope of the top level, under every call
+    pub fn top_level(&self) -> Scope {
+        self.callers.first().unwrap_or(&self.scope).clone()
+    }
+
+    /// leaves every procedure call at once and goes back to the top level.
+    /// an error skips `exit_procedure`, so this cleans up after one
+    pub fn unwind(&mut self) {
+        while !self.callers.is_empty() {
+            self.exit_procedure();
+        }
     }
 
-    /// gets mutable ref to the current layer
-    fn activate(&mut self) -> &mut Context {
-        let len = self.venv.len();
-        &mut self.venv[len - 1]
+    /// gets a handle to the current scope.
+    /// used to capture the scope for a procedure
+    pub fn capture(&self) -> Scope {
+        self.scope.clone()
     }
 
-    /// creates a variable with some value
+    /// creates a variable with some value in the current scope
     pub fn define(&mut self, variable: Arc<Variable>, value: Value) {
-        // add the variable into the context
-        self.activate()
-            .variables
-            .insert(variable.ident.clone(), (value, variable));
+        let mut context = self.scope.borrow_mut();
+
+        // redefining a variable in the same scope replaces it
+        match context.slot_mut(variable.symbol) {
+            Some(slot) => *slot = (variable.symbol, value, variable),
+            None => context.variables.push((variable.symbol, value, variable)),
+        }
+    }
+
+    /// assigns a value to a variable.
+    ///
+    /// `outer` is decided by the resolver for each assignment. if it is set the variable
+    /// from where the procedure was written is changed, otherwise the variable is created
+    /// in the current scope, so every call gets its own variable even when one outside
+    /// has the same name
+    pub fn assign(&mut self, variable: Arc<Variable>, value: Value, outer: bool) {
+        let enclosing = match outer {
+            true => self.scope.borrow().enclosing.clone(),
+            false => None,
+        };
+
+        match enclosing {
+            Some(enclosing) if Context::assign(&enclosing, variable.symbol, &value) => {}
+            _ => self.define(variable, value),
+        }
+    }
+
+    /// look up the value of a variable without building an error
+    pub fn get(&self, symbol: Symbol) -> Option<Value> {
+        Context::get(&self.scope, symbol)
+    }
+
+    /// the value of the variable an assignment would change, without reading it.
+    /// None when the assignment would create a new variable
+    pub fn target(&self, symbol: Symbol, outer: bool) -> Option<Value> {
+        let context = self.scope.borrow();
+        match outer {
+            true => Context::get(context.enclosing.as_ref()?, symbol),
+            false => context
+                .variables
+                .iter()
+                .find(|(s, ..)| *s == symbol)
+                .map(|(_, value, _)| value.clone()),
+        }
     }
 
     /// look up a variable based on the symbol
     pub fn lookup_name(
         &mut self,
-        var: &str,
+        symbol: Symbol,
         tok: Token,
         file_path: String,
-    ) -> Result<&(Value, Arc<Variable>), RuntimeError> {
-        self.activate().variables.get(var).ok_or(RuntimeError {
+    ) -> Result<Value, RuntimeError> {
+        if let Some(value) = self.get(symbol) {
+            return Ok(value);
+        }
+
+        let var = symbol.name();
+
+        // the variable might exist in a procedure that is waiting on this one.
+        // procedures only see variables from where they were written, not where they are called
+        let help = match self.find_in_callers(symbol) {
+            Some(Some(procedure)) => format!(
+                "`{var}` belongs to the PROCEDURE `{procedure}` that called this one. \
+                A PROCEDURE can only see variables from where it was written, \
+                pass `{var}` in as an argument instead"
+            ),
+            Some(None) => format!(
+                "`{var}` belongs to the file that called this PROCEDURE, \
+                not the file the PROCEDURE was written in. pass `{var}` in as an argument instead"
+            ),
+            None => format!("Make sure to create the variable `{var}` before you use it"),
+        };
+
+        Err(RuntimeError {
             named_source: NamedSource::new(file_path, tok.source.clone()),
             span: tok.span,
             message: "Invalid Variable".to_string(),
-            help: format!("Make sure to create the variable `{var}` before you use it"),
+            help,
             label: "Could not find variable".to_string(),
         })
     }
 
+    /// finds the scope of a waiting caller that has the variable.
+    /// returns the name of the procedure that owns it (None if it is the top level)
+    fn find_in_callers(&self, symbol: Symbol) -> Option<Option<Rc<str>>> {
+        self.callers
+            .iter()
+            .rev()
+            .find_map(|caller| Context::owner(caller, symbol))
+    }
+
     /// looks up the variable by comparing the entire variable object
-    pub fn lookup_var(
-        &mut self,
-        var: &Variable,
-        file_path: String,
-    ) -> Result<&Value, RuntimeError> {
-        Ok(&self
-            .lookup_name(var.ident.as_str(), var.token.clone(), file_path)?
-            .0)
+    pub fn lookup_var(&mut self, var: &Variable, file_path: String) -> Result<Value, RuntimeError> {
+        self.lookup_name(var.symbol, var.token.clone(), file_path)
+    }
+
+    /// look up a function without building an error
+    pub fn get_function(&self, function_name: &str) -> Option<Rc<dyn Callable>> {
+        self.functions
+            .get(function_name)
+            .map(|(function, _)| function.clone())
     }
 
     pub fn lookup_function(
@@ -96,7 +222,7 @@ impl Env {
         let (a, _b) = self
             .functions
             .get(&function_name)
-            .ok_or(RuntimeError {
+            .ok_or_else(|| RuntimeError {
                 named_source: NamedSource::new(file_path, tok.source.clone()),
                 span: tok.span,
                 message: "Invalid PROCEDURE".to_string(),
@@ -109,35 +235,109 @@ impl Env {
         Ok(a)
     }
 
-    /// removes a variable
+    /// removes a variable from the current scope
     pub fn remove(&mut self, variable: Arc<Variable>) -> Option<(Value, Arc<Variable>)> {
-        self.activate().variables.remove(&variable.ident)
+        let mut context = self.scope.borrow_mut();
+        let index = context
+            .variables
+            .iter()
+            .position(|(symbol, ..)| *symbol == variable.symbol)?;
+
+        let (_, value, variable) = context.variables.swap_remove(index);
+        Some((value, variable))
     }
 
     pub fn contains(&mut self, variable: Arc<Variable>) -> bool {
-        self.activate().variables.contains_key(&variable.ident)
+        self.scope.borrow_mut().slot_mut(variable.symbol).is_some()
     }
 }
 
-impl Default for Env {
-    fn default() -> Self {
-        let mut env = Self {
-            functions: Default::default(),
-            exports: Default::default(),
-            venv: vec![],
-        };
-        // push the base context layer into env so we don't panic
-        env.initialize_empty_scope();
-        env
+impl Drop for Env {
+    // the scopes are done with, but the ones holding a PROCEDURE made in them
+    // would keep each other alive. scopes a clone of this `Env` still uses are left alone
+    fn drop(&mut self) {
+        self.functions.clear();
+        self.exports.clear();
+
+        let mut scopes: Vec<Scope> = self.captured.iter().filter_map(Weak::upgrade).collect();
+        scopes.push(mem::take(&mut self.scope));
+        scopes.append(&mut self.callers);
+        cycles::collect(scopes);
     }
 }
 
+// scopes only hold a handful of variables,
+// so scanning a vec of symbols beats hashing the name
 #[derive(Default, Clone, Debug)]
 pub struct Context {
-    variables: HashMap<String, (Value, Arc<Variable>)>,
-    //              |^^^^^   |^^^       ^^^^^^^^|> Source code pointer
-    //              |        |> Value of symbol
+    variables: Vec<(Symbol, Value, Arc<Variable>)>,
+    //              |^^^^^  |^^^   ^^^^^^^^|> Source code pointer
+    //              |       |> Value of symbol
     //              |> Name of symbol
+    /// the scope this one was created in
+    enclosing: Option<Scope>,
+
+    /// the procedure this scope belongs to. None for the top level
+    procedure: Option<Rc<str>>,
+}
+
+impl Context {
+    /// every variable in this scope, in the order they were made
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        self.variables
+            .iter()
+            .map(|(_, value, variable)| (variable.ident.clone(), value.clone()))
+            .collect()
+    }
+
+    /// the scope this one was created in
+    pub fn enclosing(&self) -> Option<Scope> {
+        self.enclosing.clone()
+    }
+
+    /// the values of the variables in this scope
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.variables.iter().map(|(_, value, _)| value)
+    }
+
+    fn slot_mut(&mut self, symbol: Symbol) -> Option<&mut (Symbol, Value, Arc<Variable>)> {
+        self.variables.iter_mut().find(|(s, ..)| *s == symbol)
+    }
+
+    fn get(scope: &Scope, symbol: Symbol) -> Option<Value> {
+        let context = scope.borrow();
+
+        if let Some((_, value, _)) = context.variables.iter().find(|(s, ..)| *s == symbol) {
+            return Some(value.clone());
+        }
+
+        Context::get(context.enclosing.as_ref()?, symbol)
+    }
+
+    // returns false if no scope in the chain has the variable
+    fn assign(scope: &Scope, symbol: Symbol, value: &Value) -> bool {
+        let mut context = scope.borrow_mut();
+
+        if let Some(slot) = context.slot_mut(symbol) {
+            slot.1 = value.clone();
+            return true;
+        }
+
+        match &context.enclosing {
+            Some(enclosing) => Context::assign(enclosing, symbol, value),
+            None => false,
+        }
+    }
+
+    fn owner(scope: &Scope, symbol: Symbol) -> Option<Option<Rc<str>>> {
+        let context = scope.borrow();
+
+        if context.variables.iter().any(|(s, ..)| *s == symbol) {
+            return Some(context.procedure.clone());
+        }
+
+        Context::owner(context.enclosing.as_ref()?, symbol)
+    }
 }
 
 #[derive(Copy, Clone, Default)]
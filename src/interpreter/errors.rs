@@ -52,3 +52,53 @@ pub struct RuntimeError {
     pub help: String,
     pub label: String,
 }
+
+/// a runtime error along with the calls that led to it
+#[derive(Error, Debug)]
+#[error("{error}")]
+pub struct TracedError {
+    pub error: RuntimeError,
+    /// innermost call first
+    pub trace: Vec<TraceFrame>,
+}
+
+impl Diagnostic for TracedError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.error.code()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.error.help()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.error.named_source)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.error.labels()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        if self.trace.is_empty() {
+            return None;
+        }
+
+        Some(Box::new(
+            self.trace.iter().map(|frame| frame as &dyn Diagnostic),
+        ))
+    }
+}
+
+/// a single call in the trace of a runtime error
+#[derive(Debug, Error, Diagnostic)]
+#[error("{message}")]
+#[diagnostic(severity(Advice))]
+pub struct TraceFrame {
+    #[source_code]
+    pub named_source: NamedSource<Arc<str>>,
+    #[label("{label}")]
+    pub span: SourceSpan,
+    pub message: String,
+    pub label: String,
+}
@@ -1,20 +1,39 @@
 use crate::aplang::ApLang;
+use crate::interpreter::budget::{Meter, Running};
+use crate::interpreter::debug::{snapshot, Debugger};
 use crate::interpreter::env::{Env, LoopControl};
-use crate::interpreter::errors::{Reports, RuntimeError};
+use crate::interpreter::errors::RuntimeError;
 use crate::interpreter::procedure::FunctionMap;
-use crate::interpreter::procedure::Procedure;
+use crate::interpreter::procedure::{Callable, Procedure};
+use crate::interpreter::profiler::Profiler;
+use crate::interpreter::settings::{Engine, Settings};
+use crate::interpreter::trace::CallFrame;
 use crate::interpreter::value::Value;
+use crate::interpreter::world::World;
+use crate::input::{Input, Stdin};
 use crate::lexer::token::LiteralValue;
-use crate::parser::ast::{Ast, Binary, Expr, Literal, ProcCall, Stmt, Unary};
+use crate::output::{Output, Stdout};
+use crate::parser::ast::{
+    Access, Assignment, Ast, Binary, Expr, ForEach, Import, Literal, ProcCall, ProcDeclaration,
+    RepeatTimes, Set, Stmt, Unary, Variable,
+};
 use crate::standard_library::Modules;
 use miette::NamedSource;
-use std::cell::RefCell;
+use std::cell::{RefCell, RefMut};
 use std::mem;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
 
+/// when less than this much stack is left, a call gets a new segment
+#[cfg(feature = "native")]
+const STACK_RED_ZONE: usize = 256 * 1024;
+
+/// the size of each new stack segment
+#[cfg(feature = "native")]
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
 // we're using this weird error type because miette! slows down the execution
 // of recursive code by a HUGE amount
 // we profiled and couldn't figure out how to solve the issue
@@ -26,7 +45,7 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct Interpreter {
     pub(super) venv: Env,
-    ast: Ast,
+    pub(super) ast: Ast,
 
     file_path: Option<PathBuf>,
 
@@ -34,6 +53,35 @@ pub struct Interpreter {
     loop_stack: Vec<LoopControl>,
 
     modules: Modules,
+
+    settings: Settings,
+
+    /// the calls and imports that are running, innermost last
+    pub(super) call_stack: Vec<CallFrame>,
+
+    pub(super) meter: Meter,
+
+    /// where everything the program displays goes.
+    /// imported modules share it with the file that imported them
+    output: Rc<RefCell<dyn Output>>,
+    /// where INPUT reads from. shared with imported modules as well
+    input: Rc<RefCell<dyn Input>>,
+
+    /// the standard library call that is running
+    native_call: Option<Arc<ProcCall>>,
+
+    pub(super) world: World,
+
+    /// suspends the program between statements. shared with imported modules
+    pub(super) debugger: Option<Rc<RefCell<dyn Debugger>>>,
+
+    /// measures each call for `--profile`. shared with imported modules
+    pub(super) profiler: Option<Profiler>,
+
+    /// where the stack was at the first call, to tell how much of it the calls use.
+    /// shared with imported modules
+    #[cfg(not(feature = "native"))]
+    stack_base: Option<usize>,
 }
 
 impl Interpreter {
@@ -46,6 +94,17 @@ impl Interpreter {
 
             loop_stack: vec![], // *
             modules: Modules::init(),
+            settings: Settings::default(),
+            call_stack: vec![],
+            meter: Meter::default(),
+            output: Rc::new(RefCell::new(Stdout)),
+            input: Rc::new(RefCell::new(Stdin)),
+            native_call: None,
+            world: World::default(),
+            debugger: None,
+            profiler: None,
+            #[cfg(not(feature = "native"))]
+            stack_base: None,
         };
         //* we start in no loops
         //* if the stack is empty then we are not in a loop anymore
@@ -59,11 +118,75 @@ impl Interpreter {
         interpreter
     }
 
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.meter = Meter::start(&settings.budget);
+        self.world = World::new(&settings);
+        self.settings = settings;
+        self
+    }
+
+    /// sends everything the program displays to `output` instead of stdout
+    pub fn with_output(mut self, output: impl Output + 'static) -> Self {
+        self.output = Rc::new(RefCell::new(output));
+        self
+    }
+
+    /// where everything the program displays goes
+    pub fn output(&self) -> RefMut<'_, dyn Output> {
+        self.output.borrow_mut()
+    }
+
+    /// reads INPUT from `input` instead of stdin
+    pub fn with_input(mut self, input: impl Input + 'static) -> Self {
+        self.input = Rc::new(RefCell::new(input));
+        self
+    }
+
+    /// where INPUT reads from
+    pub fn input(&self) -> RefMut<'_, dyn Input> {
+        self.input.borrow_mut()
+    }
+
+    /// the standard library call that is running.
+    /// for errors that are about the whole call instead of one argument
+    pub fn native_call(&self) -> Option<&ProcCall> {
+        self.native_call.as_deref()
+    }
+
+    pub fn get_settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// every PROCEDURE that can be called by name
+    pub fn functions(&self) -> &FunctionMap {
+        &self.venv.functions
+    }
+
+    /// calls a PROCEDURE that takes no arguments, after the program ran.
+    /// `aplang test` runs each `TEST_` PROCEDURE this way
+    pub fn call_procedure(&mut self, name: &str) -> Result<Value, RuntimeError> {
+        let (procedure, _) = self
+            .venv
+            .functions
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| panic!("there is no PROCEDURE {name}"));
+
+        let source = self.ast.source.clone();
+        procedure.call(self, &[], &[], source)
+    }
+
+    /// every variable made at the top level, in the order they were made
+    pub fn top_level_variables(&self) -> Vec<(String, Value)> {
+        self.venv.top_level().borrow().variables()
+    }
+
     pub fn get_return_value(&self) -> &Option<Value> {
         &self.return_value
     }
 
-    pub fn get_file_path(&self) -> String {
+    /// the file this interpreter was started with
+    pub fn get_root_file_path(&self) -> String {
         if let Some(file_path) = &self.file_path {
             file_path.to_string_lossy().into_owned()
         } else {
@@ -71,66 +194,112 @@ impl Interpreter {
         }
     }
 
-    pub fn interpret_module(mut self) -> Result<FunctionMap, RuntimeError> {
-        // temporarily take the program to avoid borrow error
-        let program = mem::take(&mut self.ast.program);
+    /// where the code that runs next comes from, like a file loaded into the repl.
+    /// its errors and the PROCEDUREs it declares are reported as being from there
+    pub fn set_file_path(&mut self, file_path: PathBuf) {
+        self.file_path = Some(file_path);
+    }
 
-        for stmt in &program {
-            match stmt {
-                Stmt::Expr(expr) => {
-                    self.expr(expr.deref())?;
-                }
-                stmt => self.stmt(stmt)?,
-            }
+    pub fn interpret_module(&mut self) -> Result<FunctionMap, RuntimeError> {
+        if self.settings.engine == Engine::Bytecode {
+            self.run_program(false)?;
+            return Ok(mem::take(&mut self.venv.exports));
         }
 
+        // temporarily take the program to avoid borrow error
+        let program = mem::take(&mut self.ast.program);
+        let file = self.get_root_file_path();
+        self.notify(|debugger, _| debugger.loaded(&file, &program));
+        self.walk(&program, false)?;
         self.ast.program = program; // restore program
-        Ok(self.venv.exports)
+
+        Ok(mem::take(&mut self.venv.exports))
     }
 
     pub fn interpret(&mut self) -> Result<(), RuntimeError> {
-        // temporarily take the program to avoid borrow error
-        let program = mem::take(&mut self.ast.program);
-
-        for stmt in &program {
-            match stmt {
-                Stmt::Expr(expr) => {
-                    self.expr(expr.deref())?;
-                }
-                stmt => self.stmt(stmt)?,
-            }
+        if self.settings.engine == Engine::Bytecode {
+            return self.run_program(false).map(|_| ());
         }
 
+        // temporarily take the program to avoid borrow error
+        let program = mem::take(&mut self.ast.program);
+        let file = self.get_root_file_path();
+        self.notify(|debugger, _| debugger.loaded(&file, &program));
+        self.walk(&program, false)?;
         self.ast.program = program; // restore program
+
         Ok(())
     }
 
     pub fn interpret_debug(&mut self) -> Result<Vec<Value>, RuntimeError> {
-        let mut values = vec![];
+        if self.settings.engine == Engine::Bytecode {
+            return self.run_program(true);
+        }
 
         let program = mem::take(&mut self.ast.program); // Temporarily take the program
+        let values = self.walk(&program, true)?;
+        self.ast.program = program; // Restore the program
+
+        Ok(values)
+    }
+
+    /// runs more code in this interpreter, like the next input of a repl.
+    /// every variable and PROCEDURE from before is still there.
+    /// always walks the tree, the bytecode engine would start over each time.
+    /// returns the value of each statement that is only an expression, like `interpret_debug`
+    pub fn interpret_more(&mut self, ast: Ast) -> Result<Vec<Value>, RuntimeError> {
+        self.walk(&ast.program, true).inspect_err(|_| {
+            // an error skips the end of every call it happened in,
+            // so go back to the top level for the next input
+            self.venv.unwind();
+            self.loop_stack.clear();
+            self.return_value = None;
+        })
+    }
 
-        for stmt in &program {
+    /// walks the tree of a whole program.
+    /// returns the value of each statement that is only an expression if `collect` is set
+    fn walk(&mut self, program: &[Stmt], collect: bool) -> Result<Vec<Value>, RuntimeError> {
+        let mut values = vec![];
+
+        self.hoist(program);
+        for stmt in program {
             match stmt {
                 Stmt::Expr(expr) => {
+                    self.reach(stmt)?;
                     let value = self.expr(expr.deref())?;
-                    values.push(value);
+                    if collect {
+                        values.push(value);
+                    }
                 }
+                // already declared by `hoist`
+                Stmt::ProcDeclaration(_) => {}
                 stmt => self.stmt(stmt)?,
             }
         }
 
-        self.ast.program = program; // Restore the program
         Ok(values)
     }
 
     // a stmt by definition returns nothing
     pub(super) fn stmt(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        self.step();
+
+        // a block is only braces, the debugger stops at what is inside
+        if !matches!(stmt, Stmt::Block(_)) {
+            self.reach(stmt)?;
+        }
+
         match stmt {
             Stmt::Expr(expr) => self.expr(expr.as_ref()).map(|_| ()),
             Stmt::If(if_stmt) => {
                 // evaluate the conditional
-                if Self::is_truthy(&self.expr(&if_stmt.condition)?) {
+                let taken = Self::is_truthy(&self.expr(&if_stmt.condition)?);
+                self.notify(|debugger, interpreter| {
+                    debugger.branched(if_stmt, taken, interpreter)
+                });
+
+                if taken {
                     self.stmt(&if_stmt.then_branch)
                 } else if let Some(else_branch) = &if_stmt.else_branch {
                     self.stmt(else_branch)
@@ -139,51 +308,59 @@ impl Interpreter {
                 }
             }
             Stmt::RepeatTimes(repeat_times) => {
-                match self.expr(&repeat_times.count)? {
-                    Value::Number(count) => {
-                        // we are now in a loop so keep track of the loop_stack
-                        self.loop_stack.push(LoopControl::default());
-
-                        // floor the value into an int so we can iterate
-                        for _ in 1..=count as usize {
-                            self.stmt(&repeat_times.body)?;
-
-                            // if the CONTINUE stmt was called handle it
-                            if self.loop_stack.last().unwrap().should_continue {
-                                self.loop_stack.last_mut().unwrap().should_continue = false;
-                                continue;
-                            }
-                            
-                            // if the BREAK stmt was called handle it
-                            if self.loop_stack.last().unwrap().should_break {
-                                self.loop_stack.last_mut().unwrap().should_break = false;
-                                break;
-                            }
-                        }
-
-                        // exit the loop
-                        assert!(self.loop_stack.pop().is_some());
-
-                        Ok(())
-                    } // format!("cannot do count for value {value:?}")
-                    value => Err(RuntimeError {
-                        named_source: NamedSource::new(
-                            self.get_file_path(),
-                            repeat_times.count_token.source.clone(),
-                        ),
-                        span: repeat_times.count_token.span,
-                        message: "Invalid Value for nTIMES".to_string(),
-                        help: format!("Make sure `{value:?}` is a NUMBER"),
-                        label: "Invalid Value here".to_string(),
-                    }),
+                let count = self.expr(&repeat_times.count)?;
+                let count = self.repeat_count(repeat_times, count)?;
+
+                // we are now in a loop so keep track of the loop_stack
+                self.loop_stack.push(LoopControl::default());
+
+                for iteration in 1..=count {
+                    self.check_budget(Running::Loop(&repeat_times.repeat_token))?;
+                    self.notify(|debugger, interpreter| {
+                        debugger.iteration(&repeat_times.repeat_token, iteration, interpreter)
+                    });
+                    self.stmt(&repeat_times.body)?;
+
+                    // RETURN inside of the loop body
+                    if self.return_value.is_some() {
+                        break;
+                    }
+
+                    // if the CONTINUE stmt was called handle it
+                    if self.loop_stack.last().unwrap().should_continue {
+                        self.loop_stack.last_mut().unwrap().should_continue = false;
+                        continue;
+                    }
+                    
+                    // if the BREAK stmt was called handle it
+                    if self.loop_stack.last().unwrap().should_break {
+                        self.loop_stack.last_mut().unwrap().should_break = false;
+                        break;
+                    }
                 }
+
+                // exit the loop
+                assert!(self.loop_stack.pop().is_some());
+
+                Ok(())
             }
             Stmt::RepeatUntil(repeat_until) => {
                 // enter a loop
                 self.loop_stack.push(LoopControl::default());
 
+                let mut iteration = 0;
                 while !Self::is_truthy(&self.expr(&repeat_until.condition)?) {
+                    self.check_budget(Running::Loop(&repeat_until.repeat_token))?;
+                    iteration += 1;
+                    self.notify(|debugger, interpreter| {
+                        debugger.iteration(&repeat_until.repeat_token, iteration, interpreter)
+                    });
                     self.stmt(&repeat_until.body)?;
+
+                    // RETURN inside of the loop body
+                    if self.return_value.is_some() {
+                        break;
+                    }
                     
                     // if the BREAK stmt was called handle it
                     if self.loop_stack.last().unwrap().should_break {
@@ -205,27 +382,8 @@ impl Interpreter {
                 Ok(())
             }
             Stmt::ForEach(for_each) => {
-                let values = match self.expr(&for_each.list)? {
-                    Value::List(list) => list,
-                    Value::String(string) => Rc::new(RefCell::new(
-                        string
-                            .chars()
-                            .map(|ch| Value::String(ch.to_string()))
-                            .collect::<Vec<Value>>(),
-                    )),
-                    value => Err(RuntimeError {
-                        named_source: NamedSource::new(
-                            self.get_file_path(),
-                            for_each.list_token.source.clone(),
-                        ),
-                        span: for_each.list_token.span,
-                        message: "Invalid Iterator".to_string(),
-                        help: format!(
-                            "Cannot iterate over {value:?}. This should be a LIST or a STRING"
-                        ),
-                        label: "Invalid Iterator Here".to_string(),
-                    })?,
-                };
+                let values = self.expr(&for_each.list)?;
+                let values = self.iterable(for_each, values)?;
 
                 let element = Arc::new(for_each.item.clone());
 
@@ -237,14 +395,28 @@ impl Interpreter {
 
                 let len = values.borrow().len();
                 for i in 0..len {
+                    self.check_budget(Running::Loop(&for_each.for_token))?;
+                    self.notify(|debugger, interpreter| {
+                        debugger.iteration(&for_each.for_token, i + 1, interpreter)
+                    });
+
                     // inserting temporary value into env
-                    self.venv
-                        .define(element.clone(), values.borrow()[i].clone());
+                    let item = values.borrow()[i].clone();
+                    self.venv.define(element.clone(), item.clone());
+                    self.notify(|debugger, interpreter| {
+                        let previous = i.checked_sub(1).map(|i| values.borrow()[i].clone());
+                        debugger.assigned(&element, previous.as_ref(), &item, interpreter)
+                    });
                     // execute body
 
                     
                     self.stmt(&for_each.body)?;
 
+                    // RETURN inside of the loop body
+                    if self.return_value.is_some() {
+                        break;
+                    }
+
                     // if the BREAK stmt was called handle it
                     if self.loop_stack.last().unwrap().should_break {
                         self.loop_stack.last_mut().unwrap().should_break = false;
@@ -271,26 +443,7 @@ impl Interpreter {
                 Ok(())
             }
             Stmt::ProcDeclaration(proc_dec) => {
-                // create a new non-native aplang function
-
-                let procedure = Rc::new(Procedure {
-                    name: proc_dec.name.to_string(),
-                    params: proc_dec.params.clone(),
-                    body: proc_dec.body.clone(),
-                });
-
-                self.venv.functions.insert(
-                    procedure.name.clone(),
-                    (procedure.clone(), Some(proc_dec.clone())),
-                );
-
-                if proc_dec.exported {
-                    self.venv.exports.insert(
-                        procedure.name.clone(),
-                        (procedure.clone(), Some(proc_dec.clone())),
-                    );
-                }
-
+                self.declare_procedure(proc_dec);
                 Ok(())
             }
             Stmt::Return(ret_val) => {
@@ -322,8 +475,8 @@ impl Interpreter {
                 Ok(())
             }
             Stmt::Block(block) => {
-                self.venv.create_nested_layer();
-
+                // a block does not get its own scope.
+                // variables created inside of it are still there after it ends
                 for stmt in block.statements.iter() {
                     if self
                         .loop_stack
@@ -334,157 +487,289 @@ impl Interpreter {
                         break;
                     }
 
+                    // a RETURN stmt ends the procedure right away
+                    if self.return_value.is_some() {
+                        break;
+                    }
+
                     self.stmt(stmt)?
                 }
 
-                self.venv.flatten_nested_layer();
-
                 Ok(())
             }
-            Stmt::Import(import) => {
-                // get a ref to the module name to be imported/activated
-                let Some(LiteralValue::String(module_name)) = import.module_name.literal.as_ref()
-                else {
-                    unreachable!()
-                };
+            Stmt::Import(import) => self.import(import),
+        }
+    }
 
-                let mut module = if let Some(injector) = self.modules.lookup(module_name) {
-                    // if the module is a native standard library module, get it
-                    injector()
-                } else {
-                    // the module must be a user module or invalid
-
-                    let Some(mut current_module_path) = self.file_path.clone() else {
-                        return Err(RuntimeError {
-                            named_source: NamedSource::new(
-                                self.get_file_path(),
-                                import.module_name.source.clone(),
-                            ),
-                            span: import.module_name.span,
-                            message: "user modules cannot be called when evaluating from stdin"
-                                .to_string(),
-                            label: "cannot use module".to_string(),
-                            help: "put your code in a file to use user modules".to_string(),
-                        });
-                    };
-
-                    // strip the filename from the path
-                    current_module_path.pop();
-                    // let maybe_path = self
-                    // let maybe_path = current_module_path.join(module_name);
-                    let maybe_path = current_module_path.join(module_name);
-
-                    // check if the file has a dot ap extension.
-                    // if it does then continue
-                    // if not, then try to import an invalid std
-                    if maybe_path
-                        .extension()
-                        .map(|os_str| os_str.to_string_lossy().eq_ignore_ascii_case("ap"))
-                        .is_some_and(|res| res)
-                    {
-                    } else {
-                        Err(RuntimeError {
-                            named_source: NamedSource::new(self.get_file_path(), import.module_name.source.clone()),
-                            span: import.module_name.span,
-                            label: "invalid std module".to_string(),
-                            message: format!("std module not found {}", module_name),
-                            help: "if you meant to import a user module please enter the path to the .ap file in question".to_string()
-                            // maybe do a fuzzy module find?
-                        })?;
-                    }
+    /// runs an IMPORT and adds the PROCEDUREs it brings in
+    pub(super) fn import(&mut self, import: &Arc<Import>) -> Result<(), RuntimeError> {
+        // get a ref to the module name to be imported/activated
+        let Some(LiteralValue::String(module_name)) = import.module_name.literal.as_ref()
+        else {
+            unreachable!()
+        };
+
+        let mut module = if let Some(injector) = self.modules.lookup(module_name) {
+            // if the module is a native standard library module, get it
+            injector()
+        } else {
+            // the module must be a user module or invalid
 
-                    // we need to make sure the file is actually there!
-                    if !maybe_path.is_file() {
-                        Err(RuntimeError {
-                            named_source: NamedSource::new(self.get_file_path(), import.module_name.source.clone()),
-                            span: import.module_name.span,
-                            message: format!("file {} does not exist, or is a directory. could not import user module", module_name),
-                            label: "invalid file path".to_string(),
-                            help: "specify a valid path to '.ap' file to import an std module".to_string(),
-                        })?;
+            let Some(mut current_module_path) = self.file_path.clone() else {
+                return Err(RuntimeError {
+                    named_source: NamedSource::new(
+                        self.get_file_path(),
+                        import.module_name.source.clone(),
+                    ),
+                    span: import.module_name.span,
+                    message: "user modules cannot be called when evaluating from stdin"
+                        .to_string(),
+                    label: "cannot use module".to_string(),
+                    help: "put your code in a file to use user modules".to_string(),
+                });
+            };
+
+            // strip the filename from the path
+            current_module_path.pop();
+            // let maybe_path = self
+            // let maybe_path = current_module_path.join(module_name);
+            let maybe_path = current_module_path.join(module_name);
+
+            // check if the file has a dot ap extension.
+            // if it does then continue
+            // if not, then try to import an invalid std
+            if maybe_path
+                .extension()
+                .map(|os_str| os_str.to_string_lossy().eq_ignore_ascii_case("ap"))
+                .is_some_and(|res| res)
+            {
+            } else {
+                Err(RuntimeError {
+                    named_source: NamedSource::new(self.get_file_path(), import.module_name.source.clone()),
+                    span: import.module_name.span,
+                    label: "invalid std module".to_string(),
+                    message: format!("std module not found {}", module_name),
+                    help: "if you meant to import a user module please enter the path to the .ap file in question".to_string()
+                    // maybe do a fuzzy module find?
+                })?;
+            }
+
+            // we need to make sure the file is actually there!
+            if !maybe_path.is_file() {
+                Err(RuntimeError {
+                    named_source: NamedSource::new(self.get_file_path(), import.module_name.source.clone()),
+                    span: import.module_name.span,
+                    message: format!("file {} does not exist, or is a directory. could not import user module", module_name),
+                    label: "invalid file path".to_string(),
+                    help: "specify a valid path to '.ap' file to import an std module".to_string(),
+                })?;
+            }
+
+            // TODO: BUG: Only can accept an absolute path. work on relative paths
+            // // attempt to read module
+            // let (Ok(module_source_code), Some(file_name)) = (fs::read_to_string(maybe_path), maybe_path.file_name()) else {
+            //     Err(RuntimeError {
+            //         named_source: NamedSource::new(self.get_file_path(), import.module_name.source.clone()),
+            //         span: import.module_name.span,
+            //         message: format!("user module {} exists but could not read source", module_name),
+            //         label: "failed to read".to_string(),
+            //         help: "specify a valid path to '.ap' file to import an std module".to_string(),
+            //     })?
+            // };
+
+            // package source code
+            // let module_source_code: Arc<str> = module_source_code.into();
+
+            // convert filename into regular string
+            // let file_name = file_name.to_string_lossy().into_owned();
+
+            // init the module interpreter
+            let aplang =
+                ApLang::new_from_file(maybe_path.to_path_buf()).map_err(|_err| {
+                    RuntimeError {
+                        named_source: NamedSource::new(
+                            self.get_file_path(),
+                            import.module_name.source.clone(),
+                        ),
+                        span: import.module_name.span,
+                        message: format!(
+                            "user module {} exists but could not read source",
+                            module_name
+                        ),
+                        label: "failed to read module".to_string(),
+                        help: "specify a valid path to '.ap' file to import an std module"
+                            .to_string(),
                     }
+                })?;
+
+            // lex and parse. the module runs with the same settings as this file
+            let parsed = aplang
+                .with_settings(self.settings.clone())
+                .lex()
+                .and_then(|lexed| lexed.parse())
+                .map_err(|reports| RuntimeError {
+                    named_source: NamedSource::new(
+                        self.get_file_path(),
+                        import.module_name.source.clone(),
+                    ),
+                    span: import.module_name.span,
+                    message: format!("user module {module_name} has errors"),
+                    label: "could not import module".to_string(),
+                    help: reports
+                        .iter()
+                        .map(|report| report.to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                })?;
+
+            // execute the module, get the exports
+            let mut interpreter = parsed.interpreter();
+            interpreter.meter = self.meter.clone();
+            interpreter.output = self.output.clone();
+            interpreter.input = self.input.clone();
+            interpreter.world = self.world.clone();
+            interpreter.debugger = self.debugger.clone();
+            interpreter.profiler = self.profiler.clone();
+            #[cfg(not(feature = "native"))]
+            {
+                interpreter.stack_base = self.stack_base;
+            }
+            self.call_stack.push(CallFrame::Import {
+                import: import.clone(),
+                module: interpreter.get_root_file_path().into(),
+            });
+
+            // the calls inside of the module continue the trace
+            let exports = interpreter.interpret_module().inspect_err(|_| {
+                self.call_stack.append(&mut interpreter.call_stack);
+            })?;
+
+            self.call_stack.pop();
+            exports
+        };
 
-                    // TODO: BUG: Only can accept an absolute path. work on relative paths
-                    // // attempt to read module
-                    // let (Ok(module_source_code), Some(file_name)) = (fs::read_to_string(maybe_path), maybe_path.file_name()) else {
-                    //     Err(RuntimeError {
-                    //         named_source: NamedSource::new(self.get_file_path(), import.module_name.source.clone()),
-                    //         span: import.module_name.span,
-                    //         message: format!("user module {} exists but could not read source", module_name),
-                    //         label: "failed to read".to_string(),
-                    //         help: "specify a valid path to '.ap' file to import an std module".to_string(),
-                    //     })?
-                    // };
-
-                    // package source code
-                    // let module_source_code: Arc<str> = module_source_code.into();
-
-                    // convert filename into regular string
-                    // let file_name = file_name.to_string_lossy().into_owned();
-
-                    // init the module interpreter
-                    let aplang =
-                        ApLang::new_from_file(maybe_path.to_path_buf()).map_err(|_err| {
-                            RuntimeError {
-                                named_source: NamedSource::new(
-                                    self.get_file_path(),
-                                    import.module_name.source.clone(),
-                                ),
-                                span: import.module_name.span,
-                                message: format!(
-                                    "user module {} exists but could not read source",
-                                    module_name
-                                ),
-                                label: "failed to read module".to_string(),
-                                help: "specify a valid path to '.ap' file to import an std module"
-                                    .to_string(),
-                            }
-                        })?;
-
-                    // lex
-                    let lexed = aplang.lex().map_err(Reports::from).unwrap();
-                    // parseRun
-                    let parsed = lexed.parse().map_err(Reports::from).unwrap();
-                    // execute the module, get the exports
-                    parsed.execute_as_module()?
+        // before actually adding the function, we might have to trim the module
+        // if we're using IMPORT "x" FROM MOD "y"
+        if let Some(functions) = import.only_functions.clone() {
+            let mut trimmed_module = FunctionMap::new();
+            // generated functions need to be removed
+            // we trim the hashmap down to only specify the specified keys
+            for function in &functions {
+                let Some(LiteralValue::String(function_name)) = function.literal.as_ref()
+                else {
+                    unreachable!()
                 };
 
-                // before actually adding the function, we might have to trim the module
-                // if we're using IMPORT "x" FROM MOD "y"
-                if let Some(functions) = import.only_functions.clone() {
-                    let mut trimmed_module = FunctionMap::new();
-                    // generated functions need to be removed
-                    // we trim the hashmap down to only specify the specified keys
-                    for function in &functions {
-                        let Some(LiteralValue::String(function_name)) = function.literal.as_ref()
-                        else {
-                            unreachable!()
-                        };
-
-                        let Some(function) = module.remove(function_name) else {
-                            return Err(RuntimeError {
-                                named_source: NamedSource::new("", function.source.clone()),
-                                span: function.span,
-                                message: "Invalid Function".to_string(),
-                                help: format!("Function {function_name} does not exist in module {module_name}"),
-                                label: "Does not exist".to_string(),
-                            });
-                        };
-
-                        trimmed_module.insert(function_name.clone(), function);
-                    }
+                let Some(function) = module.remove(function_name) else {
+                    return Err(RuntimeError {
+                        named_source: NamedSource::new("", function.source.clone()),
+                        span: function.span,
+                        message: "Invalid Function".to_string(),
+                        help: format!("Function {function_name} does not exist in module {module_name}"),
+                        label: "Does not exist".to_string(),
+                    });
+                };
 
-                    module = trimmed_module;
-                }
+                trimmed_module.insert(function_name.clone(), function);
+            }
 
-                // finally, add it
-                self.venv.functions.extend(module);
+            module = trimmed_module;
+        }
 
-                Ok(())
+        // finally, add it
+        self.venv.functions.extend(module);
+
+        Ok(())
+    }
+
+    /// declares the PROCEDUREs at the top level of a file before anything runs,
+    /// so they can be called from above their declaration
+    fn hoist(&mut self, program: &[Stmt]) {
+        for stmt in program {
+            if let Stmt::ProcDeclaration(proc_dec) = stmt {
+                self.declare_procedure(proc_dec);
             }
         }
     }
 
+    /// creates a new non-native aplang function
+    fn declare_procedure(&mut self, proc_dec: &Arc<ProcDeclaration>) {
+        let procedure = Rc::new(Procedure {
+            name: proc_dec.name.as_str().into(),
+            params: proc_dec.params.clone(),
+            body: proc_dec.body.clone(),
+            closure: self.venv.capture(),
+            file: self.get_file_path().into(),
+        });
+
+        self.declare(proc_dec, procedure);
+    }
+
+    /// makes a declared PROCEDURE callable, and exports it if needed
+    pub(super) fn declare(&mut self, proc_dec: &Arc<ProcDeclaration>, procedure: Rc<dyn Callable>) {
+        if proc_dec.exported {
+            self.venv.exports.insert(
+                proc_dec.name.clone(),
+                (procedure.clone(), Some(proc_dec.clone())),
+            );
+        }
+
+        self.venv
+            .functions
+            .insert(proc_dec.name.clone(), (procedure, Some(proc_dec.clone())));
+    }
+
+    /// the number of times a REPEAT n TIMES loop runs
+    pub(super) fn repeat_count(
+        &self,
+        repeat_times: &RepeatTimes,
+        count: Value,
+    ) -> Result<usize, RuntimeError> {
+        match count {
+            // floor the value into an int so we can iterate
+            Value::Number(count) => Ok(count as usize),
+            // format!("cannot do count for value {value:?}")
+            value => Err(RuntimeError {
+                named_source: NamedSource::new(
+                    self.get_file_path(),
+                    repeat_times.count_token.source.clone(),
+                ),
+                span: repeat_times.count_token.span,
+                message: "Invalid Value for nTIMES".to_string(),
+                help: format!("Make sure `{value:?}` is a NUMBER"),
+                label: "Invalid Value here".to_string(),
+            }),
+        }
+    }
+
+    /// the items a FOR EACH loop goes over.
+    /// a STRING is turned into a LIST of characters
+    pub(super) fn iterable(
+        &self,
+        for_each: &ForEach,
+        values: Value,
+    ) -> Result<Rc<RefCell<Vec<Value>>>, RuntimeError> {
+        match values {
+            Value::List(list) => Ok(list),
+            Value::String(string) => Ok(Rc::new(RefCell::new(
+                string
+                    .chars()
+                    .map(|ch| Value::String(ch.to_string()))
+                    .collect::<Vec<Value>>(),
+            ))),
+            value => Err(RuntimeError {
+                named_source: NamedSource::new(
+                    self.get_file_path(),
+                    for_each.list_token.source.clone(),
+                ),
+                span: for_each.list_token.span,
+                message: "Invalid Iterator".to_string(),
+                help: format!("Cannot iterate over {value:?}. This should be a LIST or a STRING"),
+                label: "Invalid Iterator Here".to_string(),
+            }),
+        }
+    }
+
     pub fn interpret_expr_temp(&mut self) -> Result<Vec<Value>, RuntimeError> {
         let expressions: Vec<Expr> = self
             .ast
@@ -509,39 +794,38 @@ impl Interpreter {
             Literal(lit) => Ok(Self::literal(&lit.value)),
             Binary(binary) => self.binary(binary.as_ref()),
             Unary(unary) => self.unary(unary.as_ref()),
-            ProcCall(proc) => self.call(proc.as_ref()),
+            ProcCall(proc) => self.call(proc),
             Access(access) => self.access(access.as_ref()),
             List(list) => self.list(list.as_ref()),
-            Variable(v) => self
-                .venv
-                .lookup_name(
-                    v.ident.clone().as_str(),
-                    v.token.clone(),
-                    self.get_file_path(),
-                )
-                .cloned()
-                .map(|(value, _)| value),
+            Variable(v) => self.variable(v),
             Assign(assignment) => {
                 // execute the expression
                 let result = self.expr(&assignment.value)?;
-                match &result {
-                    Value::List(list) => {
-                        match self
-                            .venv
-                            .lookup_var(&assignment.target.clone(), self.get_file_path())
-                        {
-                            Ok(Value::List(target_list)) => {
-                                target_list.swap(list);
-                            }
-                            _ => self.venv.define(assignment.target.clone(), result.clone()),
-                        }
-                    }
-                    _ => self.venv.define(assignment.target.clone(), result.clone()),
-                }
+
+                // a list is changed in place, so keep a copy of how it was
+                let old = self
+                    .debugging()
+                    .then(|| {
+                        self.venv
+                            .target(assignment.target.symbol, assignment.is_outer())
+                            .map(snapshot)
+                    })
+                    .flatten();
+                self.assign(assignment, &result);
+                self.notify(|debugger, interpreter| {
+                    debugger.assigned(&assignment.target, old.as_ref(), &result, interpreter)
+                });
 
                 Ok(result)
             }
             Set(set) => self.set(set.as_ref()),
+            Lambda(lambda) => Ok(Value::Function(Rc::new(Procedure {
+                name: "anonymous".into(),
+                params: lambda.params.clone(),
+                body: lambda.body.clone(),
+                closure: self.venv.capture(),
+                file: self.get_file_path().into(),
+            }))),
             Logical(log) => {
                 let left = self.expr(&log.left)?;
                 let short_circuit = match log.operator {
@@ -559,7 +843,41 @@ impl Interpreter {
         value
     }
 
-    fn call(&mut self, proc: &ProcCall) -> Result<Value, RuntimeError> {
+    pub(super) fn variable(&mut self, v: &Variable) -> Result<Value, RuntimeError> {
+        if let Some(value) = self.venv.get(v.symbol) {
+            return Ok(value);
+        }
+
+        // the name of a PROCEDURE evaluates to the procedure itself
+        if let Some((function, declaration)) = self.venv.functions.get(&v.ident) {
+            return Ok(match declaration {
+                Some(_) => Value::Function(function.clone()),
+                None => Value::NativeFunction(function.clone()),
+            });
+        }
+
+        self.venv
+            .lookup_name(v.symbol, v.token.clone(), self.get_file_path())
+    }
+
+    pub(super) fn assign(&mut self, assignment: &Assignment, result: &Value) {
+        let outer = assignment.is_outer();
+        match result {
+            Value::List(list) => match self.venv.target(assignment.target.symbol, outer) {
+                Some(Value::List(target_list)) => {
+                    target_list.swap(list);
+                }
+                _ => self
+                    .venv
+                    .assign(assignment.target.clone(), result.clone(), outer),
+            },
+            _ => self
+                .venv
+                .assign(assignment.target.clone(), result.clone(), outer),
+        }
+    }
+
+    fn call(&mut self, proc: &Arc<ProcCall>) -> Result<Value, RuntimeError> {
         // todo: look into callee expr
 
         let mut argument_evaluations = Vec::new();
@@ -568,30 +886,169 @@ impl Interpreter {
             argument_evaluations.push(self.expr(arg)?)
         }
 
-        let callable = self.venv.lookup_function(
-            proc.ident.clone(),
-            proc.token.clone(),
-            self.get_file_path(),
-        )?;
+        let callable = self.callee(proc, argument_evaluations.len())?;
+        let traced = self.enter_call(&callable, proc)?;
+
+        let call = |interpreter: &mut Self| {
+            callable.call(
+                interpreter,
+                argument_evaluations.as_ref(),
+                proc.arguments_spans.as_ref(),
+                proc.token.source.clone(),
+            )
+        };
+
+        // a PROCEDURE written in aplang measures itself, since the standard library calls them too
+        let profiler = self.profiler.clone().filter(|_| !traced);
+        if let Some(profiler) = &profiler {
+            profiler.enter(callable.name());
+        }
+
+        // each call recurses in rust, so grow the stack as needed and let
+        // `max_call_depth` decide how deep the program can go
+        #[cfg(feature = "native")]
+        let value = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || call(self));
+        #[cfg(not(feature = "native"))]
+        let value = call(self);
+
+        if let Some(profiler) = &profiler {
+            profiler.exit();
+        }
+        let value = value?;
+
+        // an error leaves the frame on the call stack for the trace
+        if traced {
+            self.call_stack.pop();
+        } else {
+            self.check_native_lists(proc, &argument_evaluations, &value)?;
+        }
+
+        Ok(value)
+    }
+
+    /// puts a call on the call stack if it is to a PROCEDURE written in aplang.
+    /// returns if it did, or an error if the calls are nested too deep
+    pub(super) fn enter_call(
+        &mut self,
+        callable: &Rc<dyn Callable>,
+        proc: &Arc<ProcCall>,
+    ) -> Result<bool, RuntimeError> {
+        if callable.file().is_none() {
+            self.native_call = Some(proc.clone());
+            return Ok(false);
+        }
+
+        self.check_budget(Running::Call(proc, callable.name()))?;
+
+        if let Some(max_call_depth) = self.settings.max_call_depth {
+            if self.call_stack.len() >= max_call_depth {
+                return Err(self.too_deep(callable, proc, max_call_depth));
+            }
+        }
+
+        // deep calls would overflow the stack before reaching `max_call_depth`
+        #[cfg(not(feature = "native"))]
+        if self.out_of_stack() {
+            return Err(self.too_deep(callable, proc, self.call_stack.len()));
+        }
+
+        self.call_stack.push(CallFrame::Call {
+            procedure: callable.clone(),
+            call: proc.clone(),
+        });
+        Ok(true)
+    }
+
+    /// if the calls have used up the part of the stack they can have
+    #[cfg(not(feature = "native"))]
+    fn out_of_stack(&mut self) -> bool {
+        let here = 0u8;
+        let here = std::hint::black_box(&here) as *const u8 as usize;
+        let base = *self.stack_base.get_or_insert(here);
+        base.abs_diff(here) > self.settings.stack_limit
+    }
+
+    fn too_deep(
+        &self,
+        callable: &Rc<dyn Callable>,
+        proc: &ProcCall,
+        max_call_depth: usize,
+    ) -> RuntimeError {
+        let name = callable.name();
+
+        // almost always a PROCEDURE that calls itself forever
+        let recursive = self.call_stack.iter().any(|frame| {
+            matches!(frame, CallFrame::Call { procedure, .. } if procedure.name() == name)
+        });
+
+        let help = if recursive {
+            format!(
+                "`{name}` keeps calling itself and never stops. \
+                Make sure it has a base case: an IF that RETURNs without calling `{name}` again"
+            )
+        } else {
+            "Too many PROCEDUREs are waiting on each other. \
+            Try using a loop instead of nesting calls"
+                .to_string()
+        };
+
+        RuntimeError {
+            named_source: NamedSource::new(self.get_file_path(), proc.token.source.clone()),
+            span: proc.token.span,
+            message: "Too Much Recursion".to_string(),
+            help,
+            label: format!("This call went past the limit of {max_call_depth} nested calls"),
+        }
+    }
+
+    /// finds the PROCEDURE a call refers to and checks it gets the right number of arguments
+    pub(super) fn callee(
+        &self,
+        proc: &ProcCall,
+        arguments: usize,
+    ) -> Result<Rc<dyn Callable>, RuntimeError> {
+        // a variable holding a procedure shadows a PROCEDURE with the same name,
+        // a variable holding anything else does not
+        let callable = match self.venv.get(proc.symbol) {
+            Some(Value::Function(callable) | Value::NativeFunction(callable)) => callable,
+            Some(value) => match self.venv.get_function(&proc.ident) {
+                Some(callable) => callable,
+                None => {
+                    return Err(RuntimeError {
+                        named_source: NamedSource::new(self.get_file_path(), proc.token.source.clone()),
+                        span: proc.token.span,
+                        message: "Invalid PROCEDURE".to_string(),
+                        help: format!(
+                            "`{}` is a variable holding `{value}`. Only a PROCEDURE can be called",
+                            proc.ident
+                        ),
+                        label: "This is not a PROCEDURE".to_string(),
+                    })
+                }
+            },
+            None => match self.venv.get_function(&proc.ident) {
+                Some(callable) => callable,
+                None => self.venv.lookup_function(
+                    proc.ident.clone(),
+                    proc.token.clone(),
+                    self.get_file_path(),
+                )?,
+            },
+        };
 
-        if callable.arity() as usize != argument_evaluations.len() {
+        if callable.arity() as usize != arguments {
             return Err(
                 RuntimeError {
                     named_source: NamedSource::new(self.get_file_path(), proc.token.source.clone()),
                     span: (proc.parens.0.span.offset() + proc.parens.0.span.len() .. proc.parens.1.span.offset()).into(),
                     message: "Incorrect Number Of Args".to_string(),
                     help: "Make sure the you are passing in the correct number of arguments to the PROCEDURE".to_string(),
-                    label: format!("There should be {} arg{}; Found {}", callable.arity(), if callable.arity() == 1 {""} else {"s"}, argument_evaluations.len())
+                    label: format!("There should be {} arg{}; Found {}", callable.arity(), if callable.arity() == 1 {""} else {"s"}, arguments)
                 }
             ); // todo make this error message better -- use source proc pointer
         }
 
-        callable.call(
-            self,
-            argument_evaluations.as_ref(),
-            proc.arguments_spans.as_ref(),
-            proc.token.source.clone(),
-        )
+        Ok(callable)
     }
 
     // help: a string can be thought of a list of chars
@@ -603,10 +1060,19 @@ impl Interpreter {
             .map(|x| Value::List(RefCell::new(x).into()))
     }
 
-    fn access(&mut self, access: &crate::parser::ast::Access) -> Result<Value, RuntimeError> {
+    fn access(&mut self, access: &Access) -> Result<Value, RuntimeError> {
         let list = self.expr(&access.list)?;
         let idx = self.expr(&access.key)?;
 
+        self.access_value(access, list, idx)
+    }
+
+    pub(super) fn access_value(
+        &self,
+        access: &Access,
+        list: Value,
+        idx: Value,
+    ) -> Result<Value, RuntimeError> {
         let Value::Number(idx) = idx else {
             return Err(RuntimeError {
                 named_source: NamedSource::new(
@@ -674,11 +1140,32 @@ impl Interpreter {
         target
     }
 
-    fn set(&mut self, set: &crate::parser::ast::Set) -> Result<Value, RuntimeError> {
+    fn set(&mut self, set: &Set) -> Result<Value, RuntimeError> {
         let list = self.expr(&set.list)?;
         let idx = self.expr(&set.idx)?;
         let value = self.expr(&set.value)?;
 
+        let old = match (&list, &idx) {
+            (Value::List(items), Value::Number(index)) if self.debugging() && *index >= 1.0 => {
+                items.borrow().get((index - 1.0) as usize).cloned()
+            }
+            _ => None,
+        };
+
+        let value = self.set_value(set, list, idx.clone(), value)?;
+        if let Some(old) = old {
+            self.notify(|debugger, interpreter| debugger.set(set, &idx, &old, &value, interpreter));
+        }
+        Ok(value)
+    }
+
+    pub(super) fn set_value(
+        &self,
+        set: &Set,
+        list: Value,
+        idx: Value,
+        value: Value,
+    ) -> Result<Value, RuntimeError> {
         let Value::List(ref list) = list else {
             return Err(RuntimeError {
                 named_source: NamedSource::new(self.get_file_path(), set.list_token.source.clone()),
@@ -726,6 +1213,15 @@ impl Interpreter {
         let lhs = self.expr(&node.left)?;
         let rhs = self.expr(&node.right)?;
 
+        self.binary_value(node, lhs, rhs)
+    }
+
+    pub(super) fn binary_value(
+        &self,
+        node: &Binary,
+        lhs: Value,
+        rhs: Value,
+    ) -> Result<Value, RuntimeError> {
         use crate::interpreter::value::Value::*;
         use crate::parser::ast::BinaryOp::*;
         match (&lhs, &node.operator, &rhs) {
@@ -781,7 +1277,10 @@ impl Interpreter {
                     .cloned()
                     .chain(b.borrow().iter().cloned())
                     .collect();
-                Ok(List(RefCell::new(new_list).into()))
+
+                let list = List(RefCell::new(new_list).into());
+                self.check_list_len(&list, &node.token)?;
+                Ok(list)
             }
             _ => Err(RuntimeError {
                 named_source: NamedSource::new(self.get_file_path(), node.token.source.clone()),
@@ -793,7 +1292,7 @@ impl Interpreter {
         }
     }
 
-    fn literal(lit: &Literal) -> Value {
+    pub(super) fn literal(lit: &Literal) -> Value {
         match lit {
             Literal::Number(num) => Value::Number(*num),
             Literal::String(string) => Value::String(string.clone()),
@@ -805,6 +1304,10 @@ impl Interpreter {
     fn unary(&mut self, node: &Unary) -> Result<Value, RuntimeError> {
         let value = self.expr(&node.right)?;
 
+        self.unary_value(node, value)
+    }
+
+    pub(super) fn unary_value(&self, node: &Unary, value: Value) -> Result<Value, RuntimeError> {
         use crate::interpreter::value::Value::*;
         use crate::parser::ast::UnaryOp::*;
         match (&node.operator, value) {
@@ -817,14 +1320,14 @@ impl Interpreter {
                 help: format!("Invalid application of unary op {op} to String type"),
                 label: "Cannot do operand here".to_string(),
             }),
-            (op, NativeFunction()) => Err(RuntimeError {
+            (op, NativeFunction(_)) => Err(RuntimeError {
                 named_source: NamedSource::new(self.get_file_path(), node.token.source.clone()),
                 span: node.token.span,
                 message: "Invalid Unary Op".to_string(),
                 help: format!("Invalid application of unary op {op} to NativeFunction type"),
                 label: "Cannot do operand here".to_string(),
             }),
-            (op, Function()) => Err(RuntimeError {
+            (op, Function(_)) => Err(RuntimeError {
                 named_source: NamedSource::new(self.get_file_path(), node.token.source.clone()),
                 span: node.token.span,
                 message: "Invalid Unary Op".to_string(),
@@ -862,17 +1365,19 @@ impl Interpreter {
         }
     }
 
-    fn equals(lhs: &Value, rhs: &Value) -> bool {
+    pub(super) fn equals(lhs: &Value, rhs: &Value) -> bool {
         match (lhs, rhs) {
             (Value::Number(n1), Value::Number(n2)) => (n1 - n2).abs() < f64::EPSILON,
             (Value::String(s1), Value::String(s2)) => s1 == s2,
             (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
             (Value::Null, Value::Null) => true,
+            (Value::Function(f1), Value::Function(f2)) => Rc::ptr_eq(f1, f2),
+            (Value::NativeFunction(f1), Value::NativeFunction(f2)) => Rc::ptr_eq(f1, f2),
             (_, _) => false,
         }
     }
 
-    fn is_truthy(value: &Value) -> bool {
+    pub(super) fn is_truthy(value: &Value) -> bool {
         match value {
             Value::Bool(b) => *b,
             Value::Number(n) if *n == 0.0 => false,
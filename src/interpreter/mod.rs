@@ -1,11 +1,26 @@
+mod budget;
+mod bytecode;
+mod cancel;
+pub mod coverage;
+mod cycles;
+pub mod debug;
 mod env;
 pub mod errors;
 mod interpreter;
 mod procedure;
+pub mod profiler;
+pub mod recorder;
+mod settings;
+mod trace;
+mod tracer;
 mod value;
+mod world;
 
 // used by ApLang
+pub use cancel::CancelToken;
 pub use interpreter::Interpreter;
+pub use settings::{Budget, Clock, Engine, Settings, DEFAULT_MAX_CALL_DEPTH};
+pub use tracer::Tracer;
 
 // used by modules
 pub use procedure::FunctionMap;
@@ -1,8 +1,11 @@
+use crate::interpreter::bytecode::Closure;
+use crate::interpreter::env::Scope;
 use crate::interpreter::errors::RuntimeError;
 use crate::interpreter::{Interpreter, Value};
 use crate::parser::ast::{ProcDeclaration, Stmt, Variable};
 use miette::SourceSpan;
 use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -22,12 +25,45 @@ pub trait Callable {
         source: Arc<str>,
     ) -> Result<Value, RuntimeError>;
     fn arity(&self) -> u8;
+    fn name(&self) -> &str;
+
+    /// the bytecode version of this PROCEDURE, if it has one.
+    /// the vm runs those itself instead of going through `call`
+    fn compiled(&self) -> Option<&Closure> {
+        None
+    }
+
+    /// the file the PROCEDURE was written in. None for the standard library
+    fn file(&self) -> Option<&Arc<str>> {
+        None
+    }
+
+    /// the names of the parameters of a standard library function, for editors to show
+    fn params(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// the scope the PROCEDURE was created in. None for the standard library
+    fn closure(&self) -> Option<&Scope> {
+        None
+    }
+}
+
+impl Debug for dyn Callable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PROCEDURE {}({} args)", self.name(), self.arity())
+    }
 }
 
 pub struct Procedure {
-    pub name: String,
+    pub name: Rc<str>,
     pub params: Vec<Variable>,
     pub body: Stmt,
+    /// the scope the procedure was created in.
+    /// storing the procedure in it makes a loop that `cycles` breaks
+    pub closure: Scope,
+    /// the file the procedure was written in
+    pub file: Arc<str>,
 }
 
 impl Callable for Procedure {
@@ -38,12 +74,16 @@ impl Callable for Procedure {
         args_tokens: &[SourceSpan],
         source: Arc<str>,
     ) -> Result<Value, RuntimeError> {
+        if let Some(profiler) = &interpreter.profiler {
+            profiler.enter(&self.name);
+        }
+
         // save the return value
-        let cached_return_value = interpreter.return_value.clone();
+        let cached_return_value = interpreter.return_value.take();
 
-        // todo: consider allowing variables to be taken into context
-        // ignore the global env
-        interpreter.venv.initialize_empty_scope();
+        // the procedure gets a fresh scope that can see
+        // every variable from where it was created
+        interpreter.venv.enter_procedure(self.name.clone(), self.closure.clone());
 
         // copy in the arguments
         // assigns them to their appropriate name parameter
@@ -51,37 +91,59 @@ impl Callable for Procedure {
             .iter()
             .zip(args.iter().cloned())
             .for_each(|(param, arg)| interpreter.venv.define(Arc::new(param.clone()), arg));
+        interpreter.notify(|debugger, interpreter| {
+            debugger.entered(&self.name, &self.params, args, interpreter)
+        });
 
         // execute the function
-        interpreter.stmt(&self.body)?;
+        let result = interpreter.stmt(&self.body);
+        if let Some(profiler) = &interpreter.profiler {
+            profiler.exit();
+        }
+        result?;
 
         let return_value = interpreter.return_value.clone();
-        // todo implement backtrace
         interpreter.return_value = cached_return_value;
 
         // restore the previous env
-        interpreter.venv.scrape();
+        interpreter.venv.exit_procedure();
 
-        match return_value {
-            None => Ok(Value::Null),
-            Some(value) => Ok(value),
-        }
+        let return_value = return_value.unwrap_or(Value::Null);
+        interpreter.notify(|debugger, interpreter| {
+            debugger.returned(&self.name, &return_value, interpreter)
+        });
+        Ok(return_value)
     }
 
     fn arity(&self) -> u8 {
         self.params.len().try_into().unwrap()
     }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn file(&self) -> Option<&Arc<str>> {
+        Some(&self.file)
+    }
+
+    fn closure(&self) -> Option<&Scope> {
+        Some(&self.closure)
+    }
 }
 
+pub type NativeCallable = fn(
+    &mut Interpreter,
+    &[Value],
+    args_tokens: &[SourceSpan],
+    source: Arc<str>,
+) -> Result<Value, RuntimeError>;
+
 pub struct NativeProcedure {
     pub name: String,
     pub arity: u8,
-    pub callable: fn(
-        &mut Interpreter,
-        &[Value],
-        args_tokens: &[SourceSpan],
-        source: Arc<str>,
-    ) -> Result<Value, RuntimeError>,
+    pub params: &'static [&'static str],
+    pub callable: NativeCallable,
 }
 
 impl Callable for NativeProcedure {
@@ -98,4 +160,12 @@ impl Callable for NativeProcedure {
     fn arity(&self) -> u8 {
         self.arity
     }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn params(&self) -> &[&'static str] {
+        self.params
+    }
 }
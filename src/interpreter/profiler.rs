@@ -0,0 +1,220 @@
+use crate::interpreter::Interpreter;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// measures how long each PROCEDURE takes, for `--profile`.
+/// standard library functions are measured too. clones share the same measurements.
+/// not available on wasm, it has no clock
+#[derive(Clone, Debug)]
+pub struct Profiler {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// the calls that are running, the top level first
+    running: Vec<Running>,
+    times: HashMap<String, Times>,
+    /// the time spent in each chain of calls, keyed like `fib;fib;ADD`
+    stacks: HashMap<String, Duration>,
+}
+
+#[derive(Debug)]
+struct Running {
+    name: String,
+    /// every call under this one, like `sort;swap`
+    path: String,
+    start: Instant,
+    /// time spent in the calls it made
+    children: Duration,
+}
+
+/// how long one PROCEDURE took over the whole run
+#[derive(Clone, Debug, Default)]
+pub struct Times {
+    pub calls: u64,
+    /// from each call until it returned, including the calls it made.
+    /// a PROCEDURE that calls itself is only counted from the outermost call
+    pub total: Duration,
+    /// only the time spent in the PROCEDURE itself
+    pub own: Duration,
+}
+
+/// what a `Profiler` measured
+#[derive(Clone, Debug)]
+pub struct Profile {
+    /// how long the program ran
+    pub total: Duration,
+    /// each PROCEDURE with the top level as `TOP_LEVEL`, the slowest first
+    pub procedures: Vec<(String, Times)>,
+    stacks: Vec<(String, Duration)>,
+}
+
+/// the name the code outside of every PROCEDURE is shown with
+pub const TOP_LEVEL: &str = "top level";
+
+impl Profiler {
+    /// starts the clock. the time until `finish` belongs to the top level
+    pub fn new() -> Self {
+        let state = State {
+            running: vec![Running {
+                name: TOP_LEVEL.to_string(),
+                path: String::new(),
+                start: Instant::now(),
+                children: Duration::ZERO,
+            }],
+            times: HashMap::new(),
+            stacks: HashMap::new(),
+        };
+        Self {
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    /// a call started
+    pub(super) fn enter(&self, name: &str) {
+        let mut state = self.state.borrow_mut();
+        let path = match state.running.last() {
+            Some(caller) if !caller.path.is_empty() => format!("{};{name}", caller.path),
+            _ => name.to_string(),
+        };
+        state.running.push(Running {
+            name: name.to_string(),
+            path,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    /// the call that `enter` started returned, or failed
+    pub(super) fn exit(&self) {
+        self.state.borrow_mut().exit();
+    }
+
+    /// stops the clock and adds up the times
+    pub fn finish(&self) -> Profile {
+        let mut state = self.state.borrow_mut();
+        // an error can stop the program in the middle of calls
+        while !state.running.is_empty() {
+            state.exit();
+        }
+
+        let mut procedures: Vec<(String, Times)> = state.times.drain().collect();
+        procedures.sort_by(|(a_name, a), (b_name, b)| b.own.cmp(&a.own).then(a_name.cmp(b_name)));
+
+        let mut stacks: Vec<(String, Duration)> = state.stacks.drain().collect();
+        stacks.sort();
+
+        let total = procedures
+            .iter()
+            .find(|(name, _)| name == TOP_LEVEL)
+            .map(|(_, times)| times.total)
+            .unwrap_or_default();
+
+        Profile {
+            total,
+            procedures,
+            stacks,
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    fn exit(&mut self) {
+        let Some(call) = self.running.pop() else {
+            return;
+        };
+        let elapsed = call.start.elapsed();
+        let own = elapsed.saturating_sub(call.children);
+
+        if let Some(caller) = self.running.last_mut() {
+            caller.children += elapsed;
+        }
+
+        // the outer call already counts the time of a PROCEDURE calling itself
+        let recursive = self.running.iter().any(|caller| caller.name == call.name);
+
+        let times = self.times.entry(call.name).or_default();
+        times.calls += 1;
+        times.own += own;
+        if !recursive {
+            times.total += elapsed;
+        }
+
+        let path = if call.path.is_empty() {
+            TOP_LEVEL.to_string()
+        } else {
+            call.path
+        };
+        *self.stacks.entry(path).or_default() += own;
+    }
+}
+
+impl Profile {
+    /// a table of every PROCEDURE, the one that spent the most time in itself first
+    pub fn table(&self) -> String {
+        let width = self
+            .procedures
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain([9])
+            .max()
+            .unwrap_or_default();
+
+        let mut table = format!(
+            "{:<width$}  {:>8}  {:>12}  {:>12}  {:>6}\n",
+            "PROCEDURE", "CALLS", "TOTAL", "SELF", "SELF %"
+        );
+        for (name, times) in &self.procedures {
+            let percent = if self.total.is_zero() {
+                0.0
+            } else {
+                times.own.as_secs_f64() / self.total.as_secs_f64() * 100.0
+            };
+            let _ = writeln!(
+                table,
+                "{name:<width$}  {:>8}  {:>12}  {:>12}  {percent:>5.1}%",
+                times.calls,
+                milliseconds(times.total),
+                milliseconds(times.own),
+            );
+        }
+        table
+    }
+
+    /// the folded stacks that flamegraph tools read, like `inferno-flamegraph` or `flamegraph.pl`.
+    /// each line is a chain of calls and the nanoseconds spent at the end of it
+    pub fn folded(&self) -> String {
+        let mut folded = String::new();
+        for (path, time) in &self.stacks {
+            // the top level is under everything, so the flamegraph has one root
+            let path = match path.as_str() {
+                TOP_LEVEL => TOP_LEVEL.to_string(),
+                path => format!("{TOP_LEVEL};{path}"),
+            };
+            let _ = writeln!(folded, "{path} {}", time.as_nanos());
+        }
+        folded
+    }
+}
+
+fn milliseconds(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
+
+impl Interpreter {
+    /// measures every call the program makes
+    pub fn with_profiler(mut self, profiler: Profiler) -> Self {
+        self.profiler = Some(profiler);
+        self
+    }
+}
@@ -0,0 +1,370 @@
+use crate::interpreter::debug::Debugger;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::{Interpreter, Value};
+use crate::lexer::token::Token;
+use crate::output::Buffer;
+use crate::parser::ast::Stmt;
+use crate::parser::walk::first_token;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// after this many steps nothing more is recorded, so a loop that never ends
+/// does not fill up memory. the program still runs to the end
+pub const MAX_RECORDED_STEPS: usize = 10_000;
+
+/// the page `Recording::to_html` fills in. it has everything it needs, so it works offline
+const VIEWER: &str = include_str!("viewer.html");
+
+type List = Rc<RefCell<Vec<Value>>>;
+
+/// records every variable and list before each statement, for visualizers that draw memory.
+/// clones share the same recording
+#[derive(Clone)]
+pub struct Recorder {
+    state: Rc<RefCell<State>>,
+    /// what the program displays also goes here
+    output: Buffer,
+}
+
+#[derive(Default)]
+struct State {
+    recording: Recording,
+    /// every list seen so far, with the id it was given.
+    /// holding on to them keeps their address from being reused by a new list
+    lists: Vec<List>,
+    ids: HashMap<*const RefCell<Vec<Value>>, usize>,
+    /// where the last recorded step was, the program ends there
+    last: Option<Token>,
+}
+
+/// a whole run of a program, one step at a time
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    pub steps: Vec<Step>,
+    /// the code of every file a step was in
+    pub sources: BTreeMap<String, Arc<str>>,
+    /// everything the program displayed. each step knows how much of it was there
+    pub output: String,
+    /// the program ran more than `MAX_RECORDED_STEPS` statements.
+    /// the last step is still where it ended
+    pub truncated: bool,
+}
+
+/// the state of the program right before a statement ran, or once it ended
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub event: Event,
+    pub file: String,
+    pub line: usize,
+    /// every call that is running, the top level first
+    pub frames: Vec<FrameState>,
+    /// every list a variable can reach, by id.
+    /// two variables holding the same id hold the same list
+    pub heap: BTreeMap<usize, Vec<Recorded>>,
+    /// how many characters of `Recording::output` had been displayed
+    pub output: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// a statement is about to run
+    Step,
+    /// the program finished
+    Finished,
+    /// the program stopped with this error
+    Error(String),
+}
+
+/// the variables of one call that is running
+#[derive(Clone, Debug)]
+pub struct FrameState {
+    /// the PROCEDURE that is running, or None for the top level
+    pub procedure: Option<String>,
+    pub file: String,
+    /// the line it is on, or the line of the call it is waiting on
+    pub line: usize,
+    pub variables: Vec<(String, Recorded)>,
+}
+
+/// a value as it was recorded. lists are only referred to, their items are in the heap
+#[derive(Clone, Debug, PartialEq)]
+pub enum Recorded {
+    Null,
+    Number(f64),
+    Bool(bool),
+    String(String),
+    /// the id of a list in `Step::heap`
+    List(usize),
+    Procedure(String),
+    /// something from the standard library that aplang code cannot look inside of
+    Object,
+}
+
+impl Recorder {
+    /// `output` has to get everything the program displays, see `Tee`
+    pub fn new(output: Buffer) -> Self {
+        Self {
+            state: Rc::default(),
+            output,
+        }
+    }
+
+    /// records where the program ended, with the error that stopped it
+    pub fn finish(&self, interpreter: &Interpreter, result: &Result<(), RuntimeError>) {
+        let Some(token) = self.state.borrow().last.clone() else {
+            return;
+        };
+
+        let event = match result {
+            Ok(()) => Event::Finished,
+            Err(err) => Event::Error(err.message.clone()),
+        };
+        self.record(event, &token, interpreter);
+    }
+
+    /// everything recorded so far
+    pub fn recording(&self) -> Recording {
+        let mut recording = self.state.borrow().recording.clone();
+        recording.output = self.output.contents();
+        recording
+    }
+
+    fn record(&self, event: Event, at: &Token, interpreter: &Interpreter) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+
+        let mut heap = BTreeMap::new();
+        let mut frames = vec![];
+        for (depth, frame) in interpreter.frames(at).into_iter().enumerate() {
+            state
+                .recording
+                .sources
+                .entry(frame.file.clone())
+                .or_insert_with(|| frame.token.source.clone());
+
+            let variables = interpreter
+                .locals(depth)
+                .into_iter()
+                .map(|(name, value)| (name, state.record(&value, &mut heap)))
+                .collect();
+
+            frames.push(FrameState {
+                procedure: frame.procedure,
+                file: frame.file,
+                line: frame.token.line_number,
+                variables,
+            });
+        }
+        frames.reverse();
+
+        let file = frames
+            .last()
+            .map(|frame| frame.file.clone())
+            .unwrap_or_default();
+        state.recording.steps.push(Step {
+            event,
+            file,
+            line: at.line_number,
+            frames,
+            heap,
+            output: self.output.contents().chars().count(),
+        });
+        state.last = Some(at.clone());
+    }
+}
+
+impl State {
+    /// records a value, putting the lists it reaches into the heap
+    fn record(&mut self, value: &Value, heap: &mut BTreeMap<usize, Vec<Recorded>>) -> Recorded {
+        match value {
+            Value::Null => Recorded::Null,
+            Value::Number(number) => Recorded::Number(*number),
+            Value::Bool(bool) => Recorded::Bool(*bool),
+            Value::String(string) => Recorded::String(string.clone()),
+            Value::List(list) => {
+                let id = self.id(list);
+                // a list that holds itself would never finish
+                if !heap.contains_key(&id) {
+                    heap.insert(id, vec![]);
+                    let items = list
+                        .borrow()
+                        .iter()
+                        .map(|item| self.record(item, heap))
+                        .collect();
+                    heap.insert(id, items);
+                }
+                Recorded::List(id)
+            }
+            Value::Function(procedure) | Value::NativeFunction(procedure) => {
+                Recorded::Procedure(procedure.name().to_string())
+            }
+            Value::NativeObject(_) => Recorded::Object,
+        }
+    }
+
+    /// the same list always gets the same id, starting at 1
+    fn id(&mut self, list: &List) -> usize {
+        *self.ids.entry(Rc::as_ptr(list)).or_insert_with(|| {
+            self.lists.push(list.clone());
+            self.lists.len()
+        })
+    }
+}
+
+impl Debugger for Recorder {
+    fn reached(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> bool {
+        let full = self.state.borrow().recording.steps.len() >= MAX_RECORDED_STEPS;
+        if full {
+            let mut state = self.state.borrow_mut();
+            state.recording.truncated = true;
+            state.last = Some(first_token(stmt).clone());
+        } else {
+            self.record(Event::Step, first_token(stmt), interpreter);
+        }
+        true
+    }
+}
+
+impl Recording {
+    /// the recording as JSON, a format close to the one Python Tutor uses:
+    ///
+    /// ```json
+    /// {
+    ///   "sources": { "main.ap": "..." },
+    ///   "output": "...",
+    ///   "truncated": false,
+    ///   "steps": [{
+    ///     "event": "step", "file": "main.ap", "line": 3, "output": 0,
+    ///     "frames": [{ "procedure": null, "file": "main.ap", "line": 3,
+    ///                  "variables": [{ "name": "a", "value": { "list": 1 } }] }],
+    ///     "heap": { "1": [1, "two", true, null] }
+    ///   }]
+    /// }
+    /// ```
+    ///
+    /// an error step also has `"error": "message"`. a procedure is `{ "procedure": "name" }`
+    /// and anything else from the standard library is `{ "object": true }`
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+
+        let sources: Vec<String> = self
+            .sources
+            .iter()
+            .map(|(file, source)| format!("{}: {}", string(file), string(source)))
+            .collect();
+        let _ = writeln!(json, "  \"sources\": {{{}}},", sources.join(", "));
+        let _ = writeln!(json, "  \"output\": {},", string(&self.output));
+        let _ = writeln!(json, "  \"truncated\": {},", self.truncated);
+
+        json.push_str("  \"steps\": [");
+        for (index, step) in self.steps.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            json.push_str("\n    ");
+            step.write_json(&mut json);
+        }
+        json.push_str("\n  ]\n}\n");
+        json
+    }
+
+    /// a web page that steps through the recording and draws the memory.
+    /// everything it needs is inside of it, so it works without internet
+    pub fn to_html(&self) -> String {
+        // `<` is escaped so nothing in the program can end the script early
+        let json = self.to_json().replace('<', "\\u003c");
+        VIEWER.replace("{{recording}}", json.trim_end())
+    }
+}
+
+impl Step {
+    fn write_json(&self, json: &mut String) {
+        let event = match &self.event {
+            Event::Step => "step",
+            Event::Finished => "finished",
+            Event::Error(_) => "error",
+        };
+        let _ = write!(
+            json,
+            "{{\"event\": \"{event}\", \"file\": {}, \"line\": {}, \"output\": {}",
+            string(&self.file),
+            self.line,
+            self.output
+        );
+        if let Event::Error(message) = &self.event {
+            let _ = write!(json, ", \"error\": {}", string(message));
+        }
+
+        let frames: Vec<String> = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let variables: Vec<String> = frame
+                    .variables
+                    .iter()
+                    .map(|(name, value)| {
+                        format!("{{\"name\": {}, \"value\": {}}}", string(name), value.json())
+                    })
+                    .collect();
+                let procedure = frame.procedure.as_deref().map_or("null".to_string(), string);
+                format!(
+                    "{{\"procedure\": {procedure}, \"file\": {}, \"line\": {}, \"variables\": [{}]}}",
+                    string(&frame.file),
+                    frame.line,
+                    variables.join(", ")
+                )
+            })
+            .collect();
+        let _ = write!(json, ", \"frames\": [{}]", frames.join(", "));
+
+        let heap: Vec<String> = self
+            .heap
+            .iter()
+            .map(|(id, items)| {
+                let items: Vec<String> = items.iter().map(Recorded::json).collect();
+                format!("\"{id}\": [{}]", items.join(", "))
+            })
+            .collect();
+        let _ = write!(json, ", \"heap\": {{{}}}}}", heap.join(", "));
+    }
+}
+
+impl Recorded {
+    fn json(&self) -> String {
+        match self {
+            Recorded::Null => "null".to_string(),
+            Recorded::Number(number) if number.is_finite() => number.to_string(),
+            // JSON has no NaN or infinity
+            Recorded::Number(number) => format!("{{\"number\": \"{number}\"}}"),
+            Recorded::Bool(bool) => bool.to_string(),
+            Recorded::String(text) => string(text),
+            Recorded::List(id) => format!("{{\"list\": {id}}}"),
+            Recorded::Procedure(name) => format!("{{\"procedure\": {}}}", string(name)),
+            Recorded::Object => "{\"object\": true}".to_string(),
+        }
+    }
+}
+
+/// a JSON string
+fn string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for char in text.chars() {
+        match char {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            char if char.is_control() => {
+                let _ = write!(json, "\\u{:04x}", char as u32);
+            }
+            char => json.push(char),
+        }
+    }
+    json.push('"');
+    json
+}
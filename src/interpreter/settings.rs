@@ -0,0 +1,109 @@
+use crate::interpreter::cancel::CancelToken;
+use std::time::Duration;
+
+/// the engine that runs a program
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// walks the syntax tree directly
+    #[default]
+    Tree,
+
+    /// compiles the syntax tree to bytecode first, then runs it on a stack machine.
+    /// much faster for recursive code
+    Bytecode,
+}
+
+/// how many calls can be waiting on each other before the program is stopped.
+/// without `stacker` the stack cannot grow, so the limit is lower
+pub const DEFAULT_MAX_CALL_DEPTH: usize = if cfg!(feature = "native") {
+    10_000
+} else {
+    1_000
+};
+
+/// how much of the stack calls can use when it cannot grow.
+/// wasm has about 1MiB of stack, and threads other than main get 2MiB
+pub const DEFAULT_STACK_LIMIT: usize = if cfg!(target_family = "wasm") {
+    512 * 1024
+} else {
+    1024 * 1024
+};
+
+/// options that change how a program runs.
+/// imported modules run with the same settings as the file that imported them
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub engine: Engine,
+
+    /// the deepest calls can go. None for no limit
+    pub max_call_depth: Option<usize>,
+
+    /// how many bytes of the stack calls can use before the program is stopped, so deep
+    /// calls get an error instead of overflowing the stack.
+    /// only used without `stacker`, which grows the stack instead
+    pub stack_limit: usize,
+
+    pub budget: Budget,
+
+    /// stops the program once it is cancelled
+    pub cancel: Option<CancelToken>,
+
+    /// makes RANDOM give the same numbers every run. None for different numbers each run
+    pub seed: Option<u64>,
+
+    pub clock: Clock,
+}
+
+impl Settings {
+    /// if the program has to be checked on while running
+    pub fn is_metered(&self) -> bool {
+        self.budget.is_metered() || self.cancel.is_some()
+    }
+}
+
+/// the clock TIME and SLEEP use
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Clock {
+    /// the clock of the computer. SLEEP really waits
+    #[default]
+    Real,
+
+    /// starts at 0 and only moves forward when SLEEP is called, which returns right away.
+    /// programs that use time run the same way every time
+    Virtual,
+}
+
+/// limits on how much work a program can do, for running code that cannot be trusted.
+/// None for no limit
+#[derive(Clone, Debug, Default)]
+pub struct Budget {
+    /// the most statements that can run
+    pub max_steps: Option<u64>,
+
+    /// how long the program can run for. not available on wasm
+    pub time_limit: Option<Duration>,
+
+    /// the longest a LIST can get
+    pub max_list_len: Option<usize>,
+}
+
+impl Budget {
+    /// if steps or time have to be kept track of while running
+    pub fn is_metered(&self) -> bool {
+        self.max_steps.is_some() || self.time_limit.is_some()
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            engine: Engine::default(),
+            max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+            stack_limit: DEFAULT_STACK_LIMIT,
+            budget: Budget::default(),
+            cancel: None,
+            seed: None,
+            clock: Clock::default(),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::interpreter::errors::{RuntimeError, TraceFrame, TracedError};
+use crate::interpreter::procedure::Callable;
+use crate::interpreter::Interpreter;
+use crate::parser::ast::{Import, ProcCall};
+use miette::NamedSource;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// the most frames shown under an error
+const TRACE_LIMIT: usize = 20;
+
+/// one step on the way to the code that is running right now.
+///
+/// frames are only popped once a call finishes without an error,
+/// so after an error the call stack is exactly the trace
+#[derive(Clone)]
+pub enum CallFrame {
+    /// a call to a PROCEDURE written in aplang
+    Call {
+        procedure: Rc<dyn Callable>,
+        call: Arc<ProcCall>,
+    },
+    /// an IMPORT of a user module
+    Import {
+        import: Arc<Import>,
+        /// the file of the module
+        module: Arc<str>,
+    },
+}
+
+impl Interpreter {
+    /// the file of the code that is running right now.
+    /// a PROCEDURE from a user module belongs to the module, even when it is called from here
+    pub fn get_file_path(&self) -> String {
+        match self.call_stack.last() {
+            Some(CallFrame::Call { procedure, .. }) => procedure
+                .file()
+                .map(|file| file.to_string())
+                .unwrap_or_else(|| self.get_root_file_path()),
+            _ => self.get_root_file_path(),
+        }
+    }
+
+    /// turns a runtime error into a report that shows every call that led to it
+    pub fn report(&mut self, error: RuntimeError) -> miette::Report {
+        let trace = self.take_trace();
+        miette::Report::new(TracedError { error, trace })
+    }
+
+    /// empties the call stack and describes each frame, innermost call first
+    pub fn take_trace(&mut self) -> Vec<TraceFrame> {
+        // the file and procedure each call happened in
+        let mut file: Arc<str> = self.get_root_file_path().into();
+        let mut caller: Option<String> = None;
+
+        let mut trace = vec![];
+        for frame in self.call_stack.drain(..) {
+            match frame {
+                CallFrame::Call { procedure, call } => {
+                    let message = match &caller {
+                        Some(caller) => format!("`{}` was called by `{caller}`", procedure.name()),
+                        None => format!("`{}` was called from the top level", procedure.name()),
+                    };
+
+                    trace.push(TraceFrame {
+                        named_source: NamedSource::new(&file, call.token.source.clone()),
+                        span: call.token.span,
+                        message,
+                        label: "called here".to_string(),
+                    });
+
+                    if let Some(procedure_file) = procedure.file() {
+                        file = procedure_file.clone();
+                    }
+                    caller = Some(procedure.name().to_string());
+                }
+                CallFrame::Import { import, module } => {
+                    trace.push(TraceFrame {
+                        named_source: NamedSource::new(&file, import.module_name.source.clone()),
+                        span: import.module_name.span,
+                        message: format!("while importing {module}"),
+                        label: "imported here".to_string(),
+                    });
+
+                    file = module;
+                    caller = None;
+                }
+            }
+        }
+
+        trace.reverse();
+
+        // deep recursion would bury the error, keep both ends of the trace
+        if trace.len() > TRACE_LIMIT {
+            let hidden = trace.len() - TRACE_LIMIT;
+            trace.drain(TRACE_LIMIT / 2..TRACE_LIMIT / 2 + hidden);
+
+            let last_shown = &mut trace[TRACE_LIMIT / 2 - 1];
+            last_shown.message = format!("{}\n... {hidden} more calls", last_shown.message);
+        }
+
+        trace
+    }
+}
@@ -0,0 +1,121 @@
+use crate::interpreter::debug::Debugger;
+use crate::interpreter::{Interpreter, Value};
+use crate::lexer::token::Token;
+use crate::output::Output;
+use crate::parser::ast::{Expr, Set, Stmt, Variable};
+use crate::parser::walk::first_token;
+
+/// writes down everything a program does, the way a student traces code by hand.
+/// each statement is shown with its line, followed by what it changed.
+/// the code inside of a call is indented under it
+pub struct Tracer {
+    output: Box<dyn Output>,
+}
+
+impl Tracer {
+    pub fn new(output: impl Output + 'static) -> Self {
+        Self {
+            output: Box::new(output),
+        }
+    }
+
+    fn write(&mut self, depth: usize, text: &str) {
+        self.output
+            .write(&format!("{}{text}\n", "  ".repeat(depth)));
+    }
+
+    /// something that happened while running the statement above it
+    fn note(&mut self, depth: usize, text: &str) {
+        self.write(depth, &format!("    {text}"));
+    }
+}
+
+impl Debugger for Tracer {
+    fn reached(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> bool {
+        let token = first_token(stmt);
+        let code = token
+            .source
+            .lines()
+            .nth(token.line_number - 1)
+            .unwrap_or_default()
+            .trim();
+
+        self.write(
+            interpreter.depth(),
+            &format!("line {}: {code}", token.line_number),
+        );
+        true
+    }
+
+    fn assigned(
+        &mut self,
+        variable: &Variable,
+        old: Option<&Value>,
+        new: &Value,
+        interpreter: &Interpreter,
+    ) {
+        let text = match old {
+            Some(old) => format!("{}: {} -> {}", variable.ident, show(old), show(new)),
+            None => format!("{} = {}", variable.ident, show(new)),
+        };
+        self.note(interpreter.depth(), &text);
+    }
+
+    fn set(
+        &mut self,
+        set: &Set,
+        index: &Value,
+        old: &Value,
+        new: &Value,
+        interpreter: &Interpreter,
+    ) {
+        let list = match &set.list {
+            Expr::Variable(variable) => variable.ident.as_str(),
+            _ => "list",
+        };
+        let text = format!("{list}[{index}]: {} -> {}", show(old), show(new));
+        self.note(interpreter.depth(), &text);
+    }
+
+    fn entered(
+        &mut self,
+        procedure: &str,
+        params: &[Variable],
+        arguments: &[Value],
+        interpreter: &Interpreter,
+    ) {
+        let arguments: Vec<String> = params
+            .iter()
+            .zip(arguments)
+            .map(|(param, argument)| format!("{} = {}", param.ident, show(argument)))
+            .collect();
+
+        // the call belongs to the caller, its body is indented under it
+        let depth = interpreter.depth().saturating_sub(1);
+        self.note(
+            depth,
+            &format!("call {procedure}({})", arguments.join(", ")),
+        );
+    }
+
+    fn returned(&mut self, procedure: &str, value: &Value, interpreter: &Interpreter) {
+        let depth = interpreter.depth().saturating_sub(1);
+        self.note(depth, &format!("{procedure} returned {}", show(value)));
+    }
+
+    fn iteration(&mut self, token: &Token, count: usize, interpreter: &Interpreter) {
+        let text = format!(
+            "iteration {count} of the loop on line {}",
+            token.line_number
+        );
+        self.note(interpreter.depth(), &text);
+    }
+}
+
+/// strings are quoted so they can be told apart from numbers
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(string) => format!("{string:?}"),
+        value => value.to_string(),
+    }
+}
@@ -1,3 +1,4 @@
+use crate::interpreter::procedure::Callable;
 use std::any::Any;
 use std::cell::RefCell;
 use std::fmt::{Display, Formatter};
@@ -14,8 +15,8 @@ pub enum Value {
     String(String),
     List(Rc<RefCell<Vec<Value>>>),
     NativeObject(Rc<RefCell<dyn Any>>),
-    NativeFunction(), // Assuming some representation
-    Function(),       // Assuming some representation
+    NativeFunction(Rc<dyn Callable>), // std PROCEDURE written in rust
+    Function(Rc<dyn Callable>),       // user PROCEDURE or anonymous PROCEDURE
 }
 
 impl Eq for Value {}
@@ -28,8 +29,9 @@ impl PartialEq for Value {
             (Value::String(a), Value::String(b)) => a == b,
             (Value::List(a), Value::List(b)) => *a.borrow() == *b.borrow(),
             (Value::NativeObject(a), Value::NativeObject(b)) => Rc::ptr_eq(a, b),
-            (Value::NativeFunction(), Value::NativeFunction()) => false, // Define better comparison if needed
-            (Value::Function(), Value::Function()) => false,             // Define better comparison if needed
+            // procedures are only equal if they are the exact same procedure
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
             _ => false,
         }
     }
@@ -53,8 +55,16 @@ impl Hash for Value {
                 let ptr = Rc::as_ptr(obj) as * const ();
                 ptr.hash(state);
             }
-            Value::NativeFunction() => state.write_u8(6), // Adjust if needed
-            Value::Function() => state.write_u8(7),       // Adjust if needed
+            Value::NativeFunction(function) => {
+                state.write_u8(6);
+                let ptr = Rc::as_ptr(function) as * const ();
+                ptr.hash(state);
+            }
+            Value::Function(function) => {
+                state.write_u8(7);
+                let ptr = Rc::as_ptr(function) as * const ();
+                ptr.hash(state);
+            }
         }
     }
 }
@@ -88,6 +98,9 @@ impl Display for Value {
             Value::Number(v) => write!(f, "{v}"),
             Value::Bool(true) => write!(f, "TRUE"),
             Value::Bool(false) => write!(f, "FALSE"),
+            Value::NativeFunction(function) | Value::Function(function) => {
+                write!(f, "PROCEDURE {}", function.name())
+            }
             _ => {
                 write!(f, "NATIVE")
             }
@@ -0,0 +1,82 @@
+use crate::interpreter::budget::Running;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::settings::{Clock, Settings};
+use crate::interpreter::Interpreter;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// the randomness and time a program sees.
+/// imported modules share the world of the file that imported them,
+/// so a seeded program gives the same numbers no matter where RANDOM is called from
+#[derive(Clone, Debug)]
+pub struct World {
+    rng: Rc<RefCell<StdRng>>,
+    /// the time in milliseconds, if the clock is virtual
+    virtual_time: Option<Rc<Cell<u64>>>,
+}
+
+impl World {
+    pub fn new(settings: &Settings) -> Self {
+        let rng = match settings.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+
+        Self {
+            rng: Rc::new(RefCell::new(rng)),
+            virtual_time: (settings.clock == Clock::Virtual).then(Rc::default),
+        }
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new(&Settings::default())
+    }
+}
+
+impl Interpreter {
+    /// a random integer from `low` to `high`, including both
+    pub fn random_range(&self, low: i64, high: i64) -> i64 {
+        self.world.rng.borrow_mut().random_range(low..=high)
+    }
+
+    /// milliseconds since the unix epoch, or since the start with a virtual clock
+    pub fn time_ms(&self) -> u128 {
+        if let Some(time) = &self.world.virtual_time {
+            return time.get().into();
+        }
+
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("TIME WENT BACKWARDS???")
+            .as_millis()
+    }
+
+    /// waits, or moves a virtual clock forward right away.
+    /// a wait past the time limit only lasts until the limit, and then stops the program
+    pub fn sleep(&self, ms: u64) -> Result<(), RuntimeError> {
+        if let Some(time) = &self.world.virtual_time {
+            time.set(time.get() + ms);
+            return Ok(());
+        }
+
+        let duration = Duration::from_millis(ms);
+        match self.time_left() {
+            Some(left) if left <= duration => {
+                std::thread::sleep(left);
+                match self.native_call() {
+                    Some(call) => self.check_budget(Running::Call(call, "SLEEP")),
+                    None => Ok(()),
+                }
+            }
+            _ => {
+                std::thread::sleep(duration);
+                Ok(())
+            }
+        }
+    }
+}
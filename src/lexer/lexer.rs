@@ -1,5 +1,5 @@
-use crate::lexer::token::Token;
 use crate::lexer::token::TokenType::*;
+use crate::lexer::token::{Comment, Token};
 use crate::lexer::token::{LiteralValue, TokenType};
 use miette::{miette, LabeledSpan, Report, SourceSpan};
 use owo_colors::OwoColorize;
@@ -13,6 +13,7 @@ pub struct Lexer {
     source: Arc<str>,
 
     pub(super) tokens: Vec<Token>,
+    pub(super) comments: Vec<Comment>,
 
     start: usize,
     current: usize,
@@ -27,6 +28,7 @@ impl Lexer {
             file_name,
             source: input.into(),
             tokens: Vec::new(),
+            comments: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
@@ -44,6 +46,17 @@ impl Lexer {
         Ok(tokens)
     }
 
+    /// scans the tokens, and keeps the comments the parser does not need
+    pub fn scan_with_comments(
+        input: impl Into<Arc<str>>,
+        file_name: String,
+    ) -> miette::Result<(Vec<Token>, Vec<Comment>), Vec<Report>> {
+        let mut lexer = Self::new(input, file_name);
+        let tokens = lexer.scan_tokens()?;
+
+        Ok((tokens, lexer.comments))
+    }
+
     pub fn scan_tokens(&mut self) -> miette::Result<Vec<Token>, Vec<Report>> {
         let mut errors: Vec<Report> = vec![];
         while !self.is_at_end() {
@@ -162,6 +175,12 @@ impl Lexer {
                         }
                         self.advance();
                     }
+
+                    let text = self.source[self.start + 2..self.current].trim_end();
+                    self.comments.push(Comment {
+                        text: text.to_string(),
+                        span: SourceSpan::new(self.start.into(), self.current - self.start),
+                    });
                 } else {
                     self.add_token(Slash)
                 }
@@ -185,8 +204,9 @@ impl Lexer {
             },
             ' ' | '\r' | '\t' => { /* nop */ }
             '\n' => {
+                // blank lines before the first token count too
+                self.line += 1;
                 if let Some(prev) = self.tokens.last() {
-                    self.line += 1;
                     // use go's method of implicit semicolons
                     // see: https://go.dev/ref/spec#Semicolons
                     match prev.token_type {
@@ -345,23 +365,19 @@ impl Lexer {
         }
     }
 
-    fn peek_advance(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            return '\0';
-        }
+    // `current` is a byte offset, so spans and slices of the source line up
+    // even when the source has characters that are more than one byte
 
-        self.source.chars().nth(self.current + 1).unwrap()
+    fn peek_advance(&self) -> char {
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn advance(&mut self) -> char {
-        let c = self.source.chars().nth(self.current).unwrap();
-        self.current += 1;
+        let c = self.peek();
+        self.current += c.len_utf8();
 
         c
     }
@@ -371,9 +387,9 @@ impl Lexer {
             return false;
         }
 
-        let mut i = 1;
+        let mut chars = self.source[self.current..].chars().skip(1);
         loop {
-            let next_char = self.source.chars().nth(self.current + i);
+            let next_char = chars.next();
 
             match next_char {
                 // if we're at the end, then return false
@@ -381,9 +397,7 @@ impl Lexer {
                     break false;
                 }
                 Some(next_char) => {
-                    if next_char.is_whitespace() {
-                        i += 1;
-                    } else {
+                    if !next_char.is_whitespace() {
                         return next_char == ch;
                     }
                 }
@@ -419,10 +433,10 @@ impl Lexer {
             return false;
         }
 
-        if self.source.chars().nth(self.current).unwrap() != ch {
+        if self.peek() != ch {
             false
         } else {
-            self.current += 1;
+            self.current += ch.len_utf8();
             true
         }
     }
@@ -198,3 +198,30 @@ fn test_spans() {
             a <- a + 1\
             }";
 }
+
+#[test]
+fn keep_comments() {
+    let source = "x <- 1 // one\n// two\r\nDISPLAY(x)";
+    let mut scanner = Lexer::new(source, String::default());
+    scanner.scan_tokens().unwrap();
+
+    // comments are not tokens
+    assert!(scanner.tokens.iter().all(|token| token.token_type != Slash));
+    assert_eq!(scanner.comments.len(), 2);
+    assert_eq!(scanner.comments[0].text, " one");
+    assert_eq!(scanner.comments[0].span.offset(), 7);
+    assert_eq!(scanner.comments[1].text, " two");
+    assert_eq!(scanner.comments[1].span.offset(), 14);
+}
+
+#[test]
+fn line_numbers_count_leading_blank_lines() {
+    let source = "\n\nx <- 1\n// note\nDISPLAY(x)";
+    let mut scanner = Lexer::new(source, String::default());
+    scanner.scan_tokens().unwrap();
+
+    assert_eq!(scanner.tokens[0].lexeme, "x");
+    assert_eq!(scanner.tokens[0].line_number, 3);
+    let display = scanner.tokens.iter().find(|token| token.lexeme == "DISPLAY");
+    assert_eq!(display.unwrap().line_number, 5);
+}
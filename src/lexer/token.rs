@@ -78,6 +78,14 @@ pub struct Token {
     pub source: Arc<str>,
 }
 
+/// a `//` comment. the parser never sees these, they are kept for the formatter
+#[derive(Debug, Clone)]
+pub struct Comment {
+    /// everything after the `//`
+    pub text: String,
+    pub span: SourceSpan,
+}
+
 // Implement Display for Token
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -86,7 +94,7 @@ impl fmt::Display for Token {
 }
 
 impl Token {
-    pub fn debug_many(tokens: &Vec<Token>) -> String {
+    pub fn debug_many(tokens: &[Token]) -> String {
         let string: Vec<String> = tokens.iter().map(|t| format!("{t}")).collect();
         format!("[{}]", string.join(", "))
     }
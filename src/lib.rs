@@ -1,4 +1,8 @@
 #![allow(dead_code, unused_variables, clippy::module_inception)]
+// RuntimeError holds its source, span and messages instead of a miette report, which made
+// recursive code much slower (see the note above `Interpreter`). almost every function in
+// the interpreter returns it, so the lint is allowed for the crate instead of on each one
+#![allow(clippy::result_large_err)]
 
 //! # Hello
 //! if you are looking to use the interpreter
@@ -14,6 +18,7 @@
 //! <3
 
 pub mod aplang;
+pub mod input;
 pub mod interpreter;
 pub mod lexer;
 pub mod parser;
@@ -0,0 +1,602 @@
+use crate::lexer::token::{LiteralValue, Token, TokenType};
+use crate::lexer::Lexer;
+use crate::parser::ast::*;
+use crate::parser::walk::{walk, Node};
+use crate::parser::{Linter, Lints, Parser, Resolver};
+use crate::standard_library::docs::describe;
+use crate::standard_library::Modules;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, DocumentSymbol,
+    Documentation, Hover, HoverContents, Location, MarkupContent, MarkupKind, NumberOrString,
+    Position, Range, SymbolKind, Url,
+};
+use miette::{Report, Severity};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// a file open in the editor, along with what is known about it
+pub struct Document {
+    uri: Url,
+    path: Option<PathBuf>,
+    file_name: String,
+
+    source: Arc<str>,
+    lines: Lines,
+    tokens: Vec<Token>,
+    /// None if the file does not parse right now
+    ast: Option<Ast>,
+
+    /// every PROCEDURE that can be called from the file.
+    /// kept from the last time the file parsed, so completion still works while typing
+    procedures: Vec<Procedure>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// a PROCEDURE that can be called, and where it comes from
+#[derive(Clone)]
+struct Procedure {
+    name: String,
+    params: Vec<String>,
+    origin: Origin,
+}
+
+#[derive(Clone)]
+enum Origin {
+    /// declared in a file, this one or an imported user module
+    Declared(Location),
+    /// part of a standard library module
+    Std(String),
+}
+
+impl Procedure {
+    fn signature(&self) -> String {
+        let params = self.params.join(", ");
+        match self.origin {
+            Origin::Declared(_) => format!("PROCEDURE {}({params})", self.name),
+            Origin::Std(_) => format!("{}({params})", self.name),
+        }
+    }
+
+    fn doc(&self) -> String {
+        let mut doc = format!("```aplang\n{}\n```", self.signature());
+        match &self.origin {
+            Origin::Declared(location) => {
+                if let Some(file) = location.uri.path_segments().and_then(|mut s| s.next_back()) {
+                    doc.push_str(&format!("\n\ndeclared in `{file}`"));
+                }
+            }
+            Origin::Std(module) => {
+                if let Some(description) = describe(&self.name) {
+                    doc.push_str(&format!("\n\n{description}"));
+                }
+                if module != "CORE" {
+                    doc.push_str(&format!("\n\nfrom `IMPORT MOD \"{module}\"`"));
+                }
+            }
+        }
+        doc
+    }
+}
+
+impl Document {
+    pub fn new(uri: Url, text: String) -> Self {
+        let path = uri.to_file_path().ok();
+        let file_name = path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| uri.to_string());
+
+        let mut document = Self {
+            uri,
+            path,
+            file_name,
+            source: "".into(),
+            lines: Lines::new(""),
+            tokens: vec![],
+            ast: None,
+            procedures: vec![],
+            diagnostics: vec![],
+        };
+        document.update(text);
+        document
+    }
+
+    pub fn uri(&self) -> &Url {
+        &self.uri
+    }
+
+    /// checks the new text of the file
+    pub fn update(&mut self, text: String) {
+        self.source = text.into();
+        self.lines = Lines::new(&self.source);
+        self.tokens = vec![];
+        self.ast = None;
+
+        let reports = self.analyze();
+        self.diagnostics = reports
+            .iter()
+            .map(|report| self.diagnostic(report))
+            .collect();
+    }
+
+    fn analyze(&mut self) -> Vec<Report> {
+        let tokens = match Lexer::scan(self.source.clone(), self.file_name.clone()) {
+            Ok(tokens) => tokens,
+            Err(reports) => return reports,
+        };
+        self.tokens = tokens.clone();
+
+        let ast = match Parser::new(tokens, self.source.clone(), &self.file_name).parse() {
+            Ok(ast) => ast,
+            Err(reports) => return reports,
+        };
+        self.procedures = self.procedures(&ast);
+
+        let mut reports = Resolver::new(self.source.clone(), &self.file_name, self.path.clone())
+            .resolve(&ast)
+            .err()
+            .unwrap_or_default();
+        reports
+            .extend(Linter::new(self.source.clone(), &self.file_name, Lints::default()).lint(&ast));
+
+        self.ast = Some(ast);
+        reports
+    }
+
+    fn diagnostic(&self, report: &Report) -> Diagnostic {
+        let label = report.labels().and_then(|mut labels| labels.next());
+
+        let mut message = report.to_string();
+        if let Some(text) = label.as_ref().and_then(|label| label.label()) {
+            message.push_str(&format!("\n{text}"));
+        }
+        if let Some(help) = report.help() {
+            message.push_str(&format!("\nhelp: {help}"));
+        }
+
+        Diagnostic {
+            range: label
+                .map(|label| self.lines.range(&self.source, label.offset(), label.len()))
+                .unwrap_or_default(),
+            severity: Some(match report.severity() {
+                Some(Severity::Warning) => DiagnosticSeverity::WARNING,
+                Some(Severity::Advice) => DiagnosticSeverity::HINT,
+                _ => DiagnosticSeverity::ERROR,
+            }),
+            code: report
+                .code()
+                .map(|code| NumberOrString::String(code.to_string())),
+            source: Some("aplang".to_string()),
+            message,
+            ..Default::default()
+        }
+    }
+
+    /// finds every PROCEDURE the file can call: its own, the ones it imports, and CORE
+    fn procedures(&self, ast: &Ast) -> Vec<Procedure> {
+        let modules = Modules::init();
+        let mut procedures = std_procedures(&modules, "CORE", None);
+
+        for stmt in &ast.program {
+            walk(Node::Stmt(stmt), true, &mut |node| match node {
+                Node::Stmt(Stmt::ProcDeclaration(proc)) => {
+                    procedures.push(declared(proc, &self.uri, &self.source, &self.lines));
+                }
+                Node::Stmt(Stmt::Import(import)) => {
+                    let Some(module_name) = module_name(import) else {
+                        return;
+                    };
+                    let only = import.only_functions.as_ref().map(|functions| {
+                        functions
+                            .iter()
+                            .filter_map(|function| match &function.literal {
+                                Some(LiteralValue::String(name)) => Some(name.clone()),
+                                _ => None,
+                            })
+                            .collect::<HashSet<_>>()
+                    });
+
+                    if modules.lookup(module_name).is_some() {
+                        procedures.extend(std_procedures(&modules, module_name, only.as_ref()));
+                    } else if let Some(path) = self.module_path(module_name) {
+                        procedures.extend(exports(&path).into_iter().filter(|proc| {
+                            only.as_ref().is_none_or(|only| only.contains(&proc.name))
+                        }));
+                    }
+                }
+                _ => {}
+            });
+        }
+
+        procedures
+    }
+
+    /// where a user module is, relative to this file
+    fn module_path(&self, module_name: &str) -> Option<PathBuf> {
+        Some(self.path.as_ref()?.parent()?.join(module_name))
+    }
+
+    /// the token under the cursor
+    fn token_at(&self, position: Position) -> Option<&Token> {
+        let offset = self.lines.offset(&self.source, position);
+        // when the cursor is right after a name it still counts
+        self.tokens
+            .iter()
+            .filter(|token| token.token_type != TokenType::SoftSemi)
+            .find(|token| {
+                let start = token.span.offset();
+                start <= offset && offset <= start + token.span.len()
+            })
+    }
+
+    /// the IMPORT that names a module with this token, if there is one
+    fn import_of(&self, token: &Token) -> Option<Arc<Import>> {
+        self.ast
+            .as_ref()?
+            .program
+            .iter()
+            .find_map(|stmt| match stmt {
+                Stmt::Import(import) if import.module_name.span == token.span => {
+                    Some(import.clone())
+                }
+                _ => None,
+            })
+    }
+
+    fn procedure(&self, name: &str) -> Option<&Procedure> {
+        self.procedures
+            .iter()
+            .find(|procedure| procedure.name == name)
+    }
+
+    pub fn hover(&self, position: Position) -> Option<Hover> {
+        let token = self.token_at(position)?;
+
+        let doc = match token.token_type {
+            TokenType::Identifier => match self.procedure(&token.lexeme) {
+                Some(procedure) => procedure.doc(),
+                // a standard library function that has not been imported yet
+                None => {
+                    let module = Modules::init().functions().remove(&token.lexeme)?;
+                    std_procedures(&Modules::init(), &module, None)
+                        .into_iter()
+                        .find(|procedure| procedure.name == token.lexeme)?
+                        .doc()
+                }
+            },
+            TokenType::StringLiteral => {
+                let import = self.import_of(token)?;
+                let module_name = module_name(&import)?;
+                let modules = Modules::init();
+                modules.lookup(module_name)?;
+
+                let mut functions: Vec<_> = std_procedures(&modules, module_name, None)
+                    .into_iter()
+                    .map(|procedure| format!("- `{}`", procedure.signature()))
+                    .collect();
+                functions.sort();
+                format!("the `{module_name}` module\n\n{}", functions.join("\n"))
+            }
+            _ => return None,
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: doc,
+            }),
+            range: Some(
+                self.lines
+                    .range(&self.source, token.span.offset(), token.span.len()),
+            ),
+        })
+    }
+
+    pub fn definition(&self, position: Position) -> Option<Location> {
+        let token = self.token_at(position)?;
+
+        match token.token_type {
+            TokenType::Identifier => match &self.procedure(&token.lexeme)?.origin {
+                Origin::Declared(location) => Some(location.clone()),
+                Origin::Std(_) => None,
+            },
+            // the module of an IMPORT goes to its file
+            TokenType::StringLiteral => {
+                let import = self.import_of(token)?;
+                let path = self.module_path(module_name(&import)?)?;
+                if !path.is_file() {
+                    return None;
+                }
+                Some(Location {
+                    uri: Url::from_file_path(path).ok()?,
+                    range: Range::default(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn completion(&self, position: Position) -> Vec<CompletionItem> {
+        let typing = self.token_at(position).map(|token| token.span);
+
+        let mut items: Vec<CompletionItem> = self
+            .procedures
+            .iter()
+            .map(|procedure| CompletionItem {
+                label: procedure.name.clone(),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(procedure.signature()),
+                documentation: match &procedure.origin {
+                    Origin::Std(_) => describe(&procedure.name)
+                        .map(|description| Documentation::String(description.to_string())),
+                    Origin::Declared(_) => None,
+                },
+                ..Default::default()
+            })
+            .collect();
+
+        // any name that is not called is a variable. the lexer still works when the parser
+        // does not, so this keeps up with the file while it is being typed
+        let mut seen: HashSet<&str> = self.procedures.iter().map(|p| p.name.as_str()).collect();
+        for (token, next) in self.tokens.iter().zip(self.tokens.iter().skip(1)) {
+            if token.token_type != TokenType::Identifier
+                || next.token_type == TokenType::LeftParen
+                || Some(token.span) == typing
+                || !seen.insert(&token.lexeme)
+            {
+                continue;
+            }
+            items.push(CompletionItem {
+                label: token.lexeme.clone(),
+                kind: Some(CompletionItemKind::VARIABLE),
+                ..Default::default()
+            });
+        }
+
+        items
+    }
+
+    /// the PROCEDUREs and variables of the file, for the outline
+    #[allow(deprecated)] // `DocumentSymbol::deprecated` has to be set
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        let Some(ast) = &self.ast else {
+            return vec![];
+        };
+
+        let symbol =
+            |name: &str, detail: Option<String>, kind, range, selection_range, children| {
+                DocumentSymbol {
+                    name: name.to_string(),
+                    detail,
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    range,
+                    selection_range,
+                    children,
+                }
+            };
+
+        let mut symbols = vec![];
+        self.scope_symbols(&ast.program, &mut |stmt, variables| match stmt {
+            Some(proc) => {
+                let name = self.token_range(&proc.name_token);
+                let end = match &proc.body {
+                    Stmt::Block(block) => &block.rb_token,
+                    _ => &proc.name_token,
+                };
+                let start = self.token_range(&proc.proc_token).start;
+                let range = Range::new(start, self.token_range(end).end);
+                let params: Vec<_> = proc.params.iter().map(|p| p.ident.as_str()).collect();
+
+                symbols.push(symbol(
+                    &proc.name,
+                    Some(format!("({})", params.join(", "))),
+                    SymbolKind::FUNCTION,
+                    range,
+                    name,
+                    Some(
+                        variables
+                            .into_iter()
+                            .map(|token| {
+                                let range = self.token_range(token);
+                                symbol(
+                                    &token.lexeme,
+                                    None,
+                                    SymbolKind::VARIABLE,
+                                    range,
+                                    range,
+                                    None,
+                                )
+                            })
+                            .collect(),
+                    ),
+                ));
+            }
+            None => {
+                for token in variables {
+                    let range = self.token_range(token);
+                    symbols.push(symbol(
+                        &token.lexeme,
+                        None,
+                        SymbolKind::VARIABLE,
+                        range,
+                        range,
+                        None,
+                    ));
+                }
+            }
+        });
+
+        symbols.sort_by_key(|symbol| (symbol.range.start.line, symbol.range.start.character));
+        symbols
+    }
+
+    /// calls `found` with the variables created in the top level and in each PROCEDURE,
+    /// at the place each is first assigned
+    fn scope_symbols<'a>(
+        &self,
+        body: &'a [Stmt],
+        found: &mut dyn FnMut(Option<&'a ProcDeclaration>, Vec<&'a Token>),
+    ) {
+        let mut procedures = vec![];
+        let mut variables: Vec<&Token> = vec![];
+        let mut add = |token: &'a Token| {
+            if !variables.iter().any(|known| known.lexeme == token.lexeme) {
+                variables.push(token);
+            }
+        };
+        for stmt in body {
+            walk(Node::Stmt(stmt), false, &mut |node| match node {
+                Node::Expr(Expr::Assign(assignment)) => add(&assignment.ident_token),
+                Node::Stmt(Stmt::ForEach(for_each)) => add(&for_each.item_token),
+                Node::Stmt(Stmt::ProcDeclaration(proc)) => procedures.push(proc.as_ref()),
+                _ => {}
+            });
+        }
+        found(None, variables);
+
+        for proc in procedures {
+            let mut inner = vec![];
+            self.scope_symbols(
+                std::slice::from_ref(&proc.body),
+                &mut |nested, variables| match nested {
+                    Some(nested) => found(Some(nested), variables),
+                    None => inner = variables,
+                },
+            );
+            found(Some(proc), inner);
+        }
+    }
+
+    fn token_range(&self, token: &Token) -> Range {
+        self.lines
+            .range(&self.source, token.span.offset(), token.span.len())
+    }
+}
+
+fn module_name(import: &Import) -> Option<&str> {
+    match &import.module_name.literal {
+        Some(LiteralValue::String(name)) => Some(name),
+        _ => None,
+    }
+}
+
+fn declared(proc: &ProcDeclaration, uri: &Url, source: &str, lines: &Lines) -> Procedure {
+    let span = proc.name_token.span;
+    Procedure {
+        name: proc.name.clone(),
+        params: proc
+            .params
+            .iter()
+            .map(|param| param.ident.clone())
+            .collect(),
+        origin: Origin::Declared(Location {
+            uri: uri.clone(),
+            range: lines.range(source, span.offset(), span.len()),
+        }),
+    }
+}
+
+/// the functions of a standard library module
+fn std_procedures(
+    modules: &Modules,
+    module: &str,
+    only: Option<&HashSet<String>>,
+) -> Vec<Procedure> {
+    let Some(injector) = modules.lookup(module) else {
+        return vec![];
+    };
+    injector()
+        .into_iter()
+        .filter(|(name, _)| only.is_none_or(|only| only.contains(name)))
+        .map(|(name, (procedure, _))| Procedure {
+            name,
+            params: procedure
+                .params()
+                .iter()
+                .map(|param| param.to_string())
+                .collect(),
+            origin: Origin::Std(module.to_string()),
+        })
+        .collect()
+}
+
+/// the exported PROCEDUREs of a user module, if it can be read
+fn exports(path: &Path) -> Vec<Procedure> {
+    let Some(source) = fs::read_to_string(path).ok() else {
+        return vec![];
+    };
+    let (Ok(uri), Some(file_name)) = (Url::from_file_path(path), path.file_name()) else {
+        return vec![];
+    };
+    let file_name = file_name.to_string_lossy();
+
+    let Ok(tokens) = Lexer::scan(source.as_str(), file_name.to_string()) else {
+        return vec![];
+    };
+    let Ok(ast) = Parser::new(tokens, source.as_str().into(), &file_name).parse() else {
+        return vec![];
+    };
+
+    let lines = Lines::new(&source);
+    let mut exports = vec![];
+    for stmt in &ast.program {
+        walk(Node::Stmt(stmt), true, &mut |node| {
+            if let Node::Stmt(Stmt::ProcDeclaration(proc)) = node {
+                if proc.exported {
+                    exports.push(declared(proc, &uri, &source, &lines));
+                }
+            }
+        });
+    }
+    exports
+}
+
+/// turns byte offsets into the lines and UTF-16 columns editors use, and back
+struct Lines {
+    /// the offset each line starts at
+    starts: Vec<usize>,
+}
+
+impl Lines {
+    fn new(source: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { starts }
+    }
+
+    fn position(&self, source: &str, offset: usize) -> Position {
+        let offset = offset.min(source.len());
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.starts[line];
+        let character = source[start..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn range(&self, source: &str, offset: usize, len: usize) -> Range {
+        Range::new(
+            self.position(source, offset),
+            self.position(source, offset + len),
+        )
+    }
+
+    fn offset(&self, source: &str, position: Position) -> usize {
+        let Some(&start) = self.starts.get(position.line as usize) else {
+            return source.len();
+        };
+
+        let mut units = 0;
+        for (i, c) in source[start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        source.len()
+    }
+}
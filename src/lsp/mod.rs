@@ -0,0 +1,193 @@
+//! `aplang lsp`, a language server that editors talk to over stdin and stdout
+
+use document::Document;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _,
+};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, DocumentSymbolResponse, GotoDefinitionResponse,
+    HoverProviderCapability, OneOf, PublishDiagnosticsParams, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use miette::{miette, Result};
+use std::collections::HashMap;
+
+mod document;
+
+/// runs the language server until the editor says to stop
+pub fn serve() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    let capabilities = serde_json::to_value(capabilities).expect("capabilities are valid json");
+    connection
+        .initialize(capabilities)
+        .map_err(|err| miette!("could not start the language server\n{}", err))?;
+
+    Server {
+        connection,
+        documents: HashMap::new(),
+    }
+    .run()?;
+
+    // the server owned the connection, so it is closed and the threads can finish
+    io_threads
+        .join()
+        .map_err(|err| miette!("the language server could not stop cleanly\n{}", err))
+}
+
+struct Server {
+    connection: Connection,
+    /// the files that are open in the editor
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    fn run(mut self) -> Result<()> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    let shutdown = self
+                        .connection
+                        .handle_shutdown(&request)
+                        .map_err(|err| miette!("the editor stopped responding\n{}", err))?;
+                    if shutdown {
+                        return Ok(());
+                    }
+                    let response = self.request(request);
+                    self.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, message: Message) -> Result<()> {
+        self.connection
+            .sender
+            .send(message)
+            .map_err(|err| miette!("the editor stopped responding\n{}", err))
+    }
+
+    fn request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            HoverRequest::METHOD => answer::<HoverRequest>(request, |params| {
+                let position = params.text_document_position_params;
+                self.document(&position.text_document.uri)?
+                    .hover(position.position)
+            }),
+            GotoDefinition::METHOD => answer::<GotoDefinition>(request, |params| {
+                let position = params.text_document_position_params;
+                let location = self
+                    .document(&position.text_document.uri)?
+                    .definition(position.position)?;
+                Some(GotoDefinitionResponse::Scalar(location))
+            }),
+            Completion::METHOD => answer::<Completion>(request, |params| {
+                let position = params.text_document_position;
+                let items = self
+                    .document(&position.text_document.uri)?
+                    .completion(position.position);
+                Some(CompletionResponse::Array(items))
+            }),
+            DocumentSymbolRequest::METHOD => answer::<DocumentSymbolRequest>(request, |params| {
+                let symbols = self.document(&params.text_document.uri)?.symbols();
+                Some(DocumentSymbolResponse::Nested(symbols))
+            }),
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("aplang does not know how to answer {method}"),
+            ),
+        }
+    }
+
+    fn notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = params::<DidOpenTextDocument>(notification) else {
+                    return Ok(());
+                };
+                let document = Document::new(params.text_document.uri, params.text_document.text);
+                self.publish(&document)?;
+                self.documents.insert(document.uri().clone(), document);
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(params) = params::<DidChangeTextDocument>(notification) else {
+                    return Ok(());
+                };
+                // the whole file is sent every time, so only the last change matters
+                let (Some(document), Some(change)) = (
+                    self.documents.get_mut(&params.text_document.uri),
+                    params.content_changes.into_iter().last(),
+                ) else {
+                    return Ok(());
+                };
+                document.update(change.text);
+                let document = &self.documents[&params.text_document.uri];
+                self.publish(document)?;
+            }
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = params::<DidCloseTextDocument>(notification) else {
+                    return Ok(());
+                };
+                if let Some(mut document) = self.documents.remove(&params.text_document.uri) {
+                    // the editor keeps showing diagnostics until they are cleared
+                    document.diagnostics.clear();
+                    self.publish(&document)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn document(&self, uri: &Url) -> Option<&Document> {
+        self.documents.get(uri)
+    }
+
+    fn publish(&self, document: &Document) -> Result<()> {
+        let params = PublishDiagnosticsParams {
+            uri: document.uri().clone(),
+            diagnostics: document.diagnostics.clone(),
+            version: None,
+        };
+        self.send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))
+    }
+}
+
+/// answers a request with what `respond` returns
+fn answer<R: lsp_types::request::Request>(
+    request: Request,
+    respond: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    let id = request.id.clone();
+    match request.extract::<R::Params>(R::METHOD) {
+        Ok((id, params)) => Response::new_ok(id, respond(params)),
+        Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+    }
+}
+
+/// reads the params of a notification. None if they are not what was expected
+fn params<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Option<N::Params> {
+    notification.extract(N::METHOD).ok()
+}
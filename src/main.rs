@@ -1,4 +1,8 @@
 #![allow(dead_code, unused_variables, clippy::module_inception)]
+// RuntimeError holds its source, span and messages instead of a miette report, which made
+// recursive code much slower (see the note above `Interpreter`). almost every function in
+// the interpreter returns it, so the lint is allowed for the crate instead of on each one
+#![allow(clippy::result_large_err)]
 #[cfg(any(feature = "wasm", target_arch = "wasm32"))]
 compile_error!(r#"
 HALT! It seems like you are attempting to compile aplang into a binary with the "wasm" feature enabled. \
@@ -8,16 +12,28 @@ This features is ment for calling aplang from wasm ONLY. Please reinstall or rec
 
 
 use clap::Parser;
-use miette::{miette, Result};
+use miette::{miette, Report, Result, Severity};
 use std::io;
-use std::io::{ErrorKind, Read};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::io::{ErrorKind, Read, Write};
+use std::process;
+#[cfg(feature = "portable")]
+use std::{panic, thread};
 use std::time::Instant;
 use cfg_if::cfg_if;
-use crate::aplang::ApLang;
-use crate::arguments::{CommandLine, DebugMode};
+use crate::aplang::{ApLang, Parsed};
+use crate::arguments::{Command, CommandLine, DebugMode, EngineMode, FmtCommand, LintCommand};
 use interpreter::errors::Reports;
+use interpreter::coverage::Coverage;
+use interpreter::profiler::Profiler;
+use interpreter::recorder::Recorder;
+use interpreter::{Budget, CancelToken, Clock, Engine, Settings, Tracer};
+use output::{Buffer, Stdout, Tee, Writer};
 
 mod aplang;
+mod input;
 mod arguments;
 mod interpreter;
 mod lexer;
@@ -32,13 +48,35 @@ mod wasm;
 #[cfg(feature = "splash")]
 mod splash;
 
+#[cfg(feature = "lsp")]
+mod lsp;
+
+#[cfg(feature = "dap")]
+mod dap;
+
+#[cfg(feature = "repl")]
+mod repl;
+
+#[cfg(feature = "tester")]
+mod tester;
+
+#[cfg(feature = "grader")]
+mod grader;
+
 
 fn main() -> Result<()> {
     let args = CommandLine::parse();
     
     cfg_if! {
         if #[cfg(feature = "portable")] {
-            run(args)
+            // the stack of the main thread can be as small as 1MiB, so run on a thread
+            // that has the stack `stack_limit` expects
+            thread::Builder::new()
+                .stack_size(args.stack_size)
+                .spawn(|| run(args))
+                .map_err(|err| miette!("could not start the interpreter\n{}", err))?
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic))
         } else {
             stacker::maybe_grow(1024 * 1024, args.stack_size, || run(args))
         }
@@ -57,29 +95,66 @@ fn run(args: CommandLine) -> Result<()> {
 
 
 
-    let aplang = if let Some(file_path) = args.file {
-        ApLang::new_from_file(file_path.clone()).map_err(|err| match err.kind() {
-            ErrorKind::NotFound => miette!("Could not read file {}", file_path.display(),),
-            other_err => miette!(
-                "Critical Failure. Could not read file! {} {err:?}",
-                file_path.display(),
-            ),
-        })
-    } else if let Some(eval) = args.eval {
-        Ok(ApLang::new_from_stdin(eval))
-    } else if args.eval_stdin {
-        let mut buffer = String::new();
+    match args.command {
+        Some(Command::Lint(command)) => return lint(command),
+        Some(Command::Fmt(command)) => return fmt(command),
+        #[cfg(feature = "lsp")]
+        Some(Command::Lsp) => return lsp::serve(),
+        #[cfg(feature = "dap")]
+        Some(Command::Dap) => return dap::serve(),
+        #[cfg(feature = "repl")]
+        Some(Command::Repl) => return repl::run(),
+        #[cfg(feature = "tester")]
+        Some(Command::Test(command)) => return tester::run(command),
+        #[cfg(feature = "grader")]
+        Some(Command::Grade(command)) => return grader::run(command),
+        #[cfg(feature = "grader")]
+        Some(Command::GradeDir(command)) => return grader::run_dir(command, args.stack_size),
+        None => {}
+    }
 
-        io::stdin()
-            .read_to_string(&mut buffer)
-            .map_err(|err| miette!("failed to read from stdin\n{}", err))?;
-        Ok(ApLang::new_from_stdin(buffer))
-    } else {
-        unreachable!()
+    let aplang = load(args.file, args.eval, args.eval_stdin);
+
+    // ctrl-c stops the program cleanly, so the styles it set can be reset
+    let cancel = CancelToken::new();
+    let handle = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handle.is_cancelled() {
+            // pressed twice, the program is stuck waiting on INPUT or SLEEP
+            reset_style();
+            process::exit(130);
+        }
+        handle.cancel();
+    })
+    .map_err(|err| miette!("could not listen for ctrl-c\n{}", err))?;
+
+    let settings = Settings {
+        engine: match args.engine {
+            EngineMode::Tree => Engine::Tree,
+            EngineMode::Bytecode => Engine::Bytecode,
+        },
+        max_call_depth: (args.max_call_depth != 0).then_some(args.max_call_depth),
+        // `run` gets a stack of `stack_size`, and the rest is left for everything but calls
+        stack_limit: args.stack_size.saturating_sub(1024 * 1024),
+        budget: Budget {
+            max_steps: args.max_steps,
+            time_limit: args.time_limit,
+            max_list_len: args.max_list_len,
+        },
+        cancel: Some(cancel.clone()),
+        seed: args.seed,
+        clock: if args.virtual_clock {
+            Clock::Virtual
+        } else {
+            Clock::Real
+        },
     };
 
     // execute the lexer
-    let lexed = aplang?.lex().map_err(Reports::from)?; // todo implement errors here
+    let lexed = aplang?
+        .with_settings(settings)
+        .lex()
+        .map_err(Reports::from)?; // todo implement errors here
 
     // if the flag is enabled, capture the debug info
     if matches!(args.debug, DebugMode::All | DebugMode::Lexer) {
@@ -100,13 +175,20 @@ fn run(args: CommandLine) -> Result<()> {
 
     // stop if we're only checking
     if args.check {
-        return Ok(());
+        return report_lints(parsed.lint(args.lints.lints()));
     }
 
+    let stopped = |report| {
+        if cancel.is_cancelled() {
+            reset_style();
+        }
+        report
+    };
+
     // execute the interpreter
     let runtime = if matches!(args.debug, DebugMode::All | DebugMode::Interpreter) {
         let start = Instant::now();
-        let executed = parsed.execute_with_debug()?;
+        let executed = parsed.execute_with_debug().map_err(stopped)?;
         let elapsed = start.elapsed();
         executed
             .debug_output(&mut debug_buffer)
@@ -115,7 +197,25 @@ fn run(args: CommandLine) -> Result<()> {
         elapsed
     } else {
         let start = Instant::now();
-        parsed.execute()?;
+        if args.trace_json.is_some() || args.trace_html.is_some() {
+            record(parsed, args.trace_json, args.trace_html).map_err(stopped)?;
+        } else if let Some(path) = args.coverage {
+            coverage(parsed, &path).map_err(stopped)?;
+        } else if args.profile {
+            profile(parsed, args.profile_folded).map_err(stopped)?;
+        } else {
+            match tracer(args.trace, args.trace_file)? {
+                Some(tracer) => {
+                    let mut interpreter = parsed.interpreter().with_debugger(tracer);
+                    interpreter
+                        .interpret()
+                        .map_err(|err| stopped(interpreter.report(err)))?;
+                }
+                None => {
+                    parsed.execute().map_err(stopped)?;
+                }
+            }
+        }
         start.elapsed()
     };
 
@@ -131,3 +231,197 @@ fn run(args: CommandLine) -> Result<()> {
     Ok(())
 }
 
+/// where `--trace` writes, if it is on
+fn tracer(trace: bool, trace_file: Option<PathBuf>) -> Result<Option<Tracer>> {
+    if !trace {
+        return Ok(None);
+    }
+
+    let tracer = match trace_file {
+        Some(path) => {
+            let file = fs::File::create(&path).map_err(|err| {
+                miette!("could not create the trace file {}\n{}", path.display(), err)
+            })?;
+            Tracer::new(Writer(io::BufWriter::new(file)))
+        }
+        None => Tracer::new(Writer(io::stderr())),
+    };
+    Ok(Some(tracer))
+}
+
+/// runs the program for `--trace-json` and `--trace-html`, then writes what they asked for.
+/// the files are written even if the program fails, since the end is often the step worth seeing
+fn record(parsed: ApLang<Parsed>, json: Option<PathBuf>, html: Option<PathBuf>) -> Result<()> {
+    let buffer = Buffer::new();
+    let recorder = Recorder::new(buffer.clone());
+    let mut interpreter = parsed
+        .interpreter()
+        .with_output(Tee(Stdout, buffer))
+        .with_debugger(recorder.clone());
+
+    let result = interpreter.interpret();
+    recorder.finish(&interpreter, &result);
+
+    let recording = recorder.recording();
+    if let Some(path) = json {
+        write_trace(&path, recording.to_json())?;
+    }
+    if let Some(path) = html {
+        write_trace(&path, recording.to_html())?;
+    }
+
+    result.map_err(|err| interpreter.report(err))
+}
+
+/// runs the program for `--profile`, then shows where the time went.
+/// the times are shown even if the program fails
+fn profile(parsed: ApLang<Parsed>, folded: Option<PathBuf>) -> Result<()> {
+    let profiler = Profiler::new();
+    let mut interpreter = parsed.interpreter().with_profiler(profiler.clone());
+
+    let result = interpreter.interpret();
+    let profile = profiler.finish();
+
+    eprint!("{}", profile.table());
+    if let Some(path) = folded {
+        fs::write(&path, profile.folded()).map_err(|err| {
+            miette!("could not write the profile to {}\n{}", path.display(), err)
+        })?;
+    }
+
+    result.map_err(|err| interpreter.report(err))
+}
+
+/// runs the program for `--coverage`, then writes which lines and branches ran.
+/// the file is written even if the program fails
+fn coverage(parsed: ApLang<Parsed>, path: &Path) -> Result<()> {
+    let coverage = Coverage::new();
+    let mut interpreter = parsed.interpreter().with_debugger(coverage.clone());
+
+    let result = interpreter.interpret();
+    fs::write(path, coverage.lcov()).map_err(|err| {
+        miette!("could not write the coverage to {}\n{}", path.display(), err)
+    })?;
+
+    result.map_err(|err| interpreter.report(err))
+}
+
+fn write_trace(path: &Path, contents: String) -> Result<()> {
+    fs::write(path, contents)
+        .map_err(|err| miette!("could not write the trace file {}\n{}", path.display(), err))
+}
+
+/// reads the program from wherever the command line says it is
+fn load(file: Option<PathBuf>, eval: Option<Arc<str>>, eval_stdin: bool) -> Result<ApLang> {
+    if let Some(file_path) = file {
+        ApLang::new_from_file(file_path.clone()).map_err(|err| match err.kind() {
+            ErrorKind::NotFound => miette!("Could not read file {}", file_path.display(),),
+            other_err => miette!(
+                "Critical Failure. Could not read file! {} {err:?}",
+                file_path.display(),
+            ),
+        })
+    } else if let Some(eval) = eval {
+        Ok(ApLang::new_from_stdin(eval))
+    } else if eval_stdin {
+        let mut buffer = String::new();
+
+        io::stdin()
+            .read_to_string(&mut buffer)
+            .map_err(|err| miette!("failed to read from stdin\n{}", err))?;
+        Ok(ApLang::new_from_stdin(buffer))
+    } else {
+        unreachable!()
+    }
+}
+
+/// `aplang lint`. reports the lints of a program without running it
+fn lint(command: LintCommand) -> Result<()> {
+    let parsed = load(command.file, command.eval, false)?
+        .lex()
+        .map_err(Reports::from)?
+        .parse()
+        .map_err(Reports::from)?;
+
+    report_lints(parsed.lint(command.lints.lints()))
+}
+
+/// `aplang fmt`. rewrites files in the standard style, or with `--check` only says which ones are not
+fn fmt(command: FmtCommand) -> Result<()> {
+    let mut files = vec![];
+    for path in &command.paths {
+        find_sources(path, &mut files)?;
+    }
+
+    let mut unformatted = 0;
+    for file in files {
+        let source = fs::read_to_string(&file)
+            .map_err(|err| miette!("Could not read file {}\n{}", file.display(), err))?;
+        let file_name = file.display().to_string();
+
+        let formatted = parser::format_source(source.as_str(), &file_name, command.keyword_case.into())
+            .map_err(Reports::from)?;
+        if formatted == source {
+            continue;
+        }
+
+        if command.check {
+            println!("would reformat {file_name}");
+            unformatted += 1;
+        } else {
+            fs::write(&file, formatted)
+                .map_err(|err| miette!("Could not write file {file_name}\n{}", err))?;
+        }
+    }
+
+    if unformatted > 0 {
+        return Err(miette!(
+            help = "run `aplang fmt` without --check to format them",
+            "{unformatted} file(s) are not formatted"
+        ));
+    }
+    Ok(())
+}
+
+/// collects the path if it is a file, or every .ap file under it if it is a directory
+fn find_sources(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)
+        .and_then(|entries| entries.map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<Vec<_>>>())
+        .map_err(|err| miette!("Could not read directory {}\n{}", path.display(), err))?;
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() || entry.extension().is_some_and(|extension| extension == "ap") {
+            find_sources(&entry, files)?;
+        }
+    }
+    Ok(())
+}
+
+/// shows the warnings, and fails if any lint was denied
+fn report_lints(reports: Vec<Report>) -> Result<()> {
+    let (errors, warnings): (Vec<_>, Vec<_>) = reports
+        .into_iter()
+        .partition(|report| report.severity() == Some(Severity::Error));
+
+    for warning in warnings {
+        eprintln!("{warning:?}");
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Reports::from(errors).into())
+    }
+}
+
+/// undoes anything STYLE did to the terminal
+fn reset_style() {
+    print!("\x1b[0m");
+    let _ = io::stdout().flush();
+}
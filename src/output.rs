@@ -50,4 +50,95 @@ macro_rules! display_error {
     ($($tee:tt)*) => {
         eprint!($($tee)*)
     };
-}
\ No newline at end of file
+}
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+/// somewhere the output of a program goes.
+/// everything DISPLAY, STYLE and the other standard library functions print is written to one
+pub trait Output {
+    /// writes the text as is. there is only a newline if the text has one
+    fn write(&mut self, text: &str);
+
+    /// makes sure everything written so far shows up. called before waiting on INPUT
+    fn flush(&mut self) {}
+}
+
+/// the terminal, or the javascript callback on wasm. this is the default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stdout;
+
+impl Output for Stdout {
+    fn write(&mut self, text: &str) {
+        display!("{}", text);
+    }
+
+    fn flush(&mut self) {
+        #[cfg(not(feature = "wasm"))]
+        {
+            use std::io::Write;
+            let _ = io::stdout().flush();
+        }
+    }
+}
+
+/// keeps everything that is written so it can be looked at after the program runs.
+/// clones share the same text
+#[derive(Debug, Default, Clone)]
+pub struct Buffer {
+    text: Rc<RefCell<String>>,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// everything written so far
+    pub fn contents(&self) -> String {
+        self.text.borrow().clone()
+    }
+
+    /// everything written so far, leaving the buffer empty
+    pub fn take(&self) -> String {
+        self.text.take()
+    }
+}
+
+impl Output for Buffer {
+    fn write(&mut self, text: &str) {
+        self.text.borrow_mut().push_str(text);
+    }
+}
+
+/// writes into anything that implements `io::Write`, like a file.
+/// errors are ignored so a failing writer does not stop the program
+#[derive(Debug)]
+pub struct Writer<W: io::Write>(pub W);
+
+impl<W: io::Write> Output for Writer<W> {
+    fn write(&mut self, text: &str) {
+        let _ = self.0.write_all(text.as_bytes());
+    }
+
+    fn flush(&mut self) {
+        let _ = self.0.flush();
+    }
+}
+
+/// writes everything to both outputs, like the `tee` command
+#[derive(Debug)]
+pub struct Tee<A: Output, B: Output>(pub A, pub B);
+
+impl<A: Output, B: Output> Output for Tee<A, B> {
+    fn write(&mut self, text: &str) {
+        self.0.write(text);
+        self.1.write(text);
+    }
+
+    fn flush(&mut self) {
+        self.0.flush();
+        self.1.flush();
+    }
+}
@@ -1,8 +1,9 @@
 use crate::lexer::token::Token;
+use crate::parser::symbol::Symbol;
 use miette::SourceSpan;
 use std::hash::{Hash, Hasher};
 use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 // To facilitate better error handling down the line,
 // we're going to store the tokens that the thing came from
 // so we can report back to them later
@@ -140,6 +141,8 @@ pub enum Expr {
     Assign(Arc<Assignment>),
 
     Set(Arc<Set>),
+
+    Lambda(Arc<Lambda>),
 }
 #[derive(Debug, Clone)]
 pub struct ExprLiteral {
@@ -174,6 +177,7 @@ pub struct Grouping {
 #[derive(Debug, Clone)]
 pub struct ProcCall {
     pub ident: String,
+    pub symbol: Symbol,
     pub arguments: Vec<Expr>,
     pub arguments_spans: Vec<SourceSpan>,
 
@@ -195,17 +199,18 @@ pub struct List {
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub ident: String,
+    pub symbol: Symbol,
     pub token: Token,
 }
 impl Hash for Variable {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.ident.hash(state);
+        self.symbol.hash(state);
     }
 }
 
 impl PartialEq for Variable {
     fn eq(&self, other: &Self) -> bool {
-        self.ident.eq(&other.ident)
+        self.symbol.eq(&other.symbol)
     }
 }
 
@@ -215,10 +220,20 @@ impl Eq for Variable {}
 pub struct Assignment {
     pub target: Arc<Variable>,
     pub value: Expr,
+    /// set by the resolver. true if the assignment changes the variable from where its
+    /// PROCEDURE was written, instead of creating a new variable for the call
+    pub outer: OnceLock<bool>,
 
     pub ident_token: Token,
     pub arrow_token: Token,
 }
+
+impl Assignment {
+    /// if the assignment changes the variable from where its PROCEDURE was written
+    pub fn is_outer(&self) -> bool {
+        self.outer.get().copied().unwrap_or(false)
+    }
+}
 #[derive(Debug, Clone)]
 pub struct Set {
     pub target: Expr,
@@ -232,6 +247,15 @@ pub struct Set {
     pub arrow_token: Token,
 }
 
+#[derive(Debug, Clone)]
+pub struct Lambda {
+    pub params: Vec<Variable>,
+    pub body: Stmt,
+
+    pub proc_token: Token,
+    pub parens: (Token, Token),
+}
+
 #[derive(Debug, Clone)]
 pub enum Literal {
     Number(f64),
@@ -440,6 +464,9 @@ pub mod pretty {
                         std::iter::once(Box::new(set.value.clone()) as Box<dyn TreePrinter>),
                     ),
                 ),
+                Expr::Lambda(lambda) => Box::new(std::iter::once(
+                    Box::new(lambda.body.clone()) as Box<dyn TreePrinter>
+                )),
             }
         }
 
@@ -486,6 +513,16 @@ pub mod pretty {
                     write!(f, "{} <- {}", assignment.target, assignment.value)
                 }
                 Expr::Set(set) => write!(f, "{}[{}] = {}", set.target, set.arrow_token, set.value),
+                Expr::Lambda(lambda) => {
+                    let params = lambda
+                        .params
+                        .iter()
+                        .map(|var| var.ident.clone())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+
+                    write!(f, "procedure ({}) {}", params, lambda.body)
+                }
             }
         }
     }
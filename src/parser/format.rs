@@ -0,0 +1,441 @@
+use crate::lexer::token::{Comment, Token};
+use crate::lexer::Lexer;
+use crate::parser::ast::*;
+use crate::parser::walk::first_token;
+use crate::parser::Parser;
+use miette::{miette, Report};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// how keywords like `IF` and `PROCEDURE` are written
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum KeywordCase {
+    #[default]
+    Upper,
+    Lower,
+}
+
+const INDENT: &str = "    ";
+
+/// rewrites a file in the canonical style, keeping its comments
+pub fn format_source(
+    source: impl Into<Arc<str>>,
+    file_name: &str,
+    case: KeywordCase,
+) -> Result<String, Vec<Report>> {
+    let source = source.into();
+    let (tokens, comments) = Lexer::scan_with_comments(source.clone(), file_name.to_string())?;
+    let ast = Parser::new(tokens, source.clone(), file_name).parse()?;
+
+    let formatted = Formatter::new(&source, comments, case).format(&ast);
+
+    // never hand back code that does not work anymore
+    let reparsed = Lexer::scan(formatted.as_str(), file_name.to_string())
+        .and_then(|tokens| Parser::new(tokens, formatted.as_str().into(), file_name).parse());
+    if reparsed.is_err() {
+        return Err(vec![miette!(
+            help = "this is a bug in the formatter, the file was left alone",
+            "formatting {file_name} would break it"
+        )]);
+    }
+
+    Ok(formatted)
+}
+
+/// prints a syntax tree back out as source code.
+///
+/// comments are not part of the tree, so they are put back in by where they were in the source.
+/// a comment on its own line stays on its own line above the next statement,
+/// and a comment after some code stays at the end of that line
+pub struct Formatter<'a> {
+    source: &'a str,
+    comments: VecDeque<Comment>,
+    case: KeywordCase,
+
+    out: String,
+    indent: usize,
+    /// where the last thing written ends in the source
+    last: usize,
+    /// if nothing has been written to the current line yet
+    line_start: bool,
+    /// if nothing has been written since a `{`
+    block_start: bool,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(source: &'a str, comments: Vec<Comment>, case: KeywordCase) -> Self {
+        Self {
+            source,
+            comments: comments.into(),
+            case,
+            out: String::new(),
+            indent: 0,
+            last: 0,
+            line_start: true,
+            block_start: true,
+        }
+    }
+
+    pub fn format(mut self, ast: &Ast) -> String {
+        self.statements(&ast.program);
+        self.comments_before(self.source.len());
+
+        self.out
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.line_start {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+            self.line_start = false;
+        }
+        self.block_start = false;
+        self.out.push_str(text);
+    }
+
+    /// writes a token as it was written in the source
+    fn token(&mut self, token: &Token) {
+        self.write(&token.lexeme);
+        self.mark(token);
+    }
+
+    fn keyword(&mut self, keyword: &str) {
+        match self.case {
+            KeywordCase::Upper => self.write(keyword),
+            KeywordCase::Lower => self.write(&keyword.to_lowercase()),
+        }
+    }
+
+    /// everything up to the end of the token has been written
+    fn mark(&mut self, token: &Token) {
+        self.last = self.last.max(token.span.offset() + token.span.len());
+    }
+
+    /// ends the line, along with any comment that was at the end of it in the source
+    fn newline(&mut self) {
+        let mut trailing = vec![];
+        while let Some(comment) = self.comments.front() {
+            let start = comment.span.offset();
+            let same_line = start < self.last || !self.source[self.last..start].contains('\n');
+            if !same_line {
+                break;
+            }
+            trailing.push(self.comments.pop_front().expect("there is a comment"));
+        }
+
+        let mut trailing = trailing.into_iter();
+        if let Some(comment) = trailing.next() {
+            self.write(&format!(" //{}", comment.text));
+            self.last = self.last.max(comment.span.offset() + comment.span.len());
+        }
+        self.out.push('\n');
+        self.line_start = true;
+
+        // comments from the middle of something that was split over lines
+        for comment in trailing {
+            self.comment(&comment);
+        }
+    }
+
+    /// writes the comments on their own lines before something that starts at the offset
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.comments.front() {
+            if comment.span.offset() >= offset {
+                break;
+            }
+            let comment = self.comments.pop_front().expect("there is a comment");
+
+            self.gap(comment.span.offset());
+            self.comment(&comment);
+        }
+    }
+
+    fn comment(&mut self, comment: &Comment) {
+        self.write(&format!("//{}", comment.text));
+        self.last = self.last.max(comment.span.offset() + comment.span.len());
+        self.out.push('\n');
+        self.line_start = true;
+    }
+
+    /// keeps a blank line from the source before something that starts at the offset
+    fn gap(&mut self, offset: usize) {
+        if self.block_start || offset < self.last {
+            return;
+        }
+        if self.source[self.last..offset].matches('\n').count() > 1 {
+            self.out.push('\n');
+        }
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            let start = first_token(stmt).span.offset();
+            self.comments_before(start);
+            self.gap(start);
+
+            self.stmt(stmt);
+            self.newline();
+        }
+    }
+
+    /// the body of an IF, a loop or a PROCEDURE. always written as a block
+    fn body(&mut self, body: &Stmt) {
+        match body {
+            Stmt::Block(block) => self.block(block),
+            stmt => {
+                self.write("{");
+                self.indented(|formatter| formatter.statements(std::slice::from_ref(stmt)));
+                self.write("}");
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.token(&block.lb_token);
+        self.indented(|formatter| {
+            formatter.statements(&block.statements);
+            formatter.comments_before(block.rb_token.span.offset());
+        });
+        self.token(&block.rb_token);
+    }
+
+    fn indented(&mut self, inside: impl FnOnce(&mut Self)) {
+        self.newline();
+        self.indent += 1;
+        self.block_start = true;
+
+        inside(self);
+
+        self.indent -= 1;
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) => self.expr(expr),
+            Stmt::If(if_stmt) => {
+                self.keyword("IF");
+                self.mark(&if_stmt.if_token);
+                self.write(" (");
+                self.expr(&if_stmt.condition);
+                self.write(") ");
+                self.body(&if_stmt.then_branch);
+
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    self.write(" ");
+                    self.keyword("ELSE");
+                    self.write(" ");
+                    match else_branch {
+                        // ELSE IF stays on one line
+                        Stmt::If(_) => self.stmt(else_branch),
+                        _ => self.body(else_branch),
+                    }
+                }
+            }
+            Stmt::RepeatTimes(repeat) => {
+                self.keyword("REPEAT");
+                self.write(" ");
+                self.expr(&repeat.count);
+                self.write(" ");
+                self.keyword("TIMES");
+                self.write(" ");
+                self.body(&repeat.body);
+            }
+            Stmt::RepeatUntil(repeat) => {
+                self.keyword("REPEAT");
+                self.write(" ");
+                self.keyword("UNTIL");
+                self.write(" (");
+                self.expr(&repeat.condition);
+                self.write(") ");
+                self.body(&repeat.body);
+            }
+            Stmt::ForEach(for_each) => {
+                self.keyword("FOR");
+                self.write(" ");
+                self.keyword("EACH");
+                self.write(" ");
+                self.token(&for_each.item_token);
+                self.write(" ");
+                self.keyword("IN");
+                self.write(" ");
+                self.expr(&for_each.list);
+                self.write(" ");
+                self.body(&for_each.body);
+            }
+            Stmt::ProcDeclaration(proc) => {
+                if proc.exported {
+                    self.keyword("EXPORT");
+                    self.write(" ");
+                }
+                self.keyword("PROCEDURE");
+                self.write(" ");
+                self.token(&proc.name_token);
+                self.params(&proc.params);
+                self.write(" ");
+                self.body(&proc.body);
+            }
+            Stmt::Block(block) => self.block(block),
+            Stmt::Return(return_stmt) => {
+                self.keyword("RETURN");
+                self.mark(&return_stmt.token);
+                if let Some(data) = &return_stmt.data {
+                    self.write(" ");
+                    self.expr(data);
+                }
+            }
+            Stmt::Continue(continue_stmt) => {
+                self.keyword("CONTINUE");
+                self.mark(&continue_stmt.token);
+            }
+            Stmt::Break(break_stmt) => {
+                self.keyword("BREAK");
+                self.mark(&break_stmt.token);
+            }
+            Stmt::Import(import) => {
+                self.keyword("IMPORT");
+                self.write(" ");
+                match import.only_functions.as_deref() {
+                    Some([function]) => {
+                        self.token(function);
+                        self.write(" ");
+                        self.keyword("FROM");
+                        self.write(" ");
+                    }
+                    Some(functions) => {
+                        self.write("[");
+                        for (i, function) in functions.iter().enumerate() {
+                            if i > 0 {
+                                self.write(", ");
+                            }
+                            self.token(function);
+                        }
+                        self.write("] ");
+                        self.keyword("FROM");
+                        self.write(" ");
+                    }
+                    None => {}
+                }
+                self.keyword("MOD");
+                self.write(" ");
+                self.token(&import.module_name);
+            }
+        }
+    }
+
+    fn params(&mut self, params: &[Variable]) {
+        self.write("(");
+        for (i, param) in params.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.token(&param.token);
+        }
+        self.write(")");
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(literal) => match literal.value {
+                Literal::True => self.keyword("TRUE"),
+                Literal::False => self.keyword("FALSE"),
+                Literal::Null => self.keyword("NULL"),
+                Literal::Number(_) | Literal::String(_) => self.token(&literal.token),
+            },
+            Expr::Binary(binary) => {
+                self.expr(&binary.left);
+                self.write(" ");
+                match binary.operator {
+                    BinaryOp::EqualEqual => self.write("=="),
+                    BinaryOp::NotEqual => self.write("!="),
+                    BinaryOp::Less => self.write("<"),
+                    BinaryOp::LessEqual => self.write("<="),
+                    BinaryOp::Greater => self.write(">"),
+                    BinaryOp::GreaterEqual => self.write(">="),
+                    BinaryOp::Plus => self.write("+"),
+                    BinaryOp::Minus => self.write("-"),
+                    BinaryOp::Star => self.write("*"),
+                    BinaryOp::Slash => self.write("/"),
+                    BinaryOp::Modulo => self.keyword("MOD"),
+                }
+                self.write(" ");
+                self.expr(&binary.right);
+            }
+            Expr::Logical(logical) => {
+                self.expr(&logical.left);
+                self.write(" ");
+                match logical.operator {
+                    LogicalOp::And => self.keyword("AND"),
+                    LogicalOp::Or => self.keyword("OR"),
+                }
+                self.write(" ");
+                self.expr(&logical.right);
+            }
+            Expr::Unary(unary) => {
+                match unary.operator {
+                    UnaryOp::Minus => self.write("-"),
+                    UnaryOp::Not => {
+                        self.keyword("NOT");
+                        self.write(" ");
+                    }
+                }
+                self.expr(&unary.right);
+            }
+            Expr::Grouping(grouping) => {
+                self.write("(");
+                self.expr(&grouping.expr);
+                self.write(")");
+                self.mark(&grouping.parens.1);
+            }
+            Expr::ProcCall(call) => {
+                self.token(&call.token);
+                self.write("(");
+                self.exprs(&call.arguments);
+                self.write(")");
+                self.mark(&call.parens.1);
+            }
+            Expr::Access(access) => {
+                self.expr(&access.list);
+                self.write("[");
+                self.expr(&access.key);
+                self.write("]");
+                self.mark(&access.brackets.1);
+            }
+            Expr::List(list) => {
+                self.write("[");
+                self.exprs(&list.items);
+                self.write("]");
+                self.mark(&list.brackets.1);
+            }
+            Expr::Variable(variable) => self.token(&variable.token),
+            Expr::Assign(assignment) => {
+                self.token(&assignment.ident_token);
+                self.write(" <- ");
+                self.expr(&assignment.value);
+            }
+            Expr::Set(set) => {
+                self.expr(&set.list);
+                self.write("[");
+                self.expr(&set.idx);
+                self.write("] <- ");
+                self.expr(&set.value);
+            }
+            Expr::Lambda(lambda) => {
+                self.keyword("PROCEDURE");
+                self.write(" ");
+                self.params(&lambda.params);
+                self.write(" ");
+                self.body(&lambda.body);
+            }
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use crate::lexer::token::Token;
+use crate::parser::ast::*;
+use crate::parser::symbol::Symbol;
+use crate::parser::walk::{first_expr_token, first_token, walk, Node};
+use crate::standard_library::Modules;
+use miette::{LabeledSpan, MietteDiagnostic, NamedSource, Report, Severity};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// code that works, but is probably a mistake
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// a variable is created but never used
+    UnusedVariable,
+    /// code after a RETURN, BREAK or CONTINUE
+    UnreachableCode,
+    /// the condition of a REPEAT UNTIL loop can never change
+    UnchangingCondition,
+    /// a PROCEDURE has the same name as one from the standard library
+    ShadowedStd,
+    /// a value is assigned to a variable and replaced before it is used
+    UnusedAssignment,
+    /// a PROCEDURE creates a variable with the same name as one from where it was written
+    ShadowedVariable,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedVariable,
+        Rule::UnreachableCode,
+        Rule::UnchangingCondition,
+        Rule::ShadowedStd,
+        Rule::UnusedAssignment,
+        Rule::ShadowedVariable,
+    ];
+
+    /// the id of the rule. used as the code of its warnings
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused_variable",
+            Rule::UnreachableCode => "unreachable_code",
+            Rule::UnchangingCondition => "unchanging_condition",
+            Rule::ShadowedStd => "shadowed_std",
+            Rule::UnusedAssignment => "unused_assignment",
+            Rule::ShadowedVariable => "shadowed_variable",
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.name() == name)
+            .ok_or_else(|| format!("there is no lint rule named `{name}`"))
+    }
+}
+
+/// how a rule is reported
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Level {
+    /// not reported at all
+    Allow,
+    /// reported, but the program is still fine
+    #[default]
+    Warn,
+    /// reported as an error
+    Deny,
+}
+
+/// the level of each rule. every rule warns unless it is changed
+#[derive(Clone, Debug, Default)]
+pub struct Lints {
+    levels: HashMap<Rule, Level>,
+}
+
+impl Lints {
+    pub fn set(&mut self, rule: Rule, level: Level) -> &mut Self {
+        self.levels.insert(rule, level);
+        self
+    }
+
+    pub fn level(&self, rule: Rule) -> Level {
+        self.levels.get(&rule).copied().unwrap_or_default()
+    }
+}
+
+/// the variables of a PROCEDURE, or of the top level
+#[derive(Default)]
+struct Scope {
+    /// variables created by assigning to them, and where they were first assigned
+    assigned: HashMap<Symbol, Token>,
+    /// parameters and FOR EACH items. they are never reported
+    bound: HashSet<Symbol>,
+    read: HashSet<Symbol>,
+}
+
+impl Scope {
+    fn has(&self, symbol: Symbol) -> bool {
+        self.assigned.contains_key(&symbol) || self.bound.contains(&symbol)
+    }
+}
+
+/// finds code that is probably a mistake.
+///
+/// unlike the resolver, nothing it finds stops the program from running
+pub struct Linter {
+    named_source: NamedSource<Arc<str>>,
+    lints: Lints,
+    /// every function in the standard library and the module it is in
+    std: HashMap<String, String>,
+
+    /// the scopes around the code being linted, innermost last
+    scopes: Vec<Scope>,
+
+    /// the warnings along with where they start, so they can be sorted
+    reports: Vec<(usize, Report)>,
+}
+
+impl Linter {
+    pub fn new(source: Arc<str>, file_name: &str, lints: Lints) -> Self {
+        Self {
+            named_source: NamedSource::new(file_name, source),
+            lints,
+            std: Modules::init().functions(),
+            scopes: vec![],
+            reports: vec![],
+        }
+    }
+
+    /// lints the program. the reports are in the order they appear in the source
+    pub fn lint(mut self, ast: &Ast) -> Vec<Report> {
+        self.scope(&[], &ast.program);
+
+        self.reports.sort_by_key(|(offset, _)| *offset);
+        self.reports.into_iter().map(|(_, report)| report).collect()
+    }
+
+    fn warn(
+        &mut self,
+        rule: Rule,
+        message: String,
+        labels: Vec<LabeledSpan>,
+        help: impl Into<String>,
+    ) {
+        let severity = match self.lints.level(rule) {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+
+        let offset = labels
+            .iter()
+            .map(|label| label.offset())
+            .min()
+            .unwrap_or_default();
+        let diagnostic = MietteDiagnostic::new(message)
+            .with_code(rule.name())
+            .with_severity(severity)
+            .with_labels(labels)
+            .with_help(help);
+        let report = Report::new(diagnostic).with_source_code(self.named_source.clone());
+        self.reports.push((offset, report));
+    }
+
+    /// lints the top level, or the body of a PROCEDURE
+    fn scope(&mut self, params: &[Variable], body: &[Stmt]) {
+        let mut scope = Scope {
+            bound: params.iter().map(|param| param.symbol).collect(),
+            ..Scope::default()
+        };
+        for stmt in body {
+            walk(Node::Stmt(stmt), false, &mut |node| match node {
+                // the resolver decided which assignments change a variable from outside
+                Node::Expr(Expr::Assign(assignment)) if !assignment.is_outer() => {
+                    scope
+                        .assigned
+                        .entry(assignment.target.symbol)
+                        .or_insert_with(|| assignment.ident_token.clone());
+                }
+                Node::Stmt(Stmt::ForEach(for_each)) => {
+                    scope.bound.insert(for_each.item.symbol);
+                }
+                _ => {}
+            });
+        }
+        for (symbol, token) in &scope.assigned {
+            self.shadowed_variable(*symbol, token);
+        }
+        self.scopes.push(scope);
+
+        self.statements(body);
+        for stmt in body {
+            walk(Node::Stmt(stmt), false, &mut |node| self.visit(node));
+        }
+
+        let scope = self.scopes.pop().expect("a scope was pushed");
+        for (symbol, token) in scope.assigned {
+            if scope.read.contains(&symbol) || scope.bound.contains(&symbol) {
+                continue;
+            }
+
+            self.warn(
+                Rule::UnusedVariable,
+                format!("the variable `{}` is never used", token.lexeme),
+                vec![LabeledSpan::at(token.span(), "created here")],
+                "remove the variable, or use it",
+            );
+        }
+    }
+
+    fn visit(&mut self, node: Node) {
+        match node {
+            Node::Expr(Expr::Variable(variable)) => self.read(variable.symbol),
+            // calling a variable that holds a procedure uses it
+            Node::Expr(Expr::ProcCall(call)) => self.read(call.symbol),
+            Node::Expr(Expr::Lambda(lambda)) => {
+                self.scope(&lambda.params, std::slice::from_ref(&lambda.body))
+            }
+            Node::Stmt(Stmt::ProcDeclaration(proc)) => {
+                self.shadowed_std(proc);
+                self.scope(&proc.params, std::slice::from_ref(&proc.body));
+            }
+            Node::Stmt(Stmt::Block(block)) => self.statements(&block.statements),
+            Node::Stmt(Stmt::RepeatUntil(repeat)) => self.unchanging_condition(repeat),
+            _ => {}
+        }
+    }
+
+    /// marks the variable the symbol refers to as used
+    fn read(&mut self, symbol: Symbol) {
+        if let Some(scope) = self.scopes.iter_mut().rev().find(|scope| scope.has(symbol)) {
+            scope.read.insert(symbol);
+        }
+    }
+
+    fn is_std(&self, name: &str) -> bool {
+        self.std.contains_key(name)
+    }
+
+    /// lints a list of statements that run one after another
+    fn statements(&mut self, statements: &[Stmt]) {
+        for (i, stmt) in statements.iter().enumerate() {
+            if let Some(next) = statements.get(i + 1) {
+                self.unreachable_code(stmt, next);
+            }
+            if let Stmt::Expr(expr) = stmt {
+                if let Expr::Assign(assignment) = expr.as_ref() {
+                    self.unused_assignment(assignment, &statements[i + 1..]);
+                }
+            }
+        }
+    }
+
+    fn unreachable_code(&mut self, stmt: &Stmt, next: &Stmt) {
+        let (token, label) = match stmt {
+            Stmt::Return(return_stmt) => (&return_stmt.token, "the PROCEDURE returns here"),
+            Stmt::Break(break_stmt) => (&break_stmt.token, "the loop stops here"),
+            Stmt::Continue(continue_stmt) => (&continue_stmt.token, "the loop moves on here"),
+            _ => return,
+        };
+
+        self.warn(
+            Rule::UnreachableCode,
+            format!("code after {} never runs", token.lexeme),
+            vec![
+                LabeledSpan::at(token.span(), label),
+                LabeledSpan::at(first_token(next).span(), "so this never runs"),
+            ],
+            format!("remove the code after {}, or move it before", token.lexeme),
+        );
+    }
+
+    /// an assignment that is replaced before anything could read it
+    fn unused_assignment(&mut self, assignment: &Assignment, after: &[Stmt]) {
+        let symbol = assignment.target.symbol;
+
+        for stmt in after {
+            if let Stmt::Expr(expr) = stmt {
+                if let Expr::Assign(next) = expr.as_ref() {
+                    let value = Node::Expr(&next.value);
+                    if next.target.symbol == symbol && !self.might_read(value, symbol) {
+                        self.warn(
+                            Rule::UnusedAssignment,
+                            format!(
+                                "the value assigned to `{}` is never used",
+                                next.target.ident
+                            ),
+                            vec![
+                                LabeledSpan::at(assignment.ident_token.span(), "assigned here"),
+                                LabeledSpan::at(next.ident_token.span(), "replaced here"),
+                            ],
+                            "remove the first assignment, or use the value before replacing it",
+                        );
+                        return;
+                    }
+                }
+            }
+
+            if self.might_read(Node::Stmt(stmt), symbol) {
+                return;
+            }
+        }
+    }
+
+    /// if running the node could read the variable, or leave the list of statements it is in
+    fn might_read(&self, node: Node, symbol: Symbol) -> bool {
+        let mut reads = false;
+        walk(node, true, &mut |node| match node {
+            Node::Expr(Expr::Variable(variable)) => reads |= variable.symbol == symbol,
+            // a PROCEDURE could read the variable, a std function can not
+            Node::Expr(Expr::ProcCall(call)) => {
+                reads |= call.symbol == symbol || !self.is_std(&call.ident)
+            }
+            Node::Stmt(Stmt::Return(_) | Stmt::Break(_) | Stmt::Continue(_)) => reads = true,
+            _ => {}
+        });
+        reads
+    }
+
+    /// a variable created in a PROCEDURE, when one from where it was written has the same name.
+    /// assigning to it does not change the one outside, which is easy to miss
+    fn shadowed_variable(&mut self, symbol: Symbol, token: &Token) {
+        let Some(outer) = self.scopes.iter().rev().find(|outer| outer.has(symbol)) else {
+            return;
+        };
+
+        let mut labels = vec![LabeledSpan::at(
+            token.span(),
+            "this creates a new variable for each call",
+        )];
+        if let Some(first) = outer.assigned.get(&symbol) {
+            labels.push(LabeledSpan::at(
+                first.span(),
+                "instead of changing this one",
+            ));
+        }
+        self.warn(
+            Rule::ShadowedVariable,
+            format!(
+                "`{}` is a new variable, not the one from outside of the PROCEDURE",
+                token.lexeme
+            ),
+            labels,
+            format!(
+                "a PROCEDURE only changes a variable from outside if it uses it before \
+                assigning it, like `{0} <- {0} + 1`. otherwise give this variable a different name",
+                token.lexeme
+            ),
+        );
+    }
+
+    fn shadowed_std(&mut self, proc: &ProcDeclaration) {
+        let Some(module) = self.std.get(&proc.name) else {
+            return;
+        };
+
+        let label = if module == "CORE" {
+            format!("this replaces the built in `{}`", proc.name)
+        } else {
+            format!(
+                "`{}` is also in the standard library module {module}",
+                proc.name
+            )
+        };
+        self.warn(
+            Rule::ShadowedStd,
+            format!(
+                "the PROCEDURE `{}` has the same name as a std function",
+                proc.name
+            ),
+            vec![LabeledSpan::at(proc.name_token.span(), label)],
+            format!(
+                "give the PROCEDURE a different name, so `{}` can still be used",
+                proc.name
+            ),
+        );
+    }
+
+    /// a REPEAT UNTIL loop that can only stop if its condition changes,
+    /// when nothing in the loop could change it
+    fn unchanging_condition(&mut self, repeat: &RepeatUntil) {
+        let mut variables = HashSet::new();
+        let mut calls = false;
+        walk(
+            Node::Expr(&repeat.condition),
+            false,
+            &mut |node| match node {
+                Node::Expr(Expr::Variable(variable)) => {
+                    variables.insert(variable.symbol);
+                }
+                // a call could give something different each time
+                Node::Expr(Expr::ProcCall(_)) => calls = true,
+                _ => {}
+            },
+        );
+        if calls {
+            return;
+        }
+
+        // `expr` is one of the variables in the condition
+        let read = |expr: &Expr| match expr {
+            Expr::Variable(variable) => variables.contains(&variable.symbol),
+            _ => false,
+        };
+        let mut changes = false;
+        walk(Node::Stmt(&repeat.body), false, &mut |node| match node {
+            Node::Expr(Expr::Assign(assignment)) => {
+                changes |= variables.contains(&assignment.target.symbol)
+            }
+            Node::Expr(Expr::Set(set)) => changes |= read(&set.list),
+            Node::Stmt(Stmt::ForEach(for_each)) => {
+                changes |= variables.contains(&for_each.item.symbol)
+            }
+            // a PROCEDURE could change anything, a std function only changes the lists it is given
+            Node::Expr(Expr::ProcCall(call)) => {
+                changes |= !self.is_std(&call.ident) || call.arguments.iter().any(read)
+            }
+            // the loop can be left another way
+            Node::Stmt(Stmt::Break(_) | Stmt::Return(_)) => changes = true,
+            _ => {}
+        });
+        if changes {
+            return;
+        }
+
+        self.warn(
+            Rule::UnchangingCondition,
+            "this loop never stops".to_string(),
+            vec![
+                LabeledSpan::at(repeat.repeat_token.span(), "this loop"),
+                LabeledSpan::at(
+                    first_expr_token(&repeat.condition).span(),
+                    "waits on this condition",
+                ),
+            ],
+            "nothing inside of the loop changes the condition, so it never becomes true",
+        );
+    }
+}
@@ -1,3 +1,11 @@
 pub mod ast;
+pub mod symbol;
+pub mod walk;
+mod format;
+mod lint;
 mod parser;
+mod resolver;
+pub use format::{format_source, KeywordCase};
+pub use lint::{Level, Linter, Lints, Rule};
 pub use parser::Parser;
+pub use resolver::Resolver;
@@ -6,8 +6,9 @@ use crate::parser::ast::Continue as ContinueStatement;
 use crate::parser::ast::Import as ImportStatement;
 use crate::parser::ast::Return as ReturnValue;
 use crate::parser::ast::*;
+use crate::parser::symbol::Symbol;
 use miette::{miette, LabeledSpan, NamedSource, Report, SourceSpan};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use crate::lexer::token::TokenType::*;
 use crate::parser::ast::If as IfStmt;
@@ -17,9 +18,6 @@ pub struct Parser {
     source: Arc<str>,
     named_source: NamedSource<Arc<str>>,
     current: usize,
-    in_function_scope: bool,
-    _warnings: Vec<Report>,
-    in_loop_scope: bool,
 }
 
 impl Parser {
@@ -27,11 +25,8 @@ impl Parser {
         Self {
             tokens,
             source: source.clone(),
-            in_function_scope: false,
-            in_loop_scope: false,
             named_source: NamedSource::new(file_name, source),
             current: 0,
-            _warnings: vec![],
         }
     }
 
@@ -142,6 +137,23 @@ impl Parser {
             })?
             .clone();
 
+        let (params, _rp_token) = self.parameters()?;
+
+        // parse the body of the function
+        let body = self.statement()?;
+
+        Ok(Stmt::ProcDeclaration(Arc::new(ProcDeclaration {
+            name,
+            params,
+            body,
+            exported,
+            proc_token,
+            name_token,
+        })))
+    }
+
+    /// parses the parameter list of a procedure up to and including the `)`
+    fn parameters(&mut self) -> miette::Result<(Vec<Variable>, Token)> {
         let mut params = vec![];
         if !self.check(&RightParen) {
             loop {
@@ -159,6 +171,7 @@ impl Parser {
 
                 params.push(Variable {
                     ident: token.lexeme.clone(),
+                    symbol: Symbol::intern(&token.lexeme),
                     token,
                 });
 
@@ -168,7 +181,7 @@ impl Parser {
             }
         }
 
-        let _rp_token = self
+        let rp_token = self
             .consume(&RightParen, |token| {
                 let labels = vec![LabeledSpan::at(token.span(), "expected a `)`")];
 
@@ -182,22 +195,40 @@ impl Parser {
             })?
             .clone();
 
-        // cache previous function state and set to true temporarily, since we're in a
-        let function_scope_state_cache = self.in_function_scope;
-        self.in_function_scope = true;
+        Ok((params, rp_token))
+    }
+
+    /// anonymous procedure expression
+    /// PROCEDURE "(" ( IDENT ),* ")" stmt
+    fn lambda(&mut self, proc_token: Token) -> miette::Result<Expr> {
+        let lp_token = self
+            .consume(&LeftParen, |token| {
+                let labels = vec![
+                    LabeledSpan::at(token.span(), "expected a `(`"),
+                    LabeledSpan::at(
+                        proc_token.span(),
+                        "an anonymous PROCEDURE requires `(..)` argument list",
+                    ),
+                ];
+                miette!(
+                    labels = labels,
+                    code = "missing_lp",
+                    help = "a PROCEDURE used as a value requires a argument list in `()` after `PROCEDURE`",
+                    "expected `(` found `{}`",
+                    token.lexeme
+                )
+            })?
+            .clone();
+
+        let (params, rp_token) = self.parameters()?;
 
-        // parse the body of the function
         let body = self.statement()?;
-        // restore the previous function scope state
-        self.in_function_scope = function_scope_state_cache;
 
-        Ok(Stmt::ProcDeclaration(Arc::new(ProcDeclaration {
-            name,
+        Ok(Expr::Lambda(Arc::new(Lambda {
             params,
             body,
-            exported,
             proc_token,
-            name_token,
+            parens: (lp_token, rp_token),
         })))
     }
 
@@ -218,38 +249,19 @@ impl Parser {
         if self.match_token(&Repeat) {
             let repeat_token = self.previous().clone();
 
-            // we're now in a loop
-            let cache_loop_state = self.in_loop_scope;
-            self.in_loop_scope = true;
-
             // this is a repeat until block
-            let result = if self.check(&Until) {
+            return if self.check(&Until) {
                 self.repeat_until(repeat_token)
             } else {
                 self.repeat_times(repeat_token)
             };
-
-            // we *might* not be in a loop anymore
-            self.in_loop_scope = cache_loop_state;
-
-            // finish
-            return result;
         }
 
         if self.match_token(&For) {
             let for_token = self.previous().clone();
 
-            // we're now in a loop
-            let cache_loop_state = self.in_loop_scope;
-            self.in_loop_scope = true;
-            
             // parse FOR EACH loop
-            let result = self.for_each(for_token);
-
-            // we *might* not be in a loop anymore
-            self.in_loop_scope = cache_loop_state;
-            
-            return result
+            return self.for_each(for_token);
         }
 
         // { expr }
@@ -321,45 +333,26 @@ impl Parser {
         ))
     }
 
+    // BREAK, CONTINUE and RETURN are checked to be in the right place by the resolver
     fn break_statement(&mut self, break_token: Token) -> miette::Result<Stmt> {
-        if !self.in_loop_scope {
-            // todo improve this message
-            return Err(miette! {
-                "BREAK can only be called in a loop"
-            });
-        }
-
         Ok(Stmt::Break(Arc::new(BreakStatement { token: break_token })))
     }
 
     fn continue_statement(&mut self, continue_token: Token) -> miette::Result<Stmt> {
-        if !self.in_loop_scope {
-            // todo: make this error bearable
-            return Err(miette! {
-                "CONTINUE can only be called in a loop",
-            });
-        }
-
         Ok(Stmt::Continue(Arc::new(ContinueStatement {
             token: continue_token,
         })))
     }
 
     fn return_statement(&mut self, return_token: Token) -> miette::Result<Stmt> {
-        if !self.in_function_scope {
-            // todo make this error better
-            return Err(miette! {
-                "RETURN can only be called in a PROCEDURE"
-            });
-        }
-
-        let maybe_value = if !self.match_token(&SoftSemi) {
-            Some(self.expression()?)
-        } else {
+        // a one line body like `{ RETURN x }` ends with the `}` instead of a semicolon
+        let maybe_value = if self.check(&RightBrace) || self.match_token(&SoftSemi) {
             None
+        } else {
+            Some(self.expression()?)
         };
 
-        if maybe_value.is_some() {
+        if maybe_value.is_some() && !self.check(&RightBrace) {
             self.consume(&SoftSemi, |_token| {
                 miette! {
                     "todo: expected semicolon after return statement"
@@ -680,6 +673,7 @@ impl Parser {
         // let item = item_token.lexeme.clone();
         let item = Variable {
             ident: item_token.lexeme.to_string(),
+            symbol: Symbol::intern(&item_token.lexeme),
             token: item_token.clone(),
         };
 
@@ -767,6 +761,7 @@ impl Parser {
                     Assignment {
                         target: variable.clone(),
                         value,
+                        outer: OnceLock::new(),
                         ident_token: variable.token.clone(),
                         arrow_token,
                     }
@@ -1089,6 +1084,12 @@ impl Parser {
         }
         // done parsing literals
 
+        // PROCEDURE "(" ( IDENT ),* ")" stmt
+        if self.match_token(&Procedure) {
+            let proc_token = self.previous().clone();
+            return self.lambda(proc_token);
+        }
+
         // IDENT
         if self.match_token(&Identifier) {
             let token = self.previous().clone();
@@ -1149,6 +1150,7 @@ impl Parser {
                     .collect();
 
                 return Ok(Expr::ProcCall(Arc::new(ProcCall {
+                    symbol: Symbol::intern(&ident),
                     ident,
                     arguments,
                     arguments_spans,
@@ -1158,7 +1160,11 @@ impl Parser {
             }
 
             // ident token
-            return Ok(Expr::Variable(Arc::new(Variable { ident, token })));
+            return Ok(Expr::Variable(Arc::new(Variable {
+                symbol: Symbol::intern(&ident),
+                ident,
+                token,
+            })));
         }
 
         // "(" expr ")"
@@ -1446,18 +1452,6 @@ impl Parser {
     }
 }
 
-pub(super) mod warning {
-    use crate::parser::Parser;
-    use miette::Report;
-
-    impl Parser {
-        pub(super) fn warning(&mut self, report: Report) {
-            self._warnings
-                .push(report.with_source_code(self.named_source.clone()))
-        }
-    }
-}
-
 trait ExpectMiette<T> {
     fn miette_expect(self, report_handler: fn() -> Report) -> T;
 }
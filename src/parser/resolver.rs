@@ -0,0 +1,494 @@
+use crate::lexer::token::{LiteralValue, Token};
+use crate::lexer::Lexer;
+use crate::parser::ast::*;
+use crate::parser::symbol::Symbol;
+use crate::parser::walk::{walk, Node};
+use crate::parser::Parser;
+use crate::standard_library::Modules;
+use miette::{miette, LabeledSpan, NamedSource, Report};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// finds mistakes in a program before it runs.
+///
+/// a variable exists once it has been assigned anywhere in its scope,
+/// the order does not matter. that way a working program is never reported,
+/// even if it creates a variable in one branch and uses it later
+pub struct Resolver {
+    named_source: NamedSource<Arc<str>>,
+    /// user modules are found next to this file
+    file_path: Option<PathBuf>,
+    modules: Modules,
+
+    /// every PROCEDURE that can be called by name and how many arguments it takes.
+    /// None if there are declarations with different numbers of arguments
+    procedures: HashMap<String, Option<usize>>,
+    /// where each PROCEDURE in this file was declared
+    declarations: HashMap<String, Token>,
+    /// an imported user module could not be read,
+    /// so any unknown name might be one of its PROCEDUREs
+    unknown_module: bool,
+
+    /// the variables of each scope around the code being checked, innermost last
+    scopes: Vec<HashSet<Symbol>>,
+    /// how many loops are around the code being checked, inside of its PROCEDURE
+    loops: usize,
+    in_procedure: bool,
+
+    reports: Vec<Report>,
+}
+
+impl Resolver {
+    pub fn new(source: Arc<str>, file_name: &str, file_path: Option<PathBuf>) -> Self {
+        Self {
+            named_source: NamedSource::new(file_name, source),
+            file_path,
+            modules: Modules::init(),
+            procedures: HashMap::new(),
+            declarations: HashMap::new(),
+            unknown_module: false,
+            scopes: vec![],
+            loops: 0,
+            in_procedure: false,
+            reports: vec![],
+        }
+    }
+
+    /// names that exist before the code runs, like the variables
+    /// and PROCEDUREs from earlier inputs of the repl
+    pub fn with_globals(
+        mut self,
+        variables: impl IntoIterator<Item = Symbol>,
+        procedures: impl IntoIterator<Item = (String, usize)>,
+    ) -> Self {
+        self.scopes.push(variables.into_iter().collect());
+        for (name, arity) in procedures {
+            self.add_procedure(name, arity);
+        }
+        self
+    }
+
+    pub fn resolve(mut self, ast: &Ast) -> Result<(), Vec<Report>> {
+        // CORE is always there
+        let core = self.modules.lookup("CORE").expect("CORE is a std module")();
+        for (name, (procedure, _)) in core {
+            self.add_procedure(name, procedure.arity() as usize);
+        }
+
+        // PROCEDUREs can be called before their declaration runs, so find all of them first
+        for stmt in &ast.program {
+            walk(Node::Stmt(stmt), true, &mut |node| match node {
+                Node::Stmt(Stmt::ProcDeclaration(proc)) => self.declare(proc),
+                Node::Stmt(Stmt::Import(import)) => self.import(import),
+                _ => {}
+            });
+        }
+
+        self.body(&[], &ast.program);
+
+        if self.reports.is_empty() {
+            Ok(())
+        } else {
+            Err(self.reports)
+        }
+    }
+
+    fn report(&mut self, report: Report) {
+        self.reports
+            .push(report.with_source_code(self.named_source.clone()));
+    }
+
+    fn add_procedure(&mut self, name: String, arity: usize) {
+        self.procedures
+            .entry(name)
+            .and_modify(|known| {
+                if *known != Some(arity) {
+                    *known = None
+                }
+            })
+            .or_insert(Some(arity));
+    }
+
+    fn declare(&mut self, proc: &ProcDeclaration) {
+        if let Some(first) = self.declarations.get(&proc.name) {
+            let labels = vec![
+                LabeledSpan::at(first.span(), "first declared here"),
+                LabeledSpan::at(proc.name_token.span(), "declared again here"),
+            ];
+            let report = miette!(
+                labels = labels,
+                code = "duplicate_procedure",
+                help = "give one of the PROCEDUREs a different name",
+                "the PROCEDURE `{}` is declared more than once",
+                proc.name,
+            );
+            self.report(report);
+            return;
+        }
+
+        self.declarations
+            .insert(proc.name.clone(), proc.name_token.clone());
+        self.add_procedure(proc.name.clone(), proc.params.len());
+    }
+
+    /// learns the PROCEDUREs an IMPORT brings in
+    fn import(&mut self, import: &Import) {
+        let Some(LiteralValue::String(module_name)) = import.module_name.literal.as_ref() else {
+            unreachable!()
+        };
+
+        let module: Vec<(String, usize)> = if let Some(injector) = self.modules.lookup(module_name)
+        {
+            injector()
+                .into_iter()
+                .map(|(name, (procedure, _))| (name, procedure.arity() as usize))
+                .collect()
+        } else {
+            // whatever is wrong with the module is reported when the IMPORT runs
+            let Some(exports) = self.exports(module_name) else {
+                self.unknown_module = true;
+                return;
+            };
+            exports
+        };
+
+        for (name, arity) in module {
+            let wanted = match &import.only_functions {
+                Some(functions) => functions.iter().any(|function| {
+                    matches!(&function.literal, Some(LiteralValue::String(f)) if *f == name)
+                }),
+                None => true,
+            };
+            if wanted {
+                self.add_procedure(name, arity);
+            }
+        }
+    }
+
+    /// the exported PROCEDUREs of a user module, if it can be read
+    fn exports(&self, module_name: &str) -> Option<Vec<(String, usize)>> {
+        let mut path = self.file_path.clone()?;
+        path.pop();
+        let path = path.join(module_name);
+
+        let source = fs::read_to_string(&path).ok()?;
+        let file_name = path.file_name()?.to_string_lossy().into_owned();
+        let tokens = Lexer::scan(source.clone(), file_name.clone()).ok()?;
+        let ast = Parser::new(tokens, source.into(), &file_name)
+            .parse()
+            .ok()?;
+
+        let mut exports = vec![];
+        for stmt in &ast.program {
+            walk(Node::Stmt(stmt), true, &mut |node| {
+                if let Node::Stmt(Stmt::ProcDeclaration(proc)) = node {
+                    if proc.exported {
+                        exports.push((proc.name.clone(), proc.params.len()));
+                    }
+                }
+            });
+        }
+        Some(exports)
+    }
+
+    /// checks the top level, or the body of a PROCEDURE, in a new scope.
+    ///
+    /// this is also where each assignment in a PROCEDURE is decided. if the PROCEDURE
+    /// uses a variable from where it was written before it first assigns it, going by the
+    /// order the code is written in (like `count <- count + 1`), its assignments change that
+    /// variable. otherwise they create a new variable for each call
+    fn body(&mut self, params: &[Variable], body: &[Stmt]) {
+        // parameters and FOR EACH items always belong to the call
+        let mut scope: HashSet<Symbol> = params.iter().map(|param| param.symbol).collect();
+        for stmt in body {
+            walk(Node::Stmt(stmt), false, &mut |node| {
+                if let Node::Stmt(Stmt::ForEach(for_each)) = node {
+                    scope.insert(for_each.item.symbol);
+                }
+            });
+        }
+
+        let read_first = match self.in_procedure {
+            true => read_first(body),
+            false => HashSet::new(),
+        };
+        let outer: HashSet<Symbol> = read_first
+            .into_iter()
+            .filter(|symbol| !scope.contains(symbol) && self.is_variable(*symbol))
+            .collect();
+
+        for stmt in body {
+            walk(Node::Stmt(stmt), false, &mut |node| {
+                if let Node::Expr(Expr::Assign(assignment)) = node {
+                    let symbol = assignment.target.symbol;
+                    let _ = assignment.outer.set(outer.contains(&symbol));
+                    if !outer.contains(&symbol) {
+                        scope.insert(symbol);
+                    }
+                }
+            });
+        }
+
+        self.scopes.push(scope);
+        for stmt in body {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn procedure(&mut self, params: &[Variable], body: &Stmt) {
+        // a loop around the declaration is not a loop inside of the body
+        let loops = std::mem::take(&mut self.loops);
+        let in_procedure = std::mem::replace(&mut self.in_procedure, true);
+
+        self.body(params, std::slice::from_ref(body));
+
+        self.loops = loops;
+        self.in_procedure = in_procedure;
+    }
+
+    fn in_loop(&mut self, body: &Stmt) {
+        self.loops += 1;
+        self.stmt(body);
+        self.loops -= 1;
+    }
+
+    fn is_variable(&self, symbol: Symbol) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(&symbol))
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) => self.expr(expr),
+            Stmt::If(if_stmt) => {
+                self.expr(&if_stmt.condition);
+                self.stmt(&if_stmt.then_branch);
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::RepeatTimes(repeat) => {
+                self.expr(&repeat.count);
+                self.in_loop(&repeat.body);
+            }
+            Stmt::RepeatUntil(repeat) => {
+                self.expr(&repeat.condition);
+                self.in_loop(&repeat.body);
+            }
+            Stmt::ForEach(for_each) => {
+                self.expr(&for_each.list);
+                self.in_loop(&for_each.body);
+            }
+            Stmt::ProcDeclaration(proc) => self.procedure(&proc.params, &proc.body),
+            Stmt::Block(block) => {
+                for stmt in &block.statements {
+                    self.stmt(stmt);
+                }
+            }
+            Stmt::Return(return_stmt) => {
+                if !self.in_procedure {
+                    let labels = vec![LabeledSpan::at(
+                        return_stmt.token.span(),
+                        "not in a PROCEDURE",
+                    )];
+                    let report = miette!(
+                        labels = labels,
+                        code = "return_outside_procedure",
+                        help = "RETURN ends a PROCEDURE, there is nothing to return from here",
+                        "RETURN can only be used inside of a PROCEDURE",
+                    );
+                    self.report(report);
+                }
+                if let Some(data) = &return_stmt.data {
+                    self.expr(data);
+                }
+            }
+            Stmt::Continue(continue_stmt) => self.loop_only(&continue_stmt.token),
+            Stmt::Break(break_stmt) => self.loop_only(&break_stmt.token),
+            Stmt::Import(_) => {}
+        }
+    }
+
+    /// BREAK and CONTINUE only work inside of a loop
+    fn loop_only(&mut self, token: &Token) {
+        if self.loops > 0 {
+            return;
+        }
+
+        let labels = vec![LabeledSpan::at(token.span(), "not in a loop")];
+        let help = if self.in_procedure {
+            "a loop around a PROCEDURE does not count, use RETURN to leave the PROCEDURE"
+        } else {
+            "use it inside of a REPEAT or FOR EACH loop"
+        };
+        let report = miette!(
+            labels = labels,
+            code = "outside_loop",
+            help = help,
+            "{} can only be used inside of a loop",
+            token.lexeme,
+        );
+        self.report(report);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) => {}
+            Expr::Binary(binary) => {
+                self.expr(&binary.left);
+                self.expr(&binary.right);
+            }
+            Expr::Logical(logical) => {
+                self.expr(&logical.left);
+                self.expr(&logical.right);
+            }
+            Expr::Unary(unary) => self.expr(&unary.right),
+            Expr::Grouping(grouping) => self.expr(&grouping.expr),
+            Expr::ProcCall(call) => {
+                self.call(call);
+                for argument in &call.arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Access(access) => {
+                self.expr(&access.list);
+                self.expr(&access.key);
+            }
+            Expr::List(list) => {
+                for item in &list.items {
+                    self.expr(item);
+                }
+            }
+            Expr::Variable(variable) => self.variable(variable),
+            Expr::Assign(assignment) => self.expr(&assignment.value),
+            Expr::Set(set) => {
+                self.expr(&set.list);
+                self.expr(&set.idx);
+                self.expr(&set.value);
+            }
+            Expr::Lambda(lambda) => self.procedure(&lambda.params, &lambda.body),
+        }
+    }
+
+    fn variable(&mut self, variable: &Variable) {
+        // a PROCEDURE can be used as a value by its name
+        if self.is_variable(variable.symbol)
+            || self.procedures.contains_key(&variable.ident)
+            || self.unknown_module
+        {
+            return;
+        }
+
+        let labels = vec![LabeledSpan::at(
+            variable.token.span(),
+            "could not find variable",
+        )];
+        let mut help = format!(
+            "Make sure to create the variable `{}` before you use it",
+            variable.ident
+        );
+        if self.in_procedure {
+            help.push_str(". A PROCEDURE can only see variables from where it was written");
+        }
+        let report = miette!(
+            labels = labels,
+            code = "undefined_variable",
+            help = help,
+            "the variable `{}` is never created",
+            variable.ident,
+        );
+        self.report(report);
+    }
+
+    fn call(&mut self, call: &ProcCall) {
+        // a variable holding a procedure shadows a PROCEDURE with the same name, and one
+        // holding anything else falls back to the PROCEDURE. which one is called depends
+        // on the value, so calls through a variable are only checked when the program runs
+        if self.is_variable(call.symbol) {
+            return;
+        }
+
+        let arity = match self.procedures.get(&call.ident) {
+            Some(Some(arity)) => *arity,
+            // declared more than once with different arguments
+            Some(None) => return,
+            None if self.unknown_module => return,
+            None => {
+                let help = match self.modules.functions().remove(&call.ident) {
+                    Some(module) => format!(
+                        "`{}` is in the standard library, add `IMPORT MOD \"{module}\"` to use it",
+                        call.ident
+                    ),
+                    None => format!(
+                        "Make sure to declare the PROCEDURE `{}` or import the module it is in",
+                        call.ident
+                    ),
+                };
+                let labels = vec![LabeledSpan::at(call.token.span(), "unknown PROCEDURE")];
+                let report = miette!(
+                    labels = labels,
+                    code = "unknown_procedure",
+                    help = help,
+                    "the PROCEDURE `{}` does not exist",
+                    call.ident,
+                );
+                self.report(report);
+                return;
+            }
+        };
+
+        let found = call.arguments.len();
+        if arity == found {
+            return;
+        }
+
+        let (lp, rp) = &call.parens;
+        let mut labels = vec![LabeledSpan::at(
+            lp.span().offset() + lp.span().len()..rp.span().offset(),
+            format!(
+                "There should be {arity} arg{}; Found {found}",
+                if arity == 1 { "" } else { "s" }
+            ),
+        )];
+        if let Some(declaration) = self.declarations.get(&call.ident) {
+            labels.push(LabeledSpan::at(declaration.span(), "declared here"));
+        }
+        let report = miette!(
+            labels = labels,
+            code = "incorrect_arg_count",
+            help =
+                "Make sure the you are passing in the correct number of arguments to the PROCEDURE",
+            "`{}` is called with the wrong number of arguments",
+            call.ident,
+        );
+        self.report(report);
+    }
+}
+
+/// the variables a PROCEDURE uses before it first assigns them, going by the order
+/// the code is written in. the value of an assignment is used before the variable is assigned
+fn read_first(body: &[Stmt]) -> HashSet<Symbol> {
+    let mut read = HashSet::new();
+    let mut assigned = HashSet::new();
+    for stmt in body {
+        walk(Node::Stmt(stmt), false, &mut |node| match node {
+            Node::Expr(Expr::Variable(variable)) if !assigned.contains(&variable.symbol) => {
+                read.insert(variable.symbol);
+            }
+            Node::Expr(Expr::Assign(assignment)) => {
+                walk(Node::Expr(&assignment.value), false, &mut |node| {
+                    if let Node::Expr(Expr::Variable(variable)) = node {
+                        if !assigned.contains(&variable.symbol) {
+                            read.insert(variable.symbol);
+                        }
+                    }
+                });
+                assigned.insert(assignment.target.symbol);
+            }
+            _ => {}
+        });
+    }
+    read
+}
@@ -0,0 +1,76 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// an interned identifier.
+///
+/// every identifier is turned into a symbol once, while parsing.
+/// the interpreter compares symbols instead of hashing strings
+/// every time a variable is looked up.
+///
+/// each thread has its own symbols, so a symbol only means something
+/// on the thread that parsed it
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<Arc<str>, Symbol>,
+    names: Vec<Arc<str>>,
+}
+
+thread_local! {
+    // shared between every parser on the thread so the symbols from imported modules line up.
+    // names are never removed, so a thread that lives as long as the lsp or the repl keeps
+    // every different identifier it has seen. grade-dir parses on threads that end with it
+    static INTERNER: RefCell<Interner> = RefCell::default();
+}
+
+impl Symbol {
+    /// gets the symbol for an identifier, creating it if it is new
+    pub fn intern(name: &str) -> Self {
+        INTERNER.with_borrow_mut(|interner| {
+            if let Some(symbol) = interner.symbols.get(name) {
+                return *symbol;
+            }
+
+            let symbol = Symbol(interner.names.len() as u32);
+            let name: Arc<str> = name.into();
+            interner.names.push(name.clone());
+            interner.symbols.insert(name, symbol);
+            symbol
+        })
+    }
+
+    /// the identifier this symbol was made from
+    pub fn name(&self) -> Arc<str> {
+        INTERNER.with_borrow(|interner| interner.names[self.0 as usize].clone())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[test]
+fn intern_is_stable() {
+    let a = Symbol::intern("symbol_test_a");
+    let b = Symbol::intern("symbol_test_b");
+
+    assert_ne!(a, b);
+    assert_eq!(a, Symbol::intern("symbol_test_a"));
+    assert_eq!(&*b.name(), "symbol_test_b");
+}
+
+#[test]
+fn threads_have_their_own_symbols() {
+    Symbol::intern("symbol_test_main");
+
+    let first = std::thread::spawn(|| Symbol::intern("symbol_test_thread"))
+        .join()
+        .unwrap();
+    assert_eq!(first, Symbol(0));
+}
@@ -0,0 +1,129 @@
+use crate::lexer::token::Token;
+use crate::parser::ast::*;
+
+/// a statement or an expression
+#[derive(Copy, Clone)]
+pub enum Node<'a> {
+    Stmt(&'a Stmt),
+    Expr(&'a Expr),
+}
+
+/// calls `visit` on a node and everything inside of it.
+/// the bodies of PROCEDUREs are only visited if `procedures` is set
+pub fn walk<'a>(node: Node<'a>, procedures: bool, visit: &mut dyn FnMut(Node<'a>)) {
+    visit(node);
+
+    let stmt =
+        |stmt: &'a Stmt, visit: &mut dyn FnMut(Node<'a>)| walk(Node::Stmt(stmt), procedures, visit);
+    match node {
+        Node::Stmt(Stmt::Expr(expr)) => walk(Node::Expr(expr), procedures, visit),
+        Node::Stmt(Stmt::If(if_stmt)) => {
+            walk(Node::Expr(&if_stmt.condition), procedures, visit);
+            stmt(&if_stmt.then_branch, visit);
+            if let Some(else_branch) = &if_stmt.else_branch {
+                stmt(else_branch, visit);
+            }
+        }
+        Node::Stmt(Stmt::RepeatTimes(repeat)) => {
+            walk(Node::Expr(&repeat.count), procedures, visit);
+            stmt(&repeat.body, visit);
+        }
+        Node::Stmt(Stmt::RepeatUntil(repeat)) => {
+            walk(Node::Expr(&repeat.condition), procedures, visit);
+            stmt(&repeat.body, visit);
+        }
+        Node::Stmt(Stmt::ForEach(for_each)) => {
+            walk(Node::Expr(&for_each.list), procedures, visit);
+            stmt(&for_each.body, visit);
+        }
+        Node::Stmt(Stmt::ProcDeclaration(proc)) => {
+            if procedures {
+                stmt(&proc.body, visit);
+            }
+        }
+        Node::Stmt(Stmt::Block(block)) => {
+            for inner in &block.statements {
+                stmt(inner, visit);
+            }
+        }
+        Node::Stmt(Stmt::Return(return_stmt)) => {
+            if let Some(data) = &return_stmt.data {
+                walk(Node::Expr(data), procedures, visit);
+            }
+        }
+        Node::Stmt(Stmt::Continue(_) | Stmt::Break(_) | Stmt::Import(_)) => {}
+
+        Node::Expr(Expr::Literal(_) | Expr::Variable(_)) => {}
+        Node::Expr(Expr::Binary(binary)) => {
+            walk(Node::Expr(&binary.left), procedures, visit);
+            walk(Node::Expr(&binary.right), procedures, visit);
+        }
+        Node::Expr(Expr::Logical(logical)) => {
+            walk(Node::Expr(&logical.left), procedures, visit);
+            walk(Node::Expr(&logical.right), procedures, visit);
+        }
+        Node::Expr(Expr::Unary(unary)) => walk(Node::Expr(&unary.right), procedures, visit),
+        Node::Expr(Expr::Grouping(grouping)) => walk(Node::Expr(&grouping.expr), procedures, visit),
+        Node::Expr(Expr::ProcCall(call)) => {
+            for argument in &call.arguments {
+                walk(Node::Expr(argument), procedures, visit);
+            }
+        }
+        Node::Expr(Expr::Access(access)) => {
+            walk(Node::Expr(&access.list), procedures, visit);
+            walk(Node::Expr(&access.key), procedures, visit);
+        }
+        Node::Expr(Expr::List(list)) => {
+            for item in &list.items {
+                walk(Node::Expr(item), procedures, visit);
+            }
+        }
+        Node::Expr(Expr::Assign(assignment)) => {
+            walk(Node::Expr(&assignment.value), procedures, visit)
+        }
+        Node::Expr(Expr::Set(set)) => {
+            walk(Node::Expr(&set.list), procedures, visit);
+            walk(Node::Expr(&set.idx), procedures, visit);
+            walk(Node::Expr(&set.value), procedures, visit);
+        }
+        Node::Expr(Expr::Lambda(lambda)) => {
+            if procedures {
+                stmt(&lambda.body, visit);
+            }
+        }
+    }
+}
+
+/// the first token of a statement
+pub fn first_token(stmt: &Stmt) -> &Token {
+    match stmt {
+        Stmt::Expr(expr) => first_expr_token(expr),
+        Stmt::If(if_stmt) => &if_stmt.if_token,
+        Stmt::RepeatTimes(repeat) => &repeat.repeat_token,
+        Stmt::RepeatUntil(repeat) => &repeat.repeat_token,
+        Stmt::ForEach(for_each) => &for_each.for_token,
+        Stmt::ProcDeclaration(proc) => &proc.proc_token,
+        Stmt::Block(block) => &block.lb_token,
+        Stmt::Return(return_stmt) => &return_stmt.token,
+        Stmt::Continue(continue_stmt) => &continue_stmt.token,
+        Stmt::Break(break_stmt) => &break_stmt.token,
+        Stmt::Import(import) => &import.import_token,
+    }
+}
+
+pub fn first_expr_token(expr: &Expr) -> &Token {
+    match expr {
+        Expr::Literal(literal) => &literal.token,
+        Expr::Binary(binary) => first_expr_token(&binary.left),
+        Expr::Logical(logical) => first_expr_token(&logical.left),
+        Expr::Unary(unary) => &unary.token,
+        Expr::Grouping(grouping) => &grouping.parens.0,
+        Expr::ProcCall(call) => &call.token,
+        Expr::Access(access) => &access.list_token,
+        Expr::List(list) => &list.brackets.0,
+        Expr::Variable(variable) => &variable.token,
+        Expr::Assign(assignment) => &assignment.ident_token,
+        Expr::Set(set) => &set.list_token,
+        Expr::Lambda(lambda) => &lambda.proc_token,
+    }
+}
@@ -0,0 +1,280 @@
+//! `aplang repl`, runs code as it is typed.
+//! one interpreter runs every input, so variables and PROCEDUREs stay around
+
+use crate::interpreter::{CancelToken, Interpreter, Settings, Value};
+use crate::lexer::token::TokenType;
+use crate::lexer::Lexer;
+use crate::parser::ast::{Ast, Expr, Stmt};
+use crate::parser::symbol::Symbol;
+use crate::parser::{Parser, Resolver};
+use crate::reset_style;
+use miette::{miette, Report, Result};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+
+const PROMPT: &str = "> ";
+/// shown while a `{`, `(` or `[` is still open
+const CONTINUE: &str = "... ";
+
+/// the name errors use for typed code. user modules are found next to it, in the current directory
+const FILE_NAME: &str = "repl";
+
+const HELP: &str = "\
+:load FILE   run a file, keeping what it creates
+:vars        show every variable
+:procs       show every PROCEDURE
+:reset       forget everything and start over
+:help        show this
+:quit        leave, or press ctrl-d";
+
+/// runs the repl until the user leaves
+pub fn run() -> Result<()> {
+    // ctrl-c stops the code that is running, but not the repl
+    let cancel = CancelToken::new();
+    let handle = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handle.is_cancelled() {
+            // pressed twice, the code is stuck waiting on INPUT or SLEEP
+            reset_style();
+            process::exit(130);
+        }
+        handle.cancel();
+    })
+    .map_err(|err| miette!("could not listen for ctrl-c\n{}", err))?;
+
+    let mut editor =
+        DefaultEditor::new().map_err(|err| miette!("could not start the repl\n{}", err))?;
+    let history = history_path();
+    if let Some(history) = &history {
+        // there is no history the first time
+        let _ = editor.load_history(history);
+    }
+
+    println!(
+        "aplang {} repl. type :help for commands",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let mut repl = Repl::new(cancel);
+    while let Some(input) = read_input(&mut editor)? {
+        if input.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input.trim_end());
+
+        match input.trim().strip_prefix(':') {
+            Some(command) => {
+                if !repl.command(command) {
+                    break;
+                }
+            }
+            None => repl.eval(input.into(), Path::new(FILE_NAME)),
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+/// reads lines until the code is finished. None once the user leaves
+fn read_input(editor: &mut DefaultEditor) -> Result<Option<String>> {
+    let mut input = String::new();
+
+    loop {
+        let prompt = if input.is_empty() { PROMPT } else { CONTINUE };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                // an empty line runs what is there, even if it is not finished
+                if !input.is_empty() && line.trim().is_empty() {
+                    return Ok(Some(input));
+                }
+
+                input.push_str(&line);
+                input.push('\n');
+
+                if input.trim_start().starts_with(':') || !unfinished(&input) {
+                    return Ok(Some(input));
+                }
+            }
+            // ctrl-c throws away what was typed so far
+            Err(ReadlineError::Interrupted) => return Ok(Some(String::new())),
+            Err(ReadlineError::Eof) => return Ok(None),
+            Err(err) => return Err(miette!("could not read the input\n{}", err)),
+        }
+    }
+}
+
+/// if a `{`, `(` or `[` is still open
+fn unfinished(source: &str) -> bool {
+    // errors in the code are shown once it runs
+    let Ok(tokens) = Lexer::scan(source, FILE_NAME.to_string()) else {
+        return false;
+    };
+
+    let mut open = 0;
+    for token in &tokens {
+        match token.token_type() {
+            TokenType::LeftBrace | TokenType::LeftParen | TokenType::LeftBracket => open += 1,
+            TokenType::RightBrace | TokenType::RightParen | TokenType::RightBracket => open -= 1,
+            _ => {}
+        }
+    }
+    open > 0
+}
+
+/// where the history is kept between sessions
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".aplang_history"))
+}
+
+struct Repl {
+    interpreter: Interpreter,
+    cancel: CancelToken,
+}
+
+impl Repl {
+    fn new(cancel: CancelToken) -> Self {
+        let settings = Settings {
+            cancel: Some(cancel.clone()),
+            ..Settings::default()
+        };
+        let empty = Ast {
+            source: "".into(),
+            program: vec![],
+        };
+        let interpreter =
+            Interpreter::new(empty, Some(PathBuf::from(FILE_NAME))).with_settings(settings);
+
+        Self {
+            interpreter,
+            cancel,
+        }
+    }
+
+    /// runs a `:` command. returns false to leave
+    fn command(&mut self, command: &str) -> bool {
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(name, argument)| (name, argument.trim()));
+
+        match name {
+            "load" if argument.is_empty() => eprintln!("which file? use :load FILE"),
+            "load" => match fs::read_to_string(argument) {
+                Ok(source) => self.eval(source.into(), Path::new(argument)),
+                Err(err) => eprintln!("could not read {argument}\n{err}"),
+            },
+            "vars" => {
+                for (name, value) in self.interpreter.top_level_variables() {
+                    println!("{name} = {}", show(&value));
+                }
+            }
+            "procs" => {
+                let mut procedures: Vec<String> = self
+                    .interpreter
+                    .functions()
+                    .values()
+                    .filter_map(|(_, declaration)| declaration.as_ref())
+                    .map(|declaration| {
+                        let params: Vec<&str> = declaration
+                            .params
+                            .iter()
+                            .map(|param| param.ident.as_str())
+                            .collect();
+                        format!("PROCEDURE {}({})", declaration.name, params.join(", "))
+                    })
+                    .collect();
+                procedures.sort();
+                procedures
+                    .iter()
+                    .for_each(|procedure| println!("{procedure}"));
+            }
+            "reset" => *self = Repl::new(self.cancel.clone()),
+            "help" => println!("{HELP}"),
+            "quit" | "exit" => return false,
+            _ => eprintln!("there is no command :{name}, type :help to see them"),
+        }
+        true
+    }
+
+    /// runs some code and shows the value of each expression
+    fn eval(&mut self, source: Arc<str>, file: &Path) {
+        let ast = match self.parse(source, file) {
+            Ok(ast) => ast,
+            Err(reports) => {
+                reports
+                    .into_iter()
+                    .for_each(|report| eprintln!("{report:?}"));
+                return;
+            }
+        };
+
+        // an assignment would only echo what was just typed
+        let echoes: Vec<bool> = ast
+            .program
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Expr(expr) => Some(!matches!(**expr, Expr::Assign(_) | Expr::Set(_))),
+                _ => None,
+            })
+            .collect();
+
+        self.interpreter.set_file_path(file.to_path_buf());
+        match self.interpreter.interpret_more(ast) {
+            Ok(values) => {
+                for (value, echo) in values.iter().zip(echoes) {
+                    if echo && *value != Value::Null {
+                        println!("{}", show(value));
+                    }
+                }
+            }
+            Err(err) => {
+                if self.cancel.is_cancelled() {
+                    reset_style();
+                    self.cancel.reset();
+                }
+                eprintln!("{:?}", self.interpreter.report(err));
+            }
+        }
+    }
+
+    /// parses the code, knowing everything earlier inputs created
+    fn parse(&self, source: Arc<str>, file: &Path) -> Result<Ast, Vec<Report>> {
+        let file_name = file.display().to_string();
+
+        let tokens = Lexer::scan(source.clone(), file_name.clone())?;
+        let ast = Parser::new(tokens, source.clone(), &file_name).parse()?;
+
+        let variables: Vec<Symbol> = self
+            .interpreter
+            .top_level_variables()
+            .iter()
+            .map(|(name, _)| Symbol::intern(name))
+            .collect();
+        let procedures: Vec<(String, usize)> = self
+            .interpreter
+            .functions()
+            .iter()
+            .map(|(name, (procedure, _))| (name.clone(), procedure.arity() as usize))
+            .collect();
+
+        Resolver::new(source, &file_name, Some(file.to_path_buf()))
+            .with_globals(variables, procedures)
+            .resolve(&ast)?;
+        Ok(ast)
+    }
+}
+
+/// how the repl shows a value. strings are quoted so they can be told apart from numbers
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(string) => format!("{string:?}"),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,111 @@
+/// what a standard library function does, for editors to show
+pub fn describe(function: &str) -> Option<&'static str> {
+    let doc = match function {
+        // CORE
+        "DISPLAY" => "shows the value, then moves to the next line",
+        "DISPLAY_NOLN" => "shows the value without moving to the next line",
+        "INPUT" => "waits for the user to type a line and returns it",
+        "INSERT" => "puts the value into the list at index i, moving everything after it over",
+        "APPEND" => "adds the value to the end of the list",
+        "REMOVE" => "takes the item at index i out of the list and returns it",
+        "LENGTH" => "how many items are in a list, or characters in a string",
+        "RANDOM" => "a random whole number from a to b, including a and b",
+
+        // FS
+        "PATH_EXISTS" => "TRUE if there is a file or directory at the path",
+        "PATH_IS_FILE" => "TRUE if the path is a file",
+        "PATH_IS_DIRECTORY" => "TRUE if the path is a directory",
+        "FILE_REMOVE" => "deletes the file. TRUE if it worked",
+        "FILE_CREATE" => "makes a new empty file. FALSE if it already exists",
+        "FILE_READ" => "the contents of the file, or NULL if it cannot be read",
+        "FILE_APPEND" => "adds the contents to the end of the file. TRUE if it worked",
+        "FILE_OVERWRITE" => "replaces everything in the file with the contents. TRUE if it worked",
+        "DIRECTORY_READ" => "a list of the paths in the directory",
+        "DIRECTORY_CREATE" => "makes a new directory. TRUE if it worked",
+        "DIRECTORY_CREATE_ALL" => "makes a directory and any missing parents. TRUE if it worked",
+        "DIRECTORY_REMOVE" => "deletes an empty directory. TRUE if it worked",
+        "DIRECTORY_REMOVE_ALL" => "deletes a directory and everything in it. TRUE if it worked",
+
+        // TIME
+        "TIME" => "the current time in milliseconds",
+        "SLEEP" => "waits for the duration in milliseconds",
+
+        // MATH
+        "SIN" => "the sine of an angle in radians",
+        "COS" => "the cosine of an angle in radians",
+        "TAN" => "the tangent of an angle in radians",
+        "ASIN" => "the angle in radians whose sine is the value",
+        "ACOS" => "the angle in radians whose cosine is the value",
+        "ATAN" => "the angle in radians whose tangent is the value",
+        "ATAN2" => "the angle in radians from the x axis to the point (x, y)",
+        "SINH" => "the hyperbolic sine of the value",
+        "COSH" => "the hyperbolic cosine of the value",
+        "TANH" => "the hyperbolic tangent of the value",
+        "ASINH" => "the inverse hyperbolic sine of the value",
+        "ACOSH" => "the inverse hyperbolic cosine of the value",
+        "ATANH" => "the inverse hyperbolic tangent of the value",
+        "EXP" => "E raised to the power of the value",
+        "LOG" => "the logarithm of the value in the base",
+        "LOG10" => "the base 10 logarithm of the value",
+        "LOG2" => "the base 2 logarithm of the value",
+        "ROUND" => "the value rounded to the nearest whole number",
+        "FLOOR" => "the value rounded down",
+        "CEIL" => "the value rounded up",
+        "INT" => "the whole number part of the value",
+        "CLAMP" => "the value, kept between min and max",
+        "PI" => "the ratio of a circle's circumference to its diameter, 3.14159...",
+        "E" => "Euler's number, 2.71828...",
+        "TAU" => "two times PI, 6.28318...",
+
+        // IO
+        "INPUT_PROMPT" => "shows the prompt, then waits for the user to type a line and returns it",
+        "FORMAT" => "replaces each {} in the string with the next item of the list",
+        "DISPLAYF" => "shows the string with each {} replaced by the next item of the list",
+
+        // STRING
+        "TO_NUMBER" => "the number in the string, or NULL if it is not one",
+        "TO_BOOL" => "the boolean in the string, or NULL if it is not one",
+        "SPLIT" => "a list of the parts of the string between each pattern",
+        "TO_UPPER" => "the string in uppercase",
+        "TO_LOWER" => "the string in lowercase",
+        "TRIM" => "the string without whitespace at the start or end",
+        "CONTAINS" => "TRUE if the pattern is somewhere in the string",
+        "REPLACE" => "the string with every `from` changed to `to`",
+        "STARTS_WITH" => "TRUE if the string starts with the prefix",
+        "ENDS_WITH" => "TRUE if the string ends with the suffix",
+        "JOIN" => "the items of the list in one string, with the separator between them",
+        "SUBSTRING" => "length characters of the string, starting at index start",
+        "TO_CHAR_ARRAY" => "a list of each character in the string",
+
+        // STYLE
+        "STYLE" => "changes how DISPLAY looks, like \"red\" or \"bold\". TRUE if it exists",
+        "CLEAR_STYLE" => "goes back to the normal look",
+
+        // MAP
+        "MAP" => "a new empty map",
+        "MAP_INSERT" => "sets the value of a key, returning the value it had before or NULL",
+        "MAP_GET" => "the value of the key, or NULL if it is not in the map",
+        "MAP_CONTAINS_KEY" => "TRUE if the key is in the map",
+        "MAP_VALUES" => "a list of every value in the map",
+        "MAP_KEYS" => "a list of every key in the map",
+
+        // ROBOT
+        "ROBOT_MAP" => "a robot in the grid drawn by the string, or NULL if the grid is not valid",
+        "MOVE_FORWARD" => "moves the robot one square forward. TRUE if it reached the goal",
+        "MOVE_FOWARD" => "the same as MOVE_FORWARD",
+        "CAN_MOVE" => "TRUE if the robot can move \"forward\", \"left\", \"right\" or \"backward\"",
+        "ROTATE_LEFT" => "turns the robot 90 degrees to the left",
+        "ROTATE_RIGHT" => "turns the robot 90 degrees to the right",
+        "FORMAT_ROBOT" => "the grid with the robot in it, as a string",
+        "FORMAT_ROBOT_ASCII" => "the grid with the robot in it, as a string of plain characters",
+
+        // TEST
+        "ASSERT" => "stops the test with the message if the condition is FALSE",
+        "ASSERT_EQUAL" => "stops the test if actual is not equal to expected, showing how they differ",
+        "ASSERT_NOT_EQUAL" => "stops the test if actual is equal to expected",
+        "FAIL" => "stops the test with the message",
+
+        _ => return None,
+    };
+    Some(doc)
+}
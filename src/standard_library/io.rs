@@ -1,59 +1,33 @@
-use crate::interpreter::FunctionMap;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::{FunctionMap, Interpreter};
 use crate::interpreter::Value;
-use crate::{display, std_function};
+use crate::std_function;
+use miette::NamedSource;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-#[cfg(not(feature = "wasm"))]
-pub(super) fn input(prompt: &str) -> Option<String> {
-    use std::io;
-    use std::io::Write;
+/// reads a line for INPUT, showing the prompt first
+pub(super) fn input(interpreter: &Interpreter, prompt: &str) -> Result<String, RuntimeError> {
+    let mut input = interpreter.input();
 
-    display!("{}", prompt);
-    io::stdout().flush().ok()?;
-
-    let mut buf = String::new();
-    io::stdin().read_line(&mut buf).ok()?;
-    Some(buf.trim_end().to_string())
-}
-
-#[cfg(feature = "wasm")]
-pub(super) fn input(prompt: &str) -> Option<String> {
-    use wasm_bindgen::prelude::*;
-    use crate::wasm::IN;
-
-    // let output = IN.with(|input| {
-    //     if let Some(ref js) = *input.borrow() {
-    //        let this = JsValue::NULL;
-    //
-    //        let input_result = js.call1(
-    //            &this,
-    //            &JsValue::from_str(prompt), // prompt
-    //        ).ok()?;
-    //
-    //        input_result.as_string()
-    //     } else {
-    //         None
-    //     }
-    // });
-
-    IN.with(|input| {
-        // display!("debug log (wasm): trying to call IN.with prompt: {input:?}");
-        if let Some(ref callback) = *input.borrow() {
-            let this = JsValue::NULL;
+    if !input.shows_prompt() {
+        let mut output = interpreter.output();
+        output.write(prompt);
+        output.flush();
+    }
 
-            let res = callback.call1(
-                &this,
-                &JsValue::from_str(prompt)
-            ).ok();
-            
-            if res.is_none() {
-                display!("debug log (wasm) error: could not call callback");
-            }
-            
-            res.unwrap().as_string()
-            // display!("debug log (wasm): {}\n", res.as_string().unwrap());
-        } else { None }
+    input.read_line(prompt).ok_or_else(|| {
+        let call = interpreter
+            .native_call()
+            .expect("INPUT is only called from a program");
+
+        RuntimeError {
+            named_source: NamedSource::new(interpreter.get_file_path(), call.token.source.clone()),
+            span: call.token.span,
+            message: "End of Input".to_string(),
+            help: "The program asked for more INPUT than it was given".to_string(),
+            label: "There was nothing left to read here".to_string(),
+        }
     })
 }
 
@@ -77,8 +51,8 @@ fn format(fstring: String, args: Rc<RefCell<Vec<Value>>>) -> Option<String> {
 
 pub(super) fn std_io() -> FunctionMap {
     let mut functions = FunctionMap::new();
-    std_function!(functions => fn INPUT_PROMPT(prompt: Value::String) {
-        let result = input(prompt.as_str()).expect("Failed to get user input! Critical Failure");
+    std_function!(functions => fn INPUT_PROMPT(prompt: Value::String) using interpreter {
+        let result = input(interpreter, prompt.as_str())?;
         Ok(Value::String(result))
     });
 
@@ -87,9 +61,9 @@ pub(super) fn std_io() -> FunctionMap {
         Ok(Value::String(builder))
     });
 
-    std_function!(functions => fn DISPLAYF(fstring: Value::String, args: Value::List) {
+    std_function!(functions => fn DISPLAYF(fstring: Value::String, args: Value::List) using interpreter {
         let builder= format(fstring, args).expect("Incorrect number of format arguments. Failed to format");
-        println!("{}", builder);
+        interpreter.output().write(&format!("{builder}\n"));
 
         Ok(Value::Null)
     });
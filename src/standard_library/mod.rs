@@ -2,10 +2,9 @@ use crate::interpreter::FunctionMap;
 use crate::interpreter::Value;
 use crate::standard_library::io::input;
 use crate::std_function;
-use rand::Rng;
 use std::collections::HashMap;
-use crate::display;
 
+pub mod docs;
 mod file_system;
 mod io;
 mod math;
@@ -15,6 +14,7 @@ mod style;
 mod time;
 mod map;
 mod robot;
+mod testing;
 
 #[derive(Debug, Clone, Default)]
 pub struct Modules {
@@ -31,7 +31,8 @@ impl Modules {
         self.register("STRING", strings::std_strings);
         self.register("STYLE", style::std_style);
         self.register("MAP", map::std_map);
-        self.register("ROBOT", robot::std_robot)
+        self.register("ROBOT", robot::std_robot);
+        self.register("TEST", testing::std_testing)
     }
     pub fn init() -> Self {
         // create bland hashmap of modules
@@ -46,6 +47,17 @@ impl Modules {
         self.modules.get(module)
     }
 
+    /// every function in the standard library, along with the module it is in
+    pub fn functions(&self) -> HashMap<String, String> {
+        let mut functions = HashMap::new();
+        for (module, injector) in &self.modules {
+            for function in injector().into_keys() {
+                functions.insert(function, module.clone());
+            }
+        }
+        functions
+    }
+
     pub fn register(&mut self, module_name: &str, injector: fn() -> FunctionMap) {
         // if a module is defined again with the same name, then the prev will be discarded
         let _ = self.modules.insert(module_name.to_string(), injector);
@@ -55,21 +67,20 @@ impl Modules {
 fn std_core() -> FunctionMap {
     let mut functions = FunctionMap::new();
 
-    std_function!(functions => fn DISPLAY(value: Value) {
-        display!("{}\n", value);
+    std_function!(functions => fn DISPLAY(value: Value) using interpreter {
+        interpreter.output().write(&format!("{value}\n"));
 
         return Ok(Value::Null)
     });
 
-    std_function!(functions => fn DISPLAY_NOLN(value: Value) {
-        display!("{}", value);
+    std_function!(functions => fn DISPLAY_NOLN(value: Value) using interpreter {
+        interpreter.output().write(&value.to_string());
 
         return Ok(Value::Null)
     });
 
-    std_function!(functions => fn INPUT() {
-        let result = input("").expect("Failed to get user input! Critical Failure");
-        // let result = input("").unwrap_or_default();
+    std_function!(functions => fn INPUT() using interpreter {
+        let result = input(interpreter, "")?;
         Ok(Value::String(result))
     });
 
@@ -109,9 +120,8 @@ fn std_core() -> FunctionMap {
     });
 
     // return a random integer from a to b including a and b
-    std_function!(functions => fn RANDOM(a: Value::Number, b: Value::Number) {
-        let mut rng = rand::rng();
-        let result = rng.random_range(a as i64..=b as i64);
+    std_function!(functions => fn RANDOM(a: Value::Number, b: Value::Number) using interpreter {
+        let result = interpreter.random_range(a as i64, b as i64);
 
         return Ok(Value::Number(result as f64))
     });
@@ -1,11 +1,13 @@
 #[macro_export]
 macro_rules! std_function {
-    ($location:expr => fn $name:ident ($($arg:ident:  Value $(:: $arg_type:ident)? $(<$ot:ty>)?),*) {$($body:tt)*}) => {
+    // `using interpreter` gives the body access to the interpreter that made the call
+    ($location:expr => fn $name:ident ($($arg:ident:  Value $(:: $arg_type:ident)? $(<$ot:ty>)?),*) $(using $interpreter:ident)? {$($body:tt)*}) => {
         $location.insert(
             String::from(stringify!($name)),
             (std::rc::Rc::new($crate::interpreter::NativeProcedure {
                 name: String::from(stringify!($name)),
                 arity: $crate::arity!($($arg)*),
+                params: &[$(stringify!($arg)),*],
                 callable: |_interpreter: &mut $crate::interpreter::Interpreter,  args: &[$crate::interpreter::Value], args_toks: &[miette::SourceSpan], _source: std::sync::Arc<str>| {
                     #[allow(unused_mut, unused_variables)]
                     let mut iter = args.into_iter();
@@ -16,6 +18,10 @@ macro_rules! std_function {
                         let $arg = __iter_toks.next().unwrap();
                         $crate::unwrap_arg_type!($arg => Value $(::$arg_type)? $(<$ot>)?, _interpreter, _source);
                     )*
+                    $(
+                        #[allow(unused_variables)]
+                        let $interpreter = &mut *_interpreter;
+                    )?
 
                     $($body)*
                 }
@@ -39,7 +45,7 @@ macro_rules! unwrap_arg_type {
     ($value:ident => Value::Null, $interpreter:ident, $source:ident) => {
         #[allow(unused_mut)]
         let mut $value = match $value.0 {
-            $crate::interpreter::value::Value::Null => $crate::interpreter::Value::Null,
+            $crate::interpreter::Value::Null => $crate::interpreter::Value::Null,
             _ => return Err(
                 $crate::interpreter::errors::RuntimeError {
                     named_source: miette::NamedSource::new($interpreter.get_file_path(), $source),
@@ -81,7 +87,7 @@ macro_rules! unwrap_arg_type {
     };
     ($value:ident => Value::Bool, $interpreter:ident, $source:ident) => {
         #[allow(unused_mut)]
-        let $crate::interpreter::value::Value::Bool(mut $value) = $value.0.clone() else {
+        let $crate::interpreter::Value::Bool(mut $value) = $value.0.clone() else {
             return Err(
                 $crate::interpreter::errors::RuntimeError {
                     named_source: miette::NamedSource::new($interpreter.get_file_path(), $source),
@@ -145,8 +151,9 @@ macro_rules! unwrap_arg_type {
 #[macro_export]
 macro_rules! downcast {
     ($any:ident => $ty:ty) => {
-        #[allow(clippy::mutable_key_type)]
         let mut __any_ref = $any.as_ref().borrow_mut();
+        // a MAP is keyed by values, and a list value can change after it is used as a key
+        #[allow(clippy::mutable_key_type)]
         let $any =  __any_ref.downcast_mut::<$ty>().unwrap();
     };
 }
\ No newline at end of file
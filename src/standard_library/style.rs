@@ -1,13 +1,13 @@
 use crate::interpreter::FunctionMap;
 use crate::interpreter::Value;
-use crate::{display, std_function};
+use crate::std_function;
 use mapro::map;
 use std::collections::HashMap;
 
 pub(super) fn std_style() -> FunctionMap {
     let mut functions = FunctionMap::new();
 
-    std_function!(functions => fn STYLE(style: Value::String) {
+    std_function!(functions => fn STYLE(style: Value::String) using interpreter {
          let styles: HashMap<&str, &str> = map! {
             // default colors
             "clear" => "\x1b[0m",
@@ -61,15 +61,15 @@ pub(super) fn std_style() -> FunctionMap {
             return Ok(Value::Bool(false))
         };
 
-        display!("{}", code);
+        interpreter.output().write(code);
 
         return Ok(Value::Bool(true))
     });
 
-    std_function!(functions => fn CLEAR_STYLE() {
+    std_function!(functions => fn CLEAR_STYLE() using interpreter {
 
         // clear all styles
-        display!("\x1b[0m");
+        interpreter.output().write("\x1b[0m");
 
         return Ok(Value::Null)
     });
@@ -0,0 +1,106 @@
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::{FunctionMap, Interpreter, Value};
+use crate::std_function;
+use miette::NamedSource;
+use std::fmt::Write;
+
+pub(super) fn std_testing() -> FunctionMap {
+    let mut functions = FunctionMap::new();
+
+    std_function!(functions => fn ASSERT(condition: Value::Bool, message: Value) using interpreter {
+        if condition {
+            return Ok(Value::Null);
+        }
+        Err(failure(interpreter, "This ASSERT was FALSE", message.to_string()))
+    });
+
+    std_function!(functions => fn ASSERT_EQUAL(actual: Value, expected: Value) using interpreter {
+        if actual == expected {
+            return Ok(Value::Null);
+        }
+        Err(failure(interpreter, "These are not equal", difference(actual, expected)))
+    });
+
+    std_function!(functions => fn ASSERT_NOT_EQUAL(actual: Value, expected: Value) using interpreter {
+        if actual != expected {
+            return Ok(Value::Null);
+        }
+        Err(failure(interpreter, "These are equal", format!("both are {}", show(actual))))
+    });
+
+    std_function!(functions => fn FAIL(message: Value) using interpreter {
+        Err(failure(interpreter, "This test failed", message.to_string()))
+    });
+
+    functions
+}
+
+/// the error an assertion stops the program with, pointing at the call
+fn failure(interpreter: &Interpreter, label: &str, help: String) -> RuntimeError {
+    let call = interpreter
+        .native_call()
+        .expect("assertions are only called from a program");
+
+    RuntimeError {
+        named_source: NamedSource::new(interpreter.get_file_path(), call.token.source.clone()),
+        span: call.token.span,
+        message: "Assertion Failed".to_string(),
+        help,
+        label: label.to_string(),
+    }
+}
+
+/// explains how two values are different. lists show each item that does not match
+fn difference(actual: &Value, expected: &Value) -> String {
+    let mut text = format!("expected {}\n but got {}", show(expected), show(actual));
+
+    if let (Value::List(_), Value::List(_)) = (actual, expected) {
+        let mut items = vec![];
+        differences(actual, expected, String::new(), &mut items);
+
+        text.push_str("\ndifferences:");
+        for item in items {
+            let _ = write!(text, "\n  {item}");
+        }
+    }
+    text
+}
+
+/// every item that is different, inside of lists in lists as well.
+/// `at` is the indexes it took to get here, like `[2][1]`
+fn differences(actual: &Value, expected: &Value, at: String, items: &mut Vec<String>) {
+    let (Value::List(actual), Value::List(expected)) = (actual, expected) else {
+        if actual != expected {
+            items.push(format!(
+                "{at}: expected {}, got {}",
+                show(expected),
+                show(actual)
+            ));
+        }
+        return;
+    };
+
+    let (actual, expected) = (actual.borrow(), expected.borrow());
+    for index in 0..actual.len().max(expected.len()) {
+        // aplang lists start at 1
+        let at = format!("{at}[{}]", index + 1);
+        match (actual.get(index), expected.get(index)) {
+            (Some(actual), Some(expected)) => differences(actual, expected, at, items),
+            (Some(actual), None) => {
+                items.push(format!("{at}: expected nothing, got {}", show(actual)))
+            }
+            (None, Some(expected)) => {
+                items.push(format!("{at}: expected {}, got nothing", show(expected)))
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// strings are quoted so they can be told apart from numbers
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(string) => format!("{string:?}"),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,274 @@
+//! `aplang test`, runs every `TEST_` PROCEDURE and reports which ones failed.
+//! each test gets a fresh interpreter that runs the top level of its file first,
+//! so tests cannot change each other's variables
+
+use crate::aplang::{ApLang, Parsed};
+use crate::arguments::{ReportFormat, TestCommand};
+use crate::find_sources;
+use crate::input::Lines;
+use crate::interpreter::errors::{RuntimeError, TracedError};
+use crate::output::Buffer;
+use crate::parser::ast::ProcDeclaration;
+use miette::{miette, GraphicalReportHandler, GraphicalTheme, NamedSource, Report, Result};
+use serde_json::{json, Value as Json};
+use std::path::Path;
+use std::sync::Arc;
+
+/// runs the tests in every file and shows the results
+pub fn run(command: TestCommand) -> Result<()> {
+    let mut files = vec![];
+    for path in &command.paths {
+        find_sources(path, &mut files)?;
+    }
+
+    let mut results = vec![];
+    for file in files {
+        let file_name = file.display().to_string();
+        match load(&file) {
+            Ok(parsed) => {
+                for test in parsed.tests() {
+                    results.push(run_test(&file, &file_name, &test));
+                }
+            }
+            // a file that cannot run fails as a whole
+            Err(reports) => results.push(Outcome {
+                file: file_name,
+                name: None,
+                line: 1,
+                output: String::new(),
+                failure: Some(Failure::from_reports(reports)),
+            }),
+        }
+    }
+
+    match command.format {
+        ReportFormat::Human => show(&results),
+        ReportFormat::Json => println!("{:#}", to_json(&results)),
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| result.failure.is_some())
+        .count();
+    if failed > 0 {
+        return Err(miette!("{failed} test(s) failed"));
+    }
+    Ok(())
+}
+
+pub(crate) fn load(file: &Path) -> Result<ApLang<Parsed>, Vec<Report>> {
+    ApLang::new_from_file(file.to_path_buf())
+        .map_err(|err| vec![miette!("could not read {}\n{}", file.display(), err)])?
+        .lex()?
+        .parse()
+}
+
+/// how one test went
+struct Outcome {
+    file: String,
+    /// None when the whole file failed before any test could run
+    name: Option<String>,
+    line: usize,
+    /// everything the test displayed
+    output: String,
+    failure: Option<Failure>,
+}
+
+/// why a program stopped before it should have
+pub(crate) struct Failure {
+    pub(crate) message: String,
+    pub(crate) help: Option<String>,
+    /// the file, line and column the error points at, if it points anywhere
+    pub(crate) location: Option<(String, usize, usize)>,
+    pub(crate) reports: Vec<Report>,
+}
+
+fn run_test(file: &Path, file_name: &str, test: &ProcDeclaration) -> Outcome {
+    let output = Buffer::new();
+
+    let failure = if test.params.is_empty() {
+        // the file parsed once already, so it will again
+        load(file)
+            .map_err(Failure::from_reports)
+            .and_then(|parsed| {
+                // INPUT fails instead of waiting for someone to type
+                let mut interpreter = parsed
+                    .interpreter()
+                    .with_output(output.clone())
+                    .with_input(Lines::new(Vec::<String>::new()));
+
+                interpreter
+                    .interpret()
+                    .and_then(|()| interpreter.call_procedure(&test.name))
+                    .map_err(|err| Failure::from_runtime(err, |err| interpreter.report(err)))
+            })
+            .err()
+    } else {
+        let token = &test.name_token;
+        let err = RuntimeError {
+            named_source: NamedSource::new(file_name, token.source.clone()),
+            span: token.span,
+            message: "Test Takes Parameters".to_string(),
+            help: "A TEST_ PROCEDURE is called without arguments, so it cannot take any"
+                .to_string(),
+            label: "This test has parameters".to_string(),
+        };
+        Some(Failure::from_runtime(err, |error| {
+            Report::new(TracedError {
+                error,
+                trace: vec![],
+            })
+        }))
+    };
+
+    Outcome {
+        file: file_name.to_string(),
+        name: Some(test.name.to_string()),
+        line: test.proc_token.line_number,
+        output: output.contents(),
+        failure,
+    }
+}
+
+impl Failure {
+    pub(crate) fn from_runtime(
+        err: RuntimeError,
+        report: impl FnOnce(RuntimeError) -> Report,
+    ) -> Self {
+        let offset = err.span.offset();
+        let source: &Arc<str> = err.named_source.inner();
+        let before = &source[..offset.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+            + 1;
+
+        Self {
+            message: err.message.clone(),
+            help: Some(err.help.clone()),
+            location: Some((err.named_source.name().to_string(), line, column)),
+            reports: vec![report(err)],
+        }
+    }
+
+    pub(crate) fn from_reports(reports: Vec<Report>) -> Self {
+        let message = reports
+            .iter()
+            .map(|report| report.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Self {
+            message,
+            help: None,
+            location: None,
+            reports,
+        }
+    }
+
+    /// the reports without colors, for tools that are not terminals
+    fn plain(&self) -> String {
+        let handler = GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor());
+        let mut text = String::new();
+        for report in &self.reports {
+            if handler.render_report(&mut text, report.as_ref()).is_err() {
+                text.push_str(&format!("{report}\n"));
+            }
+        }
+        text
+    }
+
+    pub(crate) fn to_json(&self) -> Json {
+        let mut error = json!({
+            "message": self.message,
+            "help": self.help,
+            "report": self.plain(),
+        });
+        if let Some((file, line, column)) = &self.location {
+            error["file"] = json!(file);
+            error["line"] = json!(line);
+            error["column"] = json!(column);
+        }
+        error
+    }
+}
+
+impl Outcome {
+    fn title(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} {name}", self.file),
+            None => self.file.clone(),
+        }
+    }
+}
+
+/// a line for each test, then the errors of the ones that failed, like `cargo test`
+fn show(results: &[Outcome]) {
+    println!("running {} test(s)", results.len());
+    for result in results {
+        let status = if result.failure.is_some() {
+            "FAILED"
+        } else {
+            "ok"
+        };
+        println!("test {} ... {status}", result.title());
+    }
+
+    let failures: Vec<&Outcome> = results
+        .iter()
+        .filter(|result| result.failure.is_some())
+        .collect();
+    if !failures.is_empty() {
+        println!("\nfailures:");
+    }
+    for result in &failures {
+        println!("\n---- {} ----", result.title());
+        if let Some(failure) = &result.failure {
+            failure
+                .reports
+                .iter()
+                .for_each(|report| println!("{report:?}"));
+        }
+        if !result.output.is_empty() {
+            println!("output:\n{}", result.output.trim_end());
+        }
+    }
+
+    let failed = failures.len();
+    let passed = results.len() - failed;
+    let status = if failed > 0 { "FAILED" } else { "ok" };
+    println!("\ntest result: {status}. {passed} passed; {failed} failed");
+}
+
+fn to_json(results: &[Outcome]) -> Json {
+    let tests: Vec<Json> = results
+        .iter()
+        .map(|result| {
+            let mut test = json!({
+                "file": result.file,
+                "name": result.name,
+                "line": result.line,
+                "passed": result.failure.is_none(),
+                "output": result.output,
+            });
+            if let Some(failure) = &result.failure {
+                test["error"] = failure.to_json();
+            }
+            test
+        })
+        .collect();
+
+    let failed = results
+        .iter()
+        .filter(|result| result.failure.is_some())
+        .count();
+    json!({
+        "passed": results.len() - failed,
+        "failed": failed,
+        "tests": tests,
+    })
+}
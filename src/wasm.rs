@@ -4,12 +4,14 @@ use wasm_bindgen::prelude::wasm_bindgen;
 use std::cell::RefCell;
 use std::sync::Arc;
 use js_sys::Function;
+use crate::interpreter::{CancelToken, Settings};
 use crate::{display_error, ApLang};
 
 thread_local! {
     pub static OUT: RefCell<Option<Function>> = const { RefCell::new(None) };
     pub static IN: RefCell<Option<Function>> = const { RefCell::new(None) };
     pub static WAIT: RefCell<Option<Function>> = const { RefCell::new(None)};
+    pub static CANCEL: RefCell<CancelToken> = RefCell::new(CancelToken::new());
 }
 
 
@@ -34,12 +36,26 @@ pub fn bind_wait(wait: Function /* sleep(ms) */) {
 }
 
 
+/// Stop the program that is running.
+/// It stops the next time it calls back into javascript and then loops or calls a PROCEDURE
+#[wasm_bindgen]
+pub fn cancel() {
+    CANCEL.with(|cancel| cancel.borrow().cancel());
+}
+
 #[wasm_bindgen]
 pub fn aplang(source_code: &str) {
     // make sure source can escape
     let source_code: Arc<str> = source_code.into();
-    
-        let aplang = ApLang::new_from_stdin(source_code);
+
+    // every run gets a new token so an old cancel does not stop it
+    let cancel = CancelToken::new();
+    CANCEL.with(|current| *current.borrow_mut() = cancel.clone());
+
+        let aplang = ApLang::new_from_stdin(source_code).with_settings(Settings {
+            cancel: Some(cancel),
+            ..Settings::default()
+        });
 
         let lexed = match aplang.lex() {
             Ok(lexed) => lexed,
@@ -2,6 +2,12 @@
 
 use assert_cmd::Command;
 use std::fmt::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// every test runs on each engine, they have to behave the same
+const ENGINES: [&str; 2] = ["tree", "bytecode"];
 
 fn smart_test(smart_src: impl Into<String>) {
     let smart_src: String = smart_src.into();
@@ -29,16 +35,58 @@ fn smart_test(smart_src: impl Into<String>) {
     println!("=== EXP ===");
     println!("{expected}");
 
-    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+
+        let cmd = cmd
+            .args(["--engine", engine])
+            .arg(format!("-e {src}"))
+            .assert();
+
+        println!("=== OUT ({engine}) ===");
+        let runtime = String::from_utf8(cmd.get_output().stdout.clone()).unwrap();
+        println!("{runtime}");
+
+        // do assert
+        cmd.stdout(expected.clone());
+    }
+}
+
+/// a folder for the files of one test, removed when the test ends even if it fails.
+/// tests run at the same time, so every test has to use its own name
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        static NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let mut names = NAMES.lock().unwrap_or_else(|err| err.into_inner());
+        assert!(!names.iter().any(|used| used == name), "two tests use the folder {name}");
+        names.push(name.to_string());
+
+        let path = std::env::temp_dir().join(format!("aplang_{name}_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
 
-    let cmd = cmd.arg(format!("-e {src}")).assert();
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
 
-    println!("=== OUT ===");
-    let runtime = String::from_utf8(cmd.get_output().stdout.clone()).unwrap();
-    println!("{runtime}");
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
 
-    // do assert
-    cmd.stdout(expected);
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
 }
 
 #[test]
@@ -396,6 +444,41 @@ fn test_nested_procedures() {
     );
 }
 
+#[test]
+fn test_procedure_called_before_declaration() {
+    smart_test(r#"
+    DISPLAY(area(2, 3)) $6
+    PROCEDURE area(width, height) {
+        RETURN multiply(width, height)
+    }
+    PROCEDURE multiply(a, b) {
+        RETURN a * b
+    }
+    "#);
+}
+
+#[test]
+fn test_module_procedure_called_before_declaration() {
+    let dir = TempDir::new("hoist");
+
+    std::fs::write(
+        dir.join("shapes.ap"),
+        "side <- square(3)\nEXPORT PROCEDURE square(n) {\n    RETURN n * n\n}\n",
+    )
+    .unwrap();
+    let main = dir.join("main.ap");
+    std::fs::write(&main, "IMPORT MOD \"shapes.ap\"\nDISPLAY(square(4))\n").unwrap();
+
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        cmd.args(["--engine", engine])
+            .arg(&main)
+            .assert()
+            .success()
+            .stdout("16\n");
+    }
+}
+
 #[test]
 fn test_pass_by_value() {
     smart_test(
@@ -675,3 +758,1740 @@ fn test_nested_repeat_until_with_breaks_and_continue() {
     $3-3
     "#);
 }
+
+#[test]
+fn test_procedure_as_argument() {
+    smart_test(r#"
+    PROCEDURE apply(f, x) {
+        RETURN f(x)
+    }
+    PROCEDURE double(n) {
+        RETURN n * 2
+    }
+    DISPLAY(apply(double, 4)) $8
+    DISPLAY(apply(LENGTH, [1, 2, 3])) $3
+    "#);
+}
+
+#[test]
+fn test_anonymous_procedure() {
+    smart_test(r#"
+    square <- PROCEDURE (n) {
+        RETURN n * n
+    }
+    DISPLAY(square(5)) $25
+    DISPLAY(square) $PROCEDURE anonymous
+    "#);
+}
+
+#[test]
+fn test_variable_only_shadows_procedure_with_procedure() {
+    smart_test(r#"
+    PROCEDURE total(l) {
+        sum <- 0
+        FOR EACH n IN l {
+            sum <- sum + n
+        }
+        RETURN sum
+    }
+    total <- total([1, 2])
+    DISPLAY(total) $3
+    DISPLAY(total([3, 4])) $7
+    total <- PROCEDURE (l) { RETURN LENGTH(l) }
+    DISPLAY(total([3, 4])) $2
+    "#);
+}
+
+#[test]
+fn test_procedures_in_list() {
+    smart_test(r#"
+    PROCEDURE double(n) {
+        RETURN n * 2
+    }
+    steps <- [double, PROCEDURE (n) { RETURN n + 1 }]
+    FOR EACH step IN steps {
+        DISPLAY(step(10))
+    }
+    $20
+    $11
+    DISPLAY(steps[1] == double) $TRUE
+    "#);
+}
+
+#[test]
+fn test_return_procedure() {
+    smart_test(r#"
+    PROCEDURE pick(first, a, b) {
+        IF (first) {
+            RETURN a
+        }
+        RETURN b
+    }
+    f <- pick(TRUE, PROCEDURE (x) { RETURN x * 2 }, LENGTH)
+    DISPLAY(f(7)) $14
+    f <- pick(FALSE, PROCEDURE (x) { RETURN x * 2 }, LENGTH)
+    DISPLAY(f("abc")) $3
+    "#);
+}
+
+#[test]
+fn test_return_exits_loop() {
+    smart_test(r#"
+    PROCEDURE first_even(numbers) {
+        FOR EACH n IN numbers {
+            IF (n MOD 2 == 0) {
+                RETURN n
+            }
+        }
+        RETURN NULL
+    }
+    DISPLAY(first_even([3, 5, 6, 8])) $6
+    DISPLAY(first_even([1])) $NULL
+    "#);
+}
+
+#[test]
+fn test_procedure_reads_globals() {
+    smart_test(r#"
+    LIMIT <- 3
+    PROCEDURE under_limit(n) {
+        RETURN n < LIMIT
+    }
+    DISPLAY(under_limit(2)) $TRUE
+    DISPLAY(under_limit(5)) $FALSE
+    "#);
+}
+
+#[test]
+fn test_procedure_writes_globals() {
+    smart_test(r#"
+    count <- 0
+    PROCEDURE bump() {
+        count <- count + 1
+        fresh <- "only inside"
+    }
+    bump()
+    bump()
+    DISPLAY(count) $2
+    "#);
+}
+
+#[test]
+fn test_recursive_calls_get_their_own_variables() {
+    smart_test(r#"
+    x <- 100
+    PROCEDURE sumTo(n) {
+        IF (n == 0) {
+            RETURN 0
+        }
+        x <- n
+        rest <- sumTo(n - 1)
+        RETURN x + rest
+    }
+    DISPLAY(sumTo(3)) $6
+    DISPLAY(x) $100
+    "#);
+}
+
+#[test]
+fn test_assignments_are_decided_before_running() {
+    // `flag` is used before it is assigned in `set`, so it always changes the one outside,
+    // even on a call that skips the DISPLAY
+    smart_test(r#"
+    flag <- FALSE
+    PROCEDURE set(show) {
+        IF (show) {
+            DISPLAY(flag)
+        }
+        flag <- TRUE
+    }
+    PROCEDURE reset() {
+        flag <- FALSE
+    }
+    set(FALSE)
+    DISPLAY(flag) $TRUE
+    reset()
+    DISPLAY(flag) $TRUE
+    "#);
+}
+
+#[test]
+fn test_lint_shadowed_variable() {
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd
+        .arg("lint")
+        .arg("-e\nflag <- FALSE\nPROCEDURE reset() {\n    flag <- FALSE\n    RETURN flag\n}\nDISPLAY(reset())")
+        .assert()
+        .success();
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("shadowed_variable"), "{stderr}");
+
+    // changing the variable outside is not reported
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd
+        .arg("lint")
+        .arg("-e\ncount <- 0\nPROCEDURE bump() {\n    count <- count + 1\n}\nbump()\nDISPLAY(count)")
+        .assert()
+        .success();
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(!stderr.contains("shadowed_variable"), "{stderr}");
+}
+
+#[test]
+fn test_closure_captures_parameter() {
+    smart_test(r#"
+    PROCEDURE make_adder(n) {
+        RETURN PROCEDURE (x) { RETURN x + n }
+    }
+    add5 <- make_adder(5)
+    add10 <- make_adder(10)
+    DISPLAY(add5(1)) $6
+    DISPLAY(add10(1)) $11
+    "#);
+}
+
+#[test]
+fn test_closure_keeps_state() {
+    smart_test(r#"
+    PROCEDURE counter() {
+        c <- 0
+        RETURN PROCEDURE () {
+            c <- c + 1
+            RETURN c
+        }
+    }
+    a <- counter()
+    b <- counter()
+    a()
+    a()
+    DISPLAY(a()) $3
+    DISPLAY(b()) $1
+    "#);
+}
+
+#[test]
+fn test_caller_variables_are_not_visible() {
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine])
+            .arg(
+                r#"-e
+    PROCEDURE inner() {
+        RETURN secret
+    }
+    PROCEDURE outer() {
+        secret <- 1
+        RETURN inner()
+    }
+    outer()
+    "#,
+            )
+            .assert()
+            .failure()
+            .stdout("");
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("undefined_variable"));
+        assert!(stderr.contains("the variable `secret` is never created"));
+        assert!(stderr.contains("can only see variables"));
+    }
+}
+
+// the tree walker recurses in rust, so only the bytecode engine can go this deep
+#[test]
+fn test_deep_recursion_bytecode() {
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.args(["--engine", "bytecode", "--max-call-depth", "0"])
+        .arg(
+            r#"-e
+    PROCEDURE count(n) {
+        IF (n == 0) {
+            RETURN 0
+        }
+        RETURN 1 + count(n - 1)
+    }
+    DISPLAY(count(100000))
+    "#,
+        )
+        .assert()
+        .success()
+        .stdout("100000\n");
+}
+
+#[test]
+fn test_runtime_error_trace() {
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine])
+            .arg(
+                r#"-e
+    PROCEDURE divide(a, b) {
+        RETURN a / b
+    }
+    PROCEDURE average(list) {
+        RETURN divide(0, LENGTH(list))
+    }
+    average([])
+    "#,
+            )
+            .assert()
+            .failure();
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Division by Zero"));
+        assert!(stderr.contains("`divide` was called by `average`"));
+        assert!(stderr.contains("`average` was called from the top level"));
+    }
+}
+
+#[test]
+fn test_runtime_error_trace_through_import() {
+    let dir = TempDir::new("trace_import");
+
+    let module = dir.join("broken.ap");
+    std::fs::write(
+        &module,
+        "EXPORT PROCEDURE broken() {\n    RETURN 1 / 0\n}\n",
+    )
+    .unwrap();
+    let main = dir.join("main.ap");
+    std::fs::write(&main, "IMPORT MOD \"broken.ap\"\nbroken()\n").unwrap();
+
+    let importer = dir.join("importer.ap");
+    std::fs::write(&importer, "IMPORT MOD \"main.ap\"\n").unwrap();
+
+    for engine in ENGINES {
+        // the error belongs to the module the PROCEDURE was written in
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd.args(["--engine", engine]).arg(&main).assert().failure();
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("broken.ap:2"));
+        assert!(stderr.contains("`broken` was called from the top level"));
+
+        // importing a module that fails shows where it was imported
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine])
+            .arg(&importer)
+            .assert()
+            .failure();
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("`broken` was called from the top level"));
+        assert!(stderr.contains("while importing"));
+        assert!(stderr.contains("imported here"));
+    }
+}
+
+#[test]
+fn test_max_call_depth() {
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine, "--max-call-depth", "50"])
+            .arg(
+                r#"-e
+    PROCEDURE forever(n) {
+        RETURN forever(n + 1)
+    }
+    forever(0)
+    "#,
+            )
+            .assert()
+            .failure();
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Too Much Recursion"));
+        assert!(stderr.contains("limit of 50 nested calls"));
+        assert!(stderr.contains("base case"));
+        assert!(stderr.contains("more calls"));
+    }
+}
+
+#[test]
+fn test_default_max_call_depth() {
+    // runaway recursion stops with an error instead of overflowing the stack
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine])
+            .arg(
+                r#"-e
+    PROCEDURE forever(n) {
+        RETURN forever(n + 1)
+    }
+    forever(0)
+    "#,
+            )
+            .assert()
+            .failure();
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Too Much Recursion"));
+    }
+}
+
+#[test]
+fn test_recursion_to_the_default_limit() {
+    use aplang_lib::interpreter::{Engine, Settings, DEFAULT_MAX_CALL_DEPTH};
+    use aplang_lib::output::Buffer;
+    use aplang_lib::ApLang;
+
+    // loops and IFs make each call take more of the stack. without `stacker` the stack
+    // cannot grow, so the calls can stop before the limit, but only with an error
+    let depth = DEFAULT_MAX_CALL_DEPTH - 1;
+    let source = format!(
+        r#"
+    PROCEDURE down(n) {{
+        REPEAT 1 TIMES {{
+            IF (n > 0) {{
+                FOR EACH step IN [1] {{
+                    RETURN step + down(n - 1)
+                }}
+            }}
+        }}
+        RETURN 0
+    }}
+    DISPLAY(down({depth}))
+    "#
+    );
+
+    for engine in [Engine::Tree, Engine::Bytecode] {
+        let output = Buffer::new();
+        let mut interpreter = ApLang::new_from_stdin(source.as_str())
+            .lex()
+            .unwrap()
+            .parse()
+            .unwrap()
+            .with_settings(Settings {
+                engine,
+                ..Settings::default()
+            })
+            .interpreter()
+            .with_output(output.clone());
+
+        match interpreter.interpret() {
+            Ok(()) => assert_eq!(output.contents(), format!("{depth}\n")),
+            // with `stacker` the stack grows, so the limit is always reached
+            Err(error) if cfg!(feature = "native") => panic!("{}", error.message),
+            Err(error) => assert_eq!(error.message, "Too Much Recursion"),
+        }
+    }
+}
+
+#[test]
+fn test_step_limit() {
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine, "--max-steps", "1000"])
+            .arg(
+                r#"-e
+    x <- 0
+    REPEAT UNTIL (FALSE) {
+        x <- x + 1
+    }
+    "#,
+            )
+            .assert()
+            .failure();
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Step Limit Reached"));
+        assert!(stderr.contains("This REPEAT loop was running"));
+    }
+}
+
+#[test]
+fn test_time_limit() {
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine, "--time-limit", "0.2"])
+            .arg(
+                r#"-e
+    PROCEDURE spin() {
+        REPEAT UNTIL (FALSE) {}
+    }
+    spin()
+    "#,
+            )
+            .assert()
+            .failure();
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Time Limit Reached"));
+        assert!(stderr.contains("`spin` was called from the top level"));
+    }
+}
+
+#[test]
+fn test_sleep_stops_at_the_time_limit() {
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine, "--time-limit", "0.2"])
+            .arg(
+                r#"-e
+    IMPORT MOD "TIME"
+    DISPLAY("before")
+    SLEEP(1000000000)
+    DISPLAY("after")
+    "#,
+            )
+            .timeout(std::time::Duration::from_secs(10))
+            .assert()
+            .failure()
+            .stdout("before\n");
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Time Limit Reached"));
+        assert!(stderr.contains("This call to `SLEEP` was running"));
+    }
+}
+
+#[test]
+fn test_time_limit_past_the_clock() {
+    use aplang_lib::interpreter::{Budget, Settings};
+    use aplang_lib::output::Buffer;
+    use aplang_lib::ApLang;
+    use std::time::Duration;
+
+    let output = Buffer::new();
+    ApLang::new_from_stdin("DISPLAY(1)")
+        .with_settings(Settings {
+            budget: Budget {
+                time_limit: Some(Duration::MAX),
+                ..Budget::default()
+            },
+            ..Settings::default()
+        })
+        .lex()
+        .unwrap()
+        .parse()
+        .unwrap()
+        .interpreter()
+        .with_output(output.clone())
+        .interpret()
+        .unwrap();
+    assert_eq!(output.contents(), "1\n");
+}
+
+#[test]
+fn test_time_limit_has_to_be_seconds() {
+    for seconds in ["-1", "0", "1e20", "NaN"] {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .arg(format!("--time-limit={seconds}"))
+            .args(["-e", "DISPLAY(1)"])
+            .assert()
+            .failure()
+            .stdout("");
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("a number of seconds more than 0"), "{stderr}");
+    }
+}
+
+#[test]
+fn test_max_list_len() {
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine, "--max-list-len", "100"])
+            .arg(
+                r#"-e
+    items <- []
+    REPEAT 200 TIMES {
+        APPEND(items, 1)
+    }
+    "#,
+            )
+            .assert()
+            .failure();
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("List Too Long"));
+
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine, "--max-list-len", "100"])
+            .arg(
+                r#"-e
+    items <- [1]
+    REPEAT 10 TIMES {
+        items <- items + items
+    }
+    "#,
+            )
+            .assert()
+            .failure();
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("List Too Long"));
+    }
+}
+
+#[test]
+fn test_cancel() {
+    use aplang_lib::interpreter::{CancelToken, Engine, Settings};
+    use aplang_lib::ApLang;
+    use std::time::Duration;
+
+    for engine in [Engine::Tree, Engine::Bytecode] {
+        let cancel = CancelToken::new();
+        let handle = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle.cancel();
+        });
+
+        let report = ApLang::new_from_stdin("REPEAT UNTIL (FALSE) {}")
+            .with_settings(Settings {
+                engine,
+                cancel: Some(cancel),
+                ..Settings::default()
+            })
+            .lex()
+            .unwrap()
+            .parse()
+            .unwrap()
+            .execute()
+            .err()
+            .unwrap();
+
+        assert!(report.to_string().contains("Execution Cancelled"));
+    }
+}
+
+#[test]
+fn test_output_buffer() {
+    use aplang_lib::interpreter::{Engine, Settings};
+    use aplang_lib::output::{Buffer, Tee};
+    use aplang_lib::ApLang;
+
+    for engine in [Engine::Tree, Engine::Bytecode] {
+        let first = Buffer::new();
+        let second = Buffer::new();
+
+        let mut interpreter = ApLang::new_from_stdin(
+            r#"
+    IMPORT MOD "IO"
+    DISPLAY("hello")
+    DISPLAY_NOLN(1 + 2)
+    DISPLAYF("{} and {}", [4, 5])
+    "#,
+        )
+        .with_settings(Settings {
+            engine,
+            ..Settings::default()
+        })
+        .lex()
+        .unwrap()
+        .parse()
+        .unwrap()
+        .interpreter()
+        .with_output(Tee(first.clone(), second.clone()));
+
+        interpreter.interpret().unwrap();
+
+        assert_eq!(first.contents(), "hello\n34 and 5\n");
+        assert_eq!(second.take(), first.contents());
+        assert_eq!(second.contents(), "");
+    }
+}
+
+#[test]
+fn test_scripted_input() {
+    use aplang_lib::input::Lines;
+    use aplang_lib::interpreter::{Engine, Settings};
+    use aplang_lib::output::Buffer;
+    use aplang_lib::ApLang;
+
+    for engine in [Engine::Tree, Engine::Bytecode] {
+        let output = Buffer::new();
+
+        let mut interpreter = ApLang::new_from_stdin(
+            r#"
+    IMPORT MOD "IO"
+    name <- INPUT_PROMPT("name? ")
+    DISPLAY("hi " + name)
+    DISPLAY(INPUT())
+    INPUT()
+    "#,
+        )
+        .with_settings(Settings {
+            engine,
+            ..Settings::default()
+        })
+        .lex()
+        .unwrap()
+        .parse()
+        .unwrap()
+        .interpreter()
+        .with_output(output.clone())
+        .with_input(Lines::new(["ada", "second"]));
+
+        // the third INPUT has nothing left to read
+        let error = interpreter.interpret().err().unwrap();
+        assert_eq!(error.message, "End of Input");
+        assert_eq!(output.contents(), "name? hi ada\nsecond\n");
+    }
+}
+
+#[test]
+fn test_end_of_stdin() {
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let assert = cmd
+            .args(["--engine", engine])
+            .arg("-e DISPLAY(INPUT())\nDISPLAY(INPUT())")
+            .write_stdin("only line\n")
+            .assert()
+            .failure()
+            .stdout("only line\n");
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("End of Input"));
+        assert!(!stderr.contains("panicked"));
+    }
+}
+
+#[test]
+fn test_seeded_random() {
+    let src = r#"-e
+    rolls <- []
+    REPEAT 20 TIMES {
+        APPEND(rolls, RANDOM(1, 1000))
+    }
+    DISPLAY(rolls)
+    "#;
+
+    let mut outputs = vec![];
+    for engine in ENGINES {
+        for _ in 0..2 {
+            let mut cmd = Command::cargo_bin("aplang").unwrap();
+            let assert = cmd.args(["--engine", engine, "--seed", "7"]).arg(src).assert().success();
+            outputs.push(String::from_utf8(assert.get_output().stdout.clone()).unwrap());
+        }
+    }
+
+    assert!(outputs.iter().all(|output| *output == outputs[0]));
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd.args(["--seed", "8"]).arg(src).assert().success();
+    assert_ne!(String::from_utf8(assert.get_output().stdout.clone()).unwrap(), outputs[0]);
+}
+
+#[test]
+fn test_virtual_clock() {
+    for engine in ENGINES {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        cmd.args(["--engine", engine, "--virtual-clock"])
+            .arg(
+                r#"-e
+    IMPORT MOD "TIME"
+    DISPLAY(TIME())
+    SLEEP(60000)
+    DISPLAY(TIME())
+    SLEEP(1500)
+    DISPLAY(TIME())
+    "#,
+            )
+            .timeout(std::time::Duration::from_secs(10))
+            .assert()
+            .success()
+            .stdout("0\n60000\n61500\n");
+    }
+}
+
+#[test]
+fn test_resolver_reports_before_running() {
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd
+        .arg(
+            r#"-e
+    DISPLAY("started")
+    PROCEDURE add(a, b) {
+        RETURN a + b
+    }
+    PROCEDURE add(a, b, c) {
+        RETURN a + b + c
+    }
+    PROCEDURE greet(name) {
+        DISPLAY("hi " + name)
+    }
+    greet("a", "b")
+    DISPLAY(totl)
+    SIN(1)
+    "#,
+        )
+        .assert()
+        .failure()
+        .stdout("");
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("duplicate_procedure"));
+    assert!(stderr.contains("incorrect_arg_count"));
+    assert!(stderr.contains("There should be 1 arg; Found 2"));
+    assert!(stderr.contains("undefined_variable"));
+    // `totl` is at the top level, so the note about PROCEDUREs is left out
+    assert!(!stderr.contains("can only see variables"));
+    assert!(stderr.contains("unknown_procedure"));
+    assert!(stderr.contains("IMPORT MOD \"MATH\""));
+}
+
+#[test]
+fn test_resolver_misplaced_statements() {
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd
+        .arg("--check")
+        .arg(
+            r#"-e
+    REPEAT 3 TIMES {
+        PROCEDURE stop() {
+            BREAK
+        }
+    }
+    CONTINUE
+    RETURN
+    "#,
+        )
+        .assert()
+        .failure();
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("BREAK can only be used inside of a loop"));
+    assert!(stderr.contains("CONTINUE can only be used inside of a loop"));
+    assert!(stderr.contains("RETURN can only be used inside of a PROCEDURE"));
+}
+
+#[test]
+fn test_resolver_accepts_valid_programs() {
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.arg("--check")
+        .arg(
+            r#"-e
+    IMPORT MOD "MATH"
+    IMPORT "TO_UPPER" FROM MOD "STRING"
+    PROCEDURE apply(f, x) {
+        RETURN f(x)
+    }
+    PROCEDURE counter() {
+        count <- 0
+        RETURN PROCEDURE() {
+            count <- count + 1
+            RETURN count
+        }
+    }
+    IF (TRUE) {
+        late <- twice(1)
+    }
+    DISPLAY(late)
+    double <- PROCEDURE(n) { RETURN n * 2 }
+    DISPLAY(apply(double, SIN(0)))
+    DISPLAY(apply(twice, 1))
+    next <- counter()
+    DISPLAY(TO_UPPER("a"))
+    FOR EACH item IN [1, 2] {
+        IF (item == 2) {
+            BREAK
+        }
+        DISPLAY(next())
+    }
+    PROCEDURE twice(n) {
+        RETURN 2 * n
+    }
+    "#,
+        )
+        .assert()
+        .success();
+}
+
+const LINTED: &str = r#"-e
+    unused <- 1
+    x <- 1
+    x <- 2
+    DISPLAY(x)
+    PROCEDURE LENGTH(list) {
+        RETURN 0
+        DISPLAY("never")
+    }
+    done <- FALSE
+    REPEAT UNTIL (done) {
+        DISPLAY("again")
+    }
+    "#;
+
+#[test]
+fn test_lint() {
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd.arg("lint").arg(LINTED).assert().success().stdout("");
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("unused_variable"));
+    assert!(stderr.contains("the variable `unused` is never used"));
+    assert!(stderr.contains("unused_assignment"));
+    assert!(stderr.contains("unreachable_code"));
+    assert!(stderr.contains("shadowed_std"));
+    assert!(stderr.contains("unchanging_condition"));
+    // only the first assignment to `x` is a problem
+    assert_eq!(stderr.matches("never used").count(), 2);
+}
+
+#[test]
+fn test_lint_levels() {
+    // allowed rules are not reported
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd
+        .args(["lint", "--allow", "unused_variable", "--allow", "shadowed_std"])
+        .arg(LINTED)
+        .assert()
+        .success();
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(!stderr.contains("unused_variable"));
+    assert!(!stderr.contains("shadowed_std"));
+    assert!(stderr.contains("unreachable_code"));
+
+    // denied rules fail
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.args(["lint", "--deny", "unreachable_code"])
+        .arg(LINTED)
+        .assert()
+        .failure();
+
+    // unknown rules are rejected
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.args(["lint", "--allow", "not_a_rule"])
+        .arg(LINTED)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_check_warns() {
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd.arg("--check").arg(LINTED).assert().success().stdout("");
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("unchanging_condition"));
+
+    // running the program does not lint it
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd
+        .args(["-e", "unused <- 1\nDISPLAY(1)"])
+        .assert()
+        .success()
+        .stdout("1\n");
+    assert!(assert.get_output().stderr.is_empty());
+}
+
+const UNFORMATTED: &str = r#"// header
+
+x<-1 // trailing
+procedure add(a,b){return a+b}
+IF(x==1){DISPLAY("one")}else IF (x == 2) {DISPLAY("two")} else {
+  // inside
+  DISPLAY(  "other" )
+}
+
+
+REPEAT 2 TIMES DISPLAY(NOT TRUE)
+// end"#;
+
+const FORMATTED: &str = r#"// header
+
+x <- 1 // trailing
+PROCEDURE add(a, b) {
+    RETURN a + b
+}
+IF (x == 1) {
+    DISPLAY("one")
+} ELSE IF (x == 2) {
+    DISPLAY("two")
+} ELSE {
+    // inside
+    DISPLAY("other")
+}
+
+REPEAT 2 TIMES {
+    DISPLAY(NOT TRUE)
+}
+// end
+"#;
+
+#[test]
+fn test_fmt() {
+    let dir = TempDir::new("fmt");
+    let file = dir.join("messy.ap");
+    std::fs::write(&file, UNFORMATTED).unwrap();
+
+    // --check reports the file and leaves it alone
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd.args(["fmt", "--check"]).arg(dir.as_os_str()).assert().failure();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("would reformat"));
+    assert!(stdout.contains("messy.ap"));
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), UNFORMATTED);
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.arg("fmt").arg(dir.as_os_str()).assert().success();
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), FORMATTED);
+
+    // formatting again changes nothing
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.args(["fmt", "--check"]).arg(&file).assert().success().stdout("");
+}
+
+#[test]
+fn test_fmt_keyword_case() {
+    let dir = TempDir::new("fmt_case");
+    let file = dir.join("case.ap");
+    std::fs::write(&file, "IF (TRUE AND x MOD 2 == 0) { DISPLAY(NULL) }").unwrap();
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.args(["fmt", "--keyword-case", "lower"])
+        .arg(&file)
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "if (true and x mod 2 == 0) {\n    DISPLAY(null)\n}\n"
+    );
+}
+
+/// frames a message the way a language server client sends it
+fn lsp_message(json: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{json}", json.len())
+}
+
+#[test]
+fn test_lsp() {
+    let dir = TempDir::new("lsp");
+    std::fs::write(
+        dir.join("shapes.ap"),
+        "EXPORT PROCEDURE square(n) {\n    RETURN n * n\n}\n",
+    )
+    .unwrap();
+    let uri = format!("file://{}", dir.join("main.ap").display());
+    let text = r#"IMPORT MOD \"shapes.ap\"\nIMPORT MOD \"MATH\"\nDISPLAY(FLOOR(square(2)))\nDISPLAY(missing)\n"#;
+    let at = |line: u32, character: u32| {
+        format!(r#""textDocument":{{"uri":"{uri}"}},"position":{{"line":{line},"character":{character}}}"#)
+    };
+
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#.to_string(),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{uri}","languageId":"aplang","version":1,"text":"{text}"}}}}}}"#
+        ),
+        // FLOOR
+        format!(r#"{{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{{{}}}}}"#, at(2, 10)),
+        // square, from the other file
+        format!(r#"{{"jsonrpc":"2.0","id":3,"method":"textDocument/definition","params":{{{}}}}}"#, at(2, 16)),
+        format!(r#"{{"jsonrpc":"2.0","id":4,"method":"textDocument/completion","params":{{{}}}}}"#, at(3, 0)),
+        r#"{"jsonrpc":"2.0","id":5,"method":"shutdown"}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string(),
+    ];
+    let input: String = messages.iter().map(|message| lsp_message(message)).collect();
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd.arg("lsp").write_stdin(input).assert().success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+
+    // diagnostics are sent when the file opens
+    assert!(stdout.contains("textDocument/publishDiagnostics"));
+    assert!(stdout.contains("the variable `missing` is never created"));
+    // hover shows the signature and what it does
+    assert!(stdout.contains("FLOOR(value)"));
+    assert!(stdout.contains("the value rounded down"));
+    // the definition is in the imported file
+    let definition = format!(
+        r#""id":3,"result":{{"range":{{"end":{{"character":23,"line":0}},"start":{{"character":17,"line":0}}}},"uri":"file://{}""#,
+        dir.join("shapes.ap").display()
+    );
+    assert!(stdout.contains(&definition), "{stdout}");
+    // completion knows the imported PROCEDURE
+    assert!(stdout.contains(r#""label":"square""#));
+}
+
+#[test]
+fn test_dap() {
+    let dir = TempDir::new("dap");
+    let shapes = dir.join("shapes.ap");
+    let main = dir.join("main.ap");
+    std::fs::write(&shapes, "EXPORT PROCEDURE square(n) {\n    RETURN n * n\n}\n").unwrap();
+    std::fs::write(
+        &main,
+        "IMPORT MOD \"shapes.ap\"\nsides <- [4, \"four\"]\nDISPLAY(square(sides[1]))\nDISPLAY(INPUT())\n",
+    )
+    .unwrap();
+
+    let request = |seq: u32, command: &str, arguments: String| {
+        lsp_message(&format!(
+            r#"{{"seq":{seq},"type":"request","command":"{command}","arguments":{{{arguments}}}}}"#
+        ))
+    };
+    let input = [
+        request(1, "initialize", r#""adapterID":"aplang""#.to_string()),
+        request(2, "launch", format!(r#""program":"{}","input":["done"]"#, main.display())),
+        request(3, "setBreakpoints", format!(r#""source":{{"path":"{}"}},"breakpoints":[{{"line":2}}]"#, shapes.display())),
+        request(4, "configurationDone", String::new()),
+        request(5, "stackTrace", r#""threadId":1"#.to_string()),
+        // the scopes of `square` are 1 and 2, the top level gets 3 and 4
+        request(6, "scopes", r#""frameId":0"#.to_string()),
+        request(7, "variables", r#""variablesReference":1"#.to_string()),
+        request(8, "scopes", r#""frameId":1"#.to_string()),
+        request(9, "variables", r#""variablesReference":3"#.to_string()),
+        // the list `sides` was given 5
+        request(10, "variables", r#""variablesReference":5"#.to_string()),
+        request(11, "stepOut", r#""threadId":1"#.to_string()),
+        request(12, "continue", r#""threadId":1"#.to_string()),
+        request(13, "disconnect", String::new()),
+    ]
+    .concat();
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd.arg("dap").write_stdin(input).assert().success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+
+    // it stops inside of the imported PROCEDURE, called from the top level
+    assert!(stdout.contains(r#""reason":"breakpoint""#), "{stdout}");
+    assert!(stdout.contains(r#""line":2,"name":"square""#), "{stdout}");
+    assert!(stdout.contains(r#""line":3,"name":"top level""#), "{stdout}");
+    // the argument and the list it came from
+    assert!(stdout.contains(r#""name":"n","type":"number","value":"4""#), "{stdout}");
+    assert!(stdout.contains(r#""name":"sides","type":"list","value":"[4, four]","variablesReference":5"#), "{stdout}");
+    assert!(stdout.contains(r#""name":"2","type":"string","value":"\"four\"""#), "{stdout}");
+    // stepping out stops back at the top level
+    assert!(stdout.contains(r#""reason":"step""#), "{stdout}");
+    // the output goes to the debug console, and INPUT reads the launch input
+    assert!(stdout.contains(r#""output":"16\n""#), "{stdout}");
+    assert!(stdout.contains(r#""output":"done\n""#), "{stdout}");
+    assert!(stdout.contains(r#""exitCode":0"#), "{stdout}");
+}
+
+#[test]
+fn test_repl() {
+    // the history is saved in the home directory
+    let home = TempDir::new("repl");
+    std::fs::write(
+        home.join("shapes.ap"),
+        "PROCEDURE square(n) {\n    RETURN n * n\n}\nPROCEDURE half(n) {\n    RETURN n / \"two\"\n}\n",
+    )
+    .unwrap();
+
+    let input = [
+        "x <- 3",
+        "x + 1",
+        // continues until the brace is closed
+        "PROCEDURE add(a, b) {",
+        "    RETURN a + b",
+        "}",
+        "add(x, 2)",
+        "DISPLAY(\"hi\")",
+        "\"quoted\"",
+        // an error inside of a call does not leave the repl inside of it
+        "PROCEDURE broken(n) {",
+        "    RETURN n / \"zero\"",
+        "}",
+        "broken(1)",
+        "y <- [x, 4]",
+        ":load shapes.ap",
+        "square(y[2])",
+        // the error is in the file, not in what was typed
+        "half(1)",
+        ":vars",
+        ":procs",
+        ":reset",
+        "x",
+    ]
+    .join("\n");
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let assert = cmd
+        .arg("repl")
+        .current_dir(&home)
+        .env("HOME", home.as_os_str())
+        .write_stdin(input)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+
+    let expected = "4\n5\nhi\n\"quoted\"\n16\nx = 3\ny = [3, 4]\nPROCEDURE add(a, b)\nPROCEDURE broken(n)\nPROCEDURE half(n)\nPROCEDURE square(n)\n";
+    assert!(stdout.ends_with(expected), "{stdout}");
+    // `n` from inside of `broken` is not a variable at the top level
+    assert!(!stdout.contains("n = 1"), "{stdout}");
+    assert!(stderr.contains("Incomparable Values"), "{stderr}");
+    assert!(stderr.contains("[shapes.ap:5:"), "{stderr}");
+    // everything was forgotten
+    assert!(stderr.contains("the variable `x` is never created"), "{stderr}");
+    assert!(home.join(".aplang_history").exists());
+}
+
+#[test]
+fn test_trace() {
+    let dir = TempDir::new("trace");
+    let trace = dir.join("trace.txt");
+
+    // starts with a blank line, so each line number is one more than it looks
+    let code = r#"
+    PROCEDURE double(n) {
+        RETURN n * 2
+    }
+    nums <- [1, 2]
+    REPEAT 2 TIMES {
+        nums[1] <- double(nums[1])
+    }
+    FOR EACH item IN nums {
+        total <- item
+    }
+    DISPLAY(total)
+    "#;
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.arg("--trace")
+        .arg("--trace-file")
+        .arg(&trace)
+        .arg("-e")
+        .arg(code)
+        .assert()
+        .success()
+        .stdout("2\n");
+
+    let expected = "\
+line 5: nums <- [1, 2]
+    nums = [1, 2]
+line 6: REPEAT 2 TIMES {
+    iteration 1 of the loop on line 6
+line 7: nums[1] <- double(nums[1])
+    call double(n = 1)
+  line 3: RETURN n * 2
+    double returned 2
+    nums[1]: 1 -> 2
+    iteration 2 of the loop on line 6
+line 7: nums[1] <- double(nums[1])
+    call double(n = 2)
+  line 3: RETURN n * 2
+    double returned 4
+    nums[1]: 2 -> 4
+line 9: FOR EACH item IN nums {
+    iteration 1 of the loop on line 9
+    item = 4
+line 10: total <- item
+    total = 4
+    iteration 2 of the loop on line 9
+    item: 4 -> 2
+line 10: total <- item
+    total: 4 -> 2
+line 12: DISPLAY(total)
+";
+    assert_eq!(std::fs::read_to_string(&trace).unwrap(), expected);
+
+    // without a file the trace goes to stderr
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.arg("--trace")
+        .arg("-e")
+        .arg("x <- 1\nx <- x + 1")
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("line 1: x <- 1\n    x = 1\nline 2: x <- x + 1\n    x: 1 -> 2\n");
+}
+
+#[test]
+fn test_trace_json() {
+    let dir = TempDir::new("trace_json");
+    let json = dir.join("trace.json");
+    let html = dir.join("trace.html");
+
+    let code = "\
+PROCEDURE add_to_list(lst) {
+    APPEND(lst, 4)
+}
+my_list <- [1, 2, 3]
+add_to_list(my_list)
+DISPLAY(my_list)";
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.arg("--trace-json")
+        .arg(&json)
+        .arg("--trace-html")
+        .arg(&html)
+        .arg("-e")
+        .arg(code)
+        .assert()
+        .success()
+        .stdout("[1, 2, 3, 4]\n");
+
+    let trace = std::fs::read_to_string(&json).unwrap();
+    let steps: Vec<&str> = trace
+        .lines()
+        .filter(|line| line.trim_start().starts_with("{\"event\""))
+        .collect();
+    assert_eq!(steps.len(), 5, "{trace}");
+
+    // inside of the call, `lst` and `my_list` are the same list
+    assert_eq!(
+        steps[2].trim(),
+        r#"{"event": "step", "file": "", "line": 2, "output": 0, "frames": [{"procedure": null, "file": "", "line": 5, "variables": [{"name": "my_list", "value": {"list": 1}}]}, {"procedure": "add_to_list", "file": "", "line": 2, "variables": [{"name": "lst", "value": {"list": 1}}]}], "heap": {"1": [1, 2, 3]}},"#
+    );
+    // the last step is where the program ended, with everything it displayed
+    assert!(steps[4].contains(r#""event": "finished""#), "{trace}");
+    assert!(steps[4].contains(r#"{"1": [1, 2, 3, 4]}"#), "{trace}");
+    assert!(trace.contains(r#""output": "[1, 2, 3, 4]\n""#), "{trace}");
+
+    // the page has the recording inside of it
+    let page = std::fs::read_to_string(&html).unwrap();
+    assert!(page.contains(r#"{"procedure": "add_to_list""#));
+    assert!(!page.contains("{{recording}}"));
+
+    // an error still writes the steps up to it
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.arg("--trace-json")
+        .arg(&json)
+        .arg("-e")
+        .arg("x <- [1]\nDISPLAY(x[3])")
+        .assert()
+        .failure();
+    let trace = std::fs::read_to_string(&json).unwrap();
+    assert!(trace.contains(r#""event": "error""#), "{trace}");
+    assert!(trace.contains(r#""error": "Invalid List Index""#), "{trace}");
+}
+
+#[test]
+fn test_profile() {
+    let dir = TempDir::new("profile");
+    let folded = dir.join("profile.folded");
+
+    let code = "\
+PROCEDURE double(n) {
+    RETURN n * 2
+}
+PROCEDURE twice(n) {
+    RETURN double(double(n))
+}
+DISPLAY(twice(1) + twice(2))";
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let output = cmd
+        .arg("--profile")
+        .arg("--profile-folded")
+        .arg(&folded)
+        .arg("-e")
+        .arg(code)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "12\n");
+
+    // the times change every run, so only the names and calls are checked
+    let table = String::from_utf8_lossy(&output.stderr);
+    let calls: Vec<(&str, &str)> = table
+        .lines()
+        .map(|line| {
+            let columns: Vec<&str> = line.split("  ").filter(|column| !column.is_empty()).collect();
+            (columns[0], columns[1].trim())
+        })
+        .collect();
+    assert_eq!(calls[0], ("PROCEDURE", "CALLS"), "{table}");
+    let mut calls = calls[1..].to_vec();
+    calls.sort();
+    assert_eq!(
+        calls,
+        [
+            ("DISPLAY", "1"),
+            ("double", "4"),
+            ("top level", "1"),
+            ("twice", "2"),
+        ],
+        "{table}"
+    );
+
+    let folded = std::fs::read_to_string(&folded).unwrap();
+    let mut stacks: Vec<&str> = folded
+        .lines()
+        .map(|line| line.rsplit_once(' ').unwrap().0)
+        .collect();
+    stacks.sort();
+    assert_eq!(
+        stacks,
+        [
+            "top level",
+            "top level;DISPLAY",
+            "top level;twice",
+            "top level;twice;double",
+        ]
+    );
+
+    // the bytecode engine does not go through the calls that are timed
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.arg("--profile")
+        .arg("--engine")
+        .arg("bytecode")
+        .arg("-e")
+        .arg("DISPLAY(1)")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_coverage() {
+    let dir = TempDir::new("coverage");
+
+    std::fs::write(
+        dir.join("signs.ap"),
+        "\
+EXPORT PROCEDURE sign(n) {
+    IF (n < 0) {
+        RETURN \"negative\"
+    } ELSE {
+        RETURN \"positive\"
+    }
+}
+
+EXPORT PROCEDURE unused() {
+    RETURN 1
+}
+",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("main.ap"),
+        "\
+IMPORT MOD \"signs.ap\"
+
+REPEAT 2 TIMES {
+    x <- 5
+}
+IF (x > 10) {
+    DISPLAY(\"big\")
+}
+DISPLAY(sign(x))
+",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.current_dir(&dir)
+        .arg("main.ap")
+        .arg("--coverage")
+        .arg("coverage.lcov")
+        .assert()
+        .success()
+        .stdout("positive\n");
+
+    // each file has its own record, and code that never ran is counted as 0
+    let expected = "\
+TN:
+SF:main.ap
+BRDA:6,0,0,0
+BRDA:6,0,1,1
+BRF:2
+BRH:1
+DA:1,1
+DA:3,1
+DA:4,2
+DA:6,1
+DA:7,0
+DA:9,1
+LF:6
+LH:5
+end_of_record
+TN:
+SF:signs.ap
+BRDA:2,0,0,0
+BRDA:2,0,1,1
+BRF:2
+BRH:1
+DA:2,1
+DA:3,0
+DA:5,1
+DA:10,0
+LF:4
+LH:2
+end_of_record
+";
+    assert_eq!(
+        std::fs::read_to_string(dir.join("coverage.lcov")).unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn test_test_command() {
+    let dir = TempDir::new("test_command");
+
+    std::fs::write(
+        dir.join("counter.ap"),
+        r#"IMPORT MOD "TEST"
+
+count <- 0
+
+PROCEDURE bump() {
+    count <- count + 1
+    RETURN count
+}
+
+PROCEDURE TEST_first_bump() {
+    ASSERT_EQUAL(bump(), 1)
+}
+
+PROCEDURE TEST_fresh_count() {
+    // each test starts over, so the other test did not change count
+    ASSERT(bump() == 1, "count was changed by another test")
+}
+
+PROCEDURE TEST_lists() {
+    DISPLAY("comparing")
+    ASSERT_EQUAL([1, [2, 3]], [1, [2, 4], 5])
+}
+"#,
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let output = cmd.current_dir(&dir).arg("test").output().unwrap();
+    assert!(!output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results: Vec<&str> = stdout.lines().filter(|line| line.starts_with("test ")).collect();
+    assert_eq!(
+        results,
+        [
+            "test ./counter.ap TEST_first_bump ... ok",
+            "test ./counter.ap TEST_fresh_count ... ok",
+            "test ./counter.ap TEST_lists ... FAILED",
+            "test result: FAILED. 2 passed; 1 failed",
+        ],
+        "{stdout}"
+    );
+    // the list is compared item by item
+    assert!(stdout.contains("[2][2]: expected 4, got 3"), "{stdout}");
+    assert!(stdout.contains("[3]: expected 5, got nothing"), "{stdout}");
+    assert!(stdout.contains("output:\ncomparing"), "{stdout}");
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let output = cmd
+        .current_dir(&dir)
+        .arg("test")
+        .arg("counter.ap")
+        .arg("--format")
+        .arg("json")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let json = String::from_utf8_lossy(&output.stdout);
+    assert!(json.contains(r#""failed": 1,"#), "{json}");
+    assert!(json.contains(r#""passed": 2,"#), "{json}");
+    assert!(json.contains(r#""message": "Assertion Failed","#), "{json}");
+    assert!(json.contains(r#""line": 21,"#), "{json}");
+    assert!(json.contains(r#""column": 5,"#), "{json}");
+
+    // every test passing is a success
+    std::fs::write(
+        dir.join("counter.ap"),
+        "IMPORT MOD \"TEST\"\nPROCEDURE TEST_math() {\n    ASSERT_NOT_EQUAL(1 + 1, 3)\n}\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    cmd.current_dir(&dir)
+        .arg("test")
+        .assert()
+        .success()
+        .stdout("running 1 test(s)\ntest ./counter.ap TEST_math ... ok\n\ntest result: ok. 1 passed; 0 failed\n");
+}
+
+#[test]
+fn test_grade_command() {
+    let dir = TempDir::new("grade_command");
+
+    std::fs::write(
+        dir.join("twice.ap"),
+        r#"n <- INPUT()
+IF (n == "forever") {
+    REPEAT UNTIL (FALSE) {}
+}
+DISPLAY("twice:")
+DISPLAY(n + n)
+"#,
+    )
+    .unwrap();
+
+    std::fs::write(
+        dir.join("cases.toml"),
+        r#"# every case stops after 1000 statements
+max_steps = 1000
+
+[[cases]]
+name = "small"
+input = ["2"]
+expected = """
+twice:
+22
+"""
+
+[[cases]]
+name = "spaces"
+input = "10"
+expected = "\ntwice:  \n1010\n\n"
+match = "trimmed"
+
+[[cases]]
+name = "pattern"
+input = ["-3"]
+expected = '^twice:\n(-3){2}\n$'
+match = "regex"
+
+[[cases]]
+name = "wrong"
+input = ["5"]
+expected = "twice:\n10\n"
+
+[[cases]]
+name = "never ends"
+input = ["forever"]
+expected = ""
+"#,
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let output = cmd
+        .current_dir(&dir)
+        .args(["grade", "twice.ap", "cases.toml"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results: Vec<&str> = stdout.lines().filter(|line| line.starts_with("case ")).collect();
+    assert_eq!(
+        results,
+        [
+            "case small ... ok",
+            "case spaces ... ok",
+            "case pattern ... ok",
+            "case wrong ... FAILED",
+            "case never ends ... ERROR",
+        ],
+        "{stdout}"
+    );
+    assert!(stdout.contains("  twice:\n- 10\n+ 55\n"), "{stdout}");
+    assert!(stdout.contains("Step Limit Reached"), "{stdout}");
+    assert!(stdout.contains("grade: 3/5 case(s) passed"), "{stdout}");
+
+    // json case files work the same, and so does the json report
+    std::fs::write(
+        dir.join("cases.json"),
+        r#"{"cases": [{"name": "no input", "expected": "twice:\n\n"}]}"#,
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let output = cmd
+        .current_dir(&dir)
+        .args(["grade", "twice.ap", "cases.json", "--format", "json"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let json = String::from_utf8_lossy(&output.stdout);
+    assert!(json.contains(r#""status": "error""#), "{json}");
+    assert!(json.contains(r#""message": "End of Input","#), "{json}");
+    assert!(json.contains(r#""passed": 0,"#), "{json}");
+
+    // a mistake in the case file is reported before anything runs
+    std::fs::write(dir.join("cases.json"), r#"{"cases": [{"expect": ""}]}"#).unwrap();
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let output = cmd
+        .current_dir(&dir)
+        .args(["grade", "twice.ap", "cases.json"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown field `expect`"), "{stderr}");
+}
+
+#[test]
+fn test_grade_dir() {
+    let dir = TempDir::new("grade_dir");
+    let class = dir.join("class");
+    std::fs::create_dir_all(&class).unwrap();
+
+    for student in ["ada", "grace", "linus", "margaret"] {
+        std::fs::write(
+            class.join(format!("{student}.ap")),
+            "n <- INPUT()\nDISPLAY(n + n)\nDISPLAY(RANDOM(1, 1000000))\n",
+        )
+        .unwrap();
+    }
+    std::fs::write(class.join("alan.ap"), "n <- INPUT()\nDISPLAY(n)\n").unwrap();
+    std::fs::write(class.join("dennis.ap"), "REPEAT UNTIL (FALSE) {}\n").unwrap();
+    std::fs::write(
+        dir.join("cases.toml"),
+        r#"max_steps = 1000
+match = "regex"
+
+[[cases]]
+name = "one"
+input = ["1"]
+expected = '^11\n\d+\n$'
+
+[[cases]]
+name = "word"
+input = ["ab"]
+expected = '^abab\n'
+"#,
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("aplang").unwrap();
+    let output = cmd
+        .current_dir(&dir)
+        .args(["grade-dir", "class", "cases.toml", "--jobs", "3"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let sep = std::path::MAIN_SEPARATOR;
+    assert_eq!(
+        stdout,
+        format!(
+            "grading 6 submission(s) with 2 case(s)\n\
+            submission class{sep}ada.ap ... ok (2/2)\n\
+            submission class{sep}alan.ap ... FAILED (0/2): one, word\n\
+            submission class{sep}dennis.ap ... FAILED (0/2): one, word\n\
+            submission class{sep}grace.ap ... ok (2/2)\n\
+            submission class{sep}linus.ap ... ok (2/2)\n\
+            submission class{sep}margaret.ap ... ok (2/2)\n\
+            \ngrade: 4/6 submission(s) passed every case\n"
+        )
+    );
+
+    // the results are the same however many threads grade them, RANDOM included
+    let graded = |jobs: &str| {
+        let mut cmd = Command::cargo_bin("aplang").unwrap();
+        let output = cmd
+            .current_dir(&dir)
+            .args(["grade-dir", "class", "cases.toml", "--format", "json", "-j", jobs])
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.contains(r#""seconds""#))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let alone = graded("1");
+    assert!(alone.contains(r#""message": "Step Limit Reached","#), "{alone}");
+    assert_eq!(alone, graded("4"));
+}